@@ -0,0 +1,375 @@
+use std::time::Duration;
+
+use super::font::{draw_text_styled, TextStyle};
+use super::framebuffer::{Framebuffer, Sprite};
+use super::graphics::{FineRGB, RGB};
+use super::sprites::StaticSprite;
+
+/*
+Animations that run on the clock instead of on sleeps. The old switch splash drew a frame, slept 100ms and drew the next for about 3 seconds,
+and in that time nobody read the I2S driver: the DMA buffers overflowed and the first analysis after a switch was garbage. Now an Animation
+is asked what it looks like at some time after its start, the main loop keeps reading audio and just shows the animation instead of the
+painter's frame until it's done.
+
+KeyframeAnimation is the one to build splashes with: a background colour and layers (a sprite from assets/, a Sprite graphic or text), each
+with keyframed tracks for position, colour and opacity. Between two keyframes the value moves along the Easing of the later keyframe.
+Positions are in pixels on the canvas like everywhere else (bottom left of the layer, y up) and get rounded, so a slow move steps pixel by
+pixel and a fast one skips pixels instead of slowing down.
+
+AnimationPlayer keeps the start time, so the animation can be drawn with the same clock as the FrameThrottle.
+*/
+
+pub trait Animation {
+    // Draws the animation as it looks elapsed after its start. Returns false once it has finished, it then draws nothing.
+    fn draw(&mut self, framebuffer: &mut Framebuffer, elapsed: Duration) -> bool;
+}
+
+// Runs an animation from the first time it gets drawn
+pub struct AnimationPlayer {
+    animation: Box<dyn Animation>,
+    start_time: Option<Duration>,
+}
+
+impl AnimationPlayer {
+    pub fn new(animation: impl Animation + 'static) -> Self {
+        AnimationPlayer {
+            animation: Box::new(animation),
+            start_time: None,
+        }
+    }
+
+    // Same as Animation::draw, with now on any clock that doesn't go backwards
+    pub fn draw(&mut self, framebuffer: &mut Framebuffer, now: Duration) -> bool {
+        let start_time = *self.start_time.get_or_insert(now);
+        self.animation.draw(framebuffer, now.saturating_sub(start_time))
+    }
+}
+
+// How the value moves from the previous keyframe to the next, progress in time 0..1 to progress in value 0..1
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    EaseIn, // starts slow
+    EaseOut, // ends slow
+    EaseInOut,
+    Hold, // keeps the previous value and jumps when the keyframe is reached
+}
+
+impl Easing {
+    pub fn apply(self, progress: f32) -> f32 {
+        let t = progress.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => if t < 0.5 { 2.0 * t * t } else { -1.0 + (4.0 - 2.0 * t) * t },
+            Easing::Hold => if t < 1.0 { 0.0 } else { 1.0 },
+        }
+    }
+}
+
+// Values a Track can move between
+pub trait Interpolate: Copy {
+    fn interpolate(self, to: Self, amount: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn interpolate(self, to: Self, amount: f32) -> Self {
+        self + (to - self) * amount
+    }
+}
+
+// positions
+impl Interpolate for (f32, f32) {
+    fn interpolate(self, to: Self, amount: f32) -> Self {
+        (self.0.interpolate(to.0, amount), self.1.interpolate(to.1, amount))
+    }
+}
+
+// colours, in fine precision so slow fades don't step
+impl Interpolate for FineRGB {
+    fn interpolate(self, to: Self, amount: f32) -> Self {
+        let mix = |from: u16, to: u16| (from as f32).interpolate(to as f32, amount).round().clamp(0.0, u16::MAX as f32) as u16;
+        FineRGB {
+            r: mix(self.r, to.r),
+            g: mix(self.g, to.g),
+            b: mix(self.b, to.b)
+        }
+    }
+}
+
+struct Keyframe<T> {
+    at: Duration,
+    value: T,
+    easing: Easing, // from the previous keyframe to this one
+}
+
+// A value over time. Before the first keyframe it has the first value, after the last it keeps the last value.
+pub struct Track<T> {
+    keyframes: Vec<Keyframe<T>>, // sorted by time
+}
+
+impl<T: Interpolate> Track<T> {
+    // value at the start
+    pub fn new(value: T) -> Self {
+        Track {
+            keyframes: vec![Keyframe { at: Duration::ZERO, value, easing: Easing::Hold }],
+        }
+    }
+
+    pub fn with_keyframe(mut self, at: Duration, value: T, easing: Easing) -> Self {
+        let position = self.keyframes.partition_point(|keyframe| keyframe.at <= at);
+        self.keyframes.insert(position, Keyframe { at, value, easing });
+        self
+    }
+
+    pub fn value_at(&self, elapsed: Duration) -> T {
+        let next = self.keyframes.partition_point(|keyframe| keyframe.at <= elapsed);
+        if next == 0 {
+            return self.keyframes[0].value
+        }
+        let previous = &self.keyframes[next - 1];
+        let Some(next) = self.keyframes.get(next) else {
+            return previous.value
+        };
+
+        let progress = (elapsed - previous.at).as_secs_f32() / (next.at - previous.at).as_secs_f32();
+        previous.value.interpolate(next.value, next.easing.apply(progress))
+    }
+}
+
+pub enum LayerContent {
+    Sprite(&'static StaticSprite),
+    Graphic(Sprite),
+    Text(String, TextStyle), // drawn in the colour of the layer
+}
+
+pub struct Layer {
+    content: LayerContent,
+    position: Track<(f32, f32)>,
+    color: Track<FineRGB>,
+    opacity: Track<f32>,
+}
+
+impl Layer {
+    // at 0, 0, fully opaque
+    pub fn new(content: LayerContent) -> Self {
+        Layer {
+            content,
+            position: Track::new((0.0, 0.0)),
+            color: Track::new(FineRGB::from_rgb(&RGB{r:0, g:142, b:122})),
+            opacity: Track::new(1.0),
+        }
+    }
+
+    pub fn sprite(sprite: &'static StaticSprite) -> Self {
+        Layer::new(LayerContent::Sprite(sprite))
+    }
+
+    pub fn graphic(graphic: Sprite) -> Self {
+        Layer::new(LayerContent::Graphic(graphic))
+    }
+
+    pub fn text(text: &str, style: TextStyle) -> Self {
+        Layer::new(LayerContent::Text(text.to_string(), style))
+    }
+
+    pub fn with_position(mut self, position: Track<(f32, f32)>) -> Self {
+        self.position = position;
+        self
+    }
+
+    // only used by text, sprites and graphics have their own colours
+    pub fn with_color(mut self, color: Track<FineRGB>) -> Self {
+        self.color = color;
+        self
+    }
+
+    // 0 is invisible, 1 fully covers what's under the layer
+    pub fn with_opacity(mut self, opacity: Track<f32>) -> Self {
+        self.opacity = opacity;
+        self
+    }
+
+    fn draw(&self, framebuffer: &mut Framebuffer, elapsed: Duration) {
+        let opacity = self.opacity.value_at(elapsed).clamp(0.0, 1.0);
+        if opacity <= 0.0 {
+            return
+        }
+        let (x, y) = self.position.value_at(elapsed);
+        let (x, y) = (x.round() as i32, y.round() as i32);
+
+        // half transparent: draw it fully and mix the result with what was there before
+        let under = if opacity < 1.0 { Some(framebuffer.pixels().to_vec()) } else { None };
+
+        match &self.content {
+            LayerContent::Sprite(sprite) => framebuffer.blit_sprite(sprite, x, y),
+            LayerContent::Graphic(graphic) => framebuffer.blit(graphic, x, y),
+            LayerContent::Text(text, style) => draw_text_styled(framebuffer, style, text, x, y, &self.color.value_at(elapsed).to_rgb()),
+        }
+
+        if let Some(under) = under {
+            for (pixel, under) in framebuffer.pixels_mut().iter_mut().zip(under) {
+                *pixel = under.interpolate(*pixel, opacity);
+            }
+        }
+    }
+}
+
+// A background and layers drawn on top of it in the order they were added
+pub struct KeyframeAnimation {
+    duration: Duration,
+    background: Option<Track<FineRGB>>,
+    layers: Vec<Layer>,
+}
+
+impl KeyframeAnimation {
+    // no background, so it draws over whatever is in the framebuffer
+    pub fn new(duration: Duration) -> Self {
+        KeyframeAnimation {
+            duration,
+            background: None,
+            layers: vec![],
+        }
+    }
+
+    pub fn with_background(mut self, background: Track<FineRGB>) -> Self {
+        self.background = Some(background);
+        self
+    }
+
+    pub fn with_layer(mut self, layer: Layer) -> Self {
+        self.layers.push(layer);
+        self
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+impl Animation for KeyframeAnimation {
+    fn draw(&mut self, framebuffer: &mut Framebuffer, elapsed: Duration) -> bool {
+        if elapsed >= self.duration {
+            return false
+        }
+
+        if let Some(background) = &self.background {
+            framebuffer.fill_fine(background.value_at(elapsed));
+        }
+        for layer in &self.layers {
+            layer.draw(framebuffer, elapsed);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::matrix_layout::MatrixLayout;
+    use super::super::pixel_format::{ColorOrder, PixelFormat};
+    use super::super::tile_map::TileMap;
+
+    const EASINGS: [Easing; 5] = [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut, Easing::Hold];
+
+    fn seconds(seconds: f32) -> Duration {
+        Duration::from_secs_f32(seconds)
+    }
+
+    #[test]
+    fn easings_start_at_0_and_end_at_1() {
+        for easing in EASINGS {
+            assert_eq!(easing.apply(0.0), 0.0, "{:?}", easing);
+            assert_eq!(easing.apply(1.0), 1.0, "{:?}", easing);
+            // progress out of 0..1 is clamped
+            assert_eq!(easing.apply(-0.5), 0.0, "{:?}", easing);
+            assert_eq!(easing.apply(1.5), 1.0, "{:?}", easing);
+
+            let steps: Vec<f32> = (0..=100).map(|step| easing.apply(step as f32 / 100.0)).collect();
+            assert!(steps.windows(2).all(|step| step[0] <= step[1]), "{:?} goes back", easing);
+        }
+    }
+
+    #[test]
+    fn easings_halfway() {
+        let halfway: Vec<f32> = EASINGS.iter().map(|easing| easing.apply(0.5)).collect();
+        assert_eq!(halfway, [0.5, 0.25, 0.75, 0.5, 0.0]);
+        assert_eq!(Easing::Hold.apply(0.999), 0.0);
+    }
+
+    #[test]
+    fn track_moves_between_keyframes() {
+        let track = Track::new(0.0)
+            .with_keyframe(seconds(1.0), 10.0, Easing::Linear)
+            .with_keyframe(seconds(3.0), 20.0, Easing::EaseIn)
+            .with_keyframe(seconds(4.0), 30.0, Easing::Hold);
+
+        assert_eq!(track.value_at(Duration::ZERO), 0.0);
+        assert_eq!(track.value_at(seconds(0.5)), 5.0);
+        assert_eq!(track.value_at(seconds(1.0)), 10.0);
+        assert_eq!(track.value_at(seconds(2.0)), 12.5); // eased in, a quarter of the way halfway through
+        assert_eq!(track.value_at(seconds(3.0)), 20.0);
+        assert_eq!(track.value_at(seconds(3.9)), 20.0);
+        assert_eq!(track.value_at(seconds(4.0)), 30.0);
+        assert_eq!(track.value_at(seconds(60.0)), 30.0); // keeps the last value
+    }
+
+    #[test]
+    fn keyframes_are_sorted_by_time() {
+        let in_order = Track::new(0.0).with_keyframe(seconds(1.0), 10.0, Easing::Linear).with_keyframe(seconds(2.0), 0.0, Easing::Linear);
+        let out_of_order = Track::new(0.0).with_keyframe(seconds(2.0), 0.0, Easing::Linear).with_keyframe(seconds(1.0), 10.0, Easing::Linear);
+        for elapsed in [0.0, 0.5, 1.0, 1.25, 2.0, 3.0] {
+            assert_eq!(in_order.value_at(seconds(elapsed)), out_of_order.value_at(seconds(elapsed)), "at {}", elapsed);
+        }
+    }
+
+    #[test]
+    fn two_keyframes_at_the_same_time_jump() {
+        // the one added later wins from that time on, the move towards it is to the first one
+        let track = Track::new(0.0).with_keyframe(seconds(1.0), 10.0, Easing::Linear).with_keyframe(seconds(1.0), 50.0, Easing::Linear);
+        assert_eq!(track.value_at(seconds(0.5)), 5.0);
+        assert_eq!(track.value_at(seconds(1.0)), 50.0);
+    }
+
+    #[test]
+    fn positions_and_colours_interpolate() {
+        let position = Track::new((0.0, 8.0)).with_keyframe(seconds(1.0), (4.0, 0.0), Easing::Linear);
+        assert_eq!(position.value_at(seconds(0.25)), (1.0, 6.0));
+
+        let color = Track::new(FineRGB { r: 0, g: 1000, b: 10 }).with_keyframe(seconds(1.0), FineRGB { r: 512, g: 0, b: 11 }, Easing::Linear);
+        assert_eq!(color.value_at(seconds(0.5)), FineRGB { r: 256, g: 500, b: 11 }); // rounded, 10.5 goes up
+    }
+
+    #[test]
+    fn player_starts_at_the_first_draw_and_stops_after_the_duration() {
+        let tile_map = TileMap::single(MatrixLayout::new(2, 2));
+        let mut framebuffer = Framebuffer::new(&tile_map, PixelFormat::new(ColorOrder::GRB));
+        let background = Track::new(FineRGB::default()).with_keyframe(seconds(1.0), FineRGB { r: 1000, g: 0, b: 0 }, Easing::Linear);
+        let mut player = AnimationPlayer::new(KeyframeAnimation::new(seconds(1.0)).with_background(background));
+
+        assert!(player.draw(&mut framebuffer, seconds(10.0)));
+        assert_eq!(framebuffer.pixel(0, 0), Some(FineRGB::default()));
+        assert!(player.draw(&mut framebuffer, seconds(10.5)));
+        assert_eq!(framebuffer.pixel(1, 1), Some(FineRGB { r: 500, g: 0, b: 0 }));
+
+        // done: nothing is drawn any more
+        framebuffer.fill_fine(FineRGB { r: 1, g: 2, b: 3 });
+        assert!(!player.draw(&mut framebuffer, seconds(11.0)));
+        assert_eq!(framebuffer.pixel(0, 0), Some(FineRGB { r: 1, g: 2, b: 3 }));
+    }
+
+    #[test]
+    fn half_transparent_layer_mixes_with_what_is_under_it() {
+        let tile_map = TileMap::single(MatrixLayout::new(2, 1));
+        let mut framebuffer = Framebuffer::new(&tile_map, PixelFormat::new(ColorOrder::GRB));
+        let dot = vec![vec![Some(RGB { r: 200, g: 0, b: 0 })]];
+        let mut animation = KeyframeAnimation::new(seconds(1.0))
+            .with_background(Track::new(FineRGB::from_rgb(&RGB { r: 0, g: 0, b: 100 })))
+            .with_layer(Layer::graphic(dot).with_position(Track::new((1.0, 0.0))).with_opacity(Track::new(0.25)));
+
+        assert!(animation.draw(&mut framebuffer, Duration::ZERO));
+        assert_eq!(framebuffer.pixel(0, 0), Some(FineRGB::from_rgb(&RGB { r: 0, g: 0, b: 100 })));
+        assert_eq!(framebuffer.pixel(1, 0), Some(FineRGB::from_rgb(&RGB { r: 50, g: 0, b: 75 })));
+    }
+}
@@ -13,7 +13,9 @@ pub struct AudioFrequalizer {
     samples_buffer: Vec<f32>,
     samples_max: usize,
     res_edges: AdaptiveResultEdges, // the edges for the bins the FFT result gets placed in, keep state so range can be adapted
-    pub eq_bins: Vec<f32>
+    pub eq_bins: Vec<f32>,
+    pub level_bins: Vec<f32>, // the same bins not normalized: the loudest FFT line in each, 1.0 is a full scale sine
+    pub new_frame: bool, // set when eq_bins has a new FFT frame, whoever needs every frame only once resets it
 }
 
 // Number of samples and sample rate determine the min and max frequencies that are measured by the FFT
@@ -21,6 +23,7 @@ impl AudioFrequalizer {
     pub fn new(num_bins: usize, sample_rate: u32) -> Self {
         let samples_max = 2048; // 2048 was about the max I could fill the FFT transform with before crashing the ESP and it gives a good range of frequencies, slightly more than can be heard by most humans.
         let eq_bins = Vec::with_capacity(num_bins as usize);
+        let level_bins = Vec::with_capacity(num_bins);
 
         // set up the result bins, need to init with edges
         let mut res_edges = AdaptiveResultEdges::new(samples_max, num_bins, sample_rate);
@@ -31,7 +34,9 @@ impl AudioFrequalizer {
             samples_buffer: vec![],
             samples_max,
             res_edges,
-            eq_bins
+            eq_bins,
+            level_bins,
+            new_frame: false
         }
     }
 
@@ -46,8 +51,10 @@ impl AudioFrequalizer {
             let fft_over_samples = raw_buffer.fft_transform(&mut self.fft_planner);
             let adapted_edges = fft_over_samples.adapt_edges(&mut self.res_edges);
             let fft_result_bins = adapted_edges.distribute_fft_to_fixed_bins(&mut self.res_edges);
+            self.level_bins = fft_result_bins.output_levels();
             let normalized_fft_result_bins = fft_result_bins.normalize_logarithmic_bins();
             self.eq_bins = normalized_fft_result_bins.output();
+            self.new_frame = true;
         }
     }
 }
@@ -66,7 +73,8 @@ struct AdaptedEdges {
 }
 
 struct FFTResultBins{
-    bins: Vec<f32>
+    bins: Vec<f32>,
+    levels: Vec<f32>
 }
 struct NormalizedFFTResultBins{
     bins: Vec<f32>
@@ -139,6 +147,7 @@ impl FFTOverSamples {
 impl AdaptedEdges{
     fn distribute_fft_to_fixed_bins(self, res_edges: &mut AdaptiveResultEdges) -> FFTResultBins {
         let mut result = vec![0.0; res_edges.edges.len() - 1];
+        let mut levels = vec![0.0_f32; res_edges.edges.len() - 1];
         let fft_len = self.ffted_samples.len();
         let freq_resolution = res_edges.sample_rate as f32/ fft_len as f32;
     
@@ -156,6 +165,7 @@ impl AdaptedEdges{
             };
     
             result[bin_index] += mag;
+            levels[bin_index] = levels[bin_index].max(mag * 2.0 / fft_len as f32); // a sine of amplitude 1 gives a line of N/2
         }
 
         for i in 0..result.len() {
@@ -166,12 +176,18 @@ impl AdaptedEdges{
         }
 
         FFTResultBins{
-            bins: result
+            bins: result,
+            levels
         }
     }
 }
 
 impl FFTResultBins {
+    // the levels before the normalization, without the first and last bin like output
+    fn output_levels(&self) -> Vec<f32> {
+        self.levels[1..(self.levels.len()-1)].to_vec()
+    }
+
     // find max magnitude and normalize the values with that max
     fn normalize_logarithmic_bins(mut self) -> NormalizedFFTResultBins {
         let max_magnitude = self.bins.iter().cloned().fold(0.0_f32, f32::max);
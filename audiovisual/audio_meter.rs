@@ -0,0 +1,304 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+use std::str::FromStr;
+use std::time::Duration;
+
+/*
+Level meter: absolute levels of the filtered samples in dBFS, 0 dBFS is the ADC full scale. The samples are after the gain, full_scale is
+the sample value the ADC full scale has after it, so the levels don't move with the gain knob and the clip latch is set where the ADC
+clips. The equalizer can't show this, it normalizes every frame to its loudest bin.
+
+Three measurements:
+ - Level: what a VU meter or a PPM shows. The detector and its attack and release times are the Ballistics, VU is the RMS with 300ms to
+   99% up and down, the PPM (IEC 60268-10 type II) follows the peaks within a few ms and falls back slowly at 24 dB in 2.8 seconds.
+ - True peak: the highest peak in between the samples as well (ITU-R BS.1770), the samples are 4x oversampled to find it. Held for a while,
+   then it falls. When it reaches 0 dBFS the clip latch is set, it stays set until reset_clip().
+ - Short-term loudness: BS.1770 K-weighted mean square over the last 3 seconds, in LUFS. Mono, so no channel weights.
+
+Attack and release are exponential: rising or falling by the same fraction of the distance to the input every sample, that falls in a
+straight line on a dB scale.
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Detector {
+    Rms,
+    Peak,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ballistics {
+    pub detector: Detector,
+    pub attack: Duration, // time constant going up
+    pub release: Duration, // time constant going down, 8.7 dB per time constant
+}
+
+impl Ballistics {
+    // 300ms to 99% is 4.6 time constants
+    pub const VU: Ballistics = Ballistics::new(Detector::Rms, Duration::from_millis(65), Duration::from_millis(65));
+    // a 10ms tone burst reads 4 dB low, falls 24 dB in 2.8 seconds
+    pub const PPM: Ballistics = Ballistics::new(Detector::Peak, Duration::from_millis(5), Duration::from_millis(1000));
+
+    pub const fn new(detector: Detector, attack: Duration, release: Duration) -> Self {
+        Ballistics {
+            detector,
+            attack,
+            release,
+        }
+    }
+}
+
+// vu or ppm, for a setting or the command line
+impl FromStr for Ballistics {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "vu" => Ok(Ballistics::VU),
+            "ppm" => Ok(Ballistics::PPM),
+            _ => Err(format!("unknown ballistics: {} (vu or ppm)", s))
+        }
+    }
+}
+
+// What the meter shows, in dBFS and LUFS
+#[derive(Clone, Copy, Debug)]
+pub struct MeterLevels {
+    pub level_db: f32,
+    pub true_peak_db: f32, // held
+    pub short_term_lufs: f32,
+    pub clipped: bool, // latched
+}
+
+impl MeterLevels {
+    // what silence reads, well below anything a meter shows
+    pub const FLOOR_DB: f32 = -120.0;
+}
+
+fn to_db(amplitude: f32) -> f32 {
+    (20.0 * amplitude.log10()).max(MeterLevels::FLOOR_DB)
+}
+
+// One second order IIR section, for the K-weighting
+#[derive(Clone, Copy, Default)]
+struct Biquad {
+    b: [f32; 3],
+    a: [f32; 2], // a1 a2, a0 is 1
+    state: [f32; 2],
+}
+
+impl Biquad {
+    // transposed direct form II
+    fn process(&mut self, sample: f32) -> f32 {
+        let output = self.b[0] * sample + self.state[0];
+        self.state[0] = self.b[1] * sample - self.a[0] * output + self.state[1];
+        self.state[1] = self.b[2] * sample - self.a[1] * output;
+        output
+    }
+}
+
+// The two stages of the BS.1770 K-weighting for any sample rate: a high shelf of +4 dB for the head, then a highpass at 38Hz
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+    let fs = sample_rate as f32;
+
+    let k = (PI * 1681.974 / fs).tan();
+    let q = 0.70717525;
+    let vh = 10f32.powf(3.9998438 / 20.0);
+    let vb = vh.powf(0.49966677);
+    let a0 = 1.0 + k / q + k * k;
+    let shelf = Biquad {
+        b: [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        state: [0.0; 2],
+    };
+
+    let k = (PI * 38.13547 / fs).tan();
+    let q = 0.50032704;
+    let a0 = 1.0 + k / q + k * k;
+    let highpass = Biquad {
+        b: [1.0, -2.0, 1.0],
+        a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        state: [0.0; 2],
+    };
+
+    [shelf, highpass]
+}
+
+pub struct Meter {
+    full_scale: f32,
+    ballistics: Ballistics,
+    attack_coefficient: f32, // per sample
+    release_coefficient: f32,
+    detector_state: f32, // mean square for Rms, amplitude for Peak
+
+    // true peak
+    oversampling_taps: Vec<[f32; 4]>, // per input sample of the history, the weight for each of the 4 output phases
+    oversampling_history: VecDeque<f32>,
+    peak_hold_samples: usize,
+    peak_fall_db_per_sample: f32,
+    held_peak_db: f32,
+    held_peak_for: usize, // samples
+
+    // short-term loudness
+    k_weighting: [Biquad; 2],
+    loudness_block_length: usize, // 100ms
+    loudness_block_sum: f32,
+    loudness_block_count: usize,
+    loudness_blocks: VecDeque<f32>, // mean square of the last 3 seconds in 100ms blocks
+
+    // output for comm with visual processor
+    pub levels: MeterLevels,
+}
+
+impl Meter {
+    // full_scale is the sample value of 0 dBFS, peak_hold how long the true peak stays up before it falls
+    pub fn new(ballistics: Ballistics, full_scale: f32, peak_hold: Duration, sample_rate: u32) -> Self {
+        let coefficient = |time_constant: Duration| 1.0 - (-1.0 / (time_constant.as_secs_f32() * sample_rate as f32).max(f32::EPSILON)).exp();
+
+        // windowed sinc for 4x oversampling, 8 input samples per output sample
+        let taps_per_phase = 8;
+        let oversampling_taps = (0..taps_per_phase).map(|tap| {
+            std::array::from_fn(|phase| {
+                let n = (tap * 4 + phase) as f32;
+                let center = (taps_per_phase * 4) as f32 / 2.0;
+                let x = (n - center) / 4.0;
+                let sinc = if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) };
+                let window = 0.5 + 0.5 * (PI * (n - center) / center).cos(); // hann
+                sinc * window
+            })
+        }).collect();
+
+        Meter {
+            full_scale,
+            ballistics,
+            attack_coefficient: coefficient(ballistics.attack),
+            release_coefficient: coefficient(ballistics.release),
+            detector_state: 0.0,
+            oversampling_taps,
+            oversampling_history: VecDeque::from(vec![0.0; taps_per_phase]),
+            peak_hold_samples: (peak_hold.as_secs_f32() * sample_rate as f32) as usize,
+            peak_fall_db_per_sample: 20.0 / sample_rate as f32, // 20 dB per second
+            held_peak_db: MeterLevels::FLOOR_DB,
+            held_peak_for: 0,
+            k_weighting: k_weighting(sample_rate),
+            loudness_block_length: (sample_rate / 10) as usize,
+            loudness_block_sum: 0.0,
+            loudness_block_count: 0,
+            loudness_blocks: VecDeque::with_capacity(30),
+            levels: MeterLevels {
+                level_db: MeterLevels::FLOOR_DB,
+                true_peak_db: MeterLevels::FLOOR_DB,
+                short_term_lufs: MeterLevels::FLOOR_DB,
+                clipped: false,
+            },
+        }
+    }
+
+    // the gain changed, levels measured so far stay as they are
+    pub fn set_full_scale(&mut self, full_scale: f32) {
+        self.full_scale = full_scale;
+    }
+
+    pub fn ballistics(&self) -> Ballistics {
+        self.ballistics
+    }
+
+    pub fn reset_clip(&mut self) {
+        self.levels.clipped = false;
+    }
+
+    pub fn measure(&mut self, samples: &[f32]) {
+        let mut block_peak: f32 = 0.0;
+
+        for &sample in samples {
+            let sample = sample / self.full_scale;
+
+            // level with ballistics
+            let detector_input = match self.ballistics.detector {
+                Detector::Rms => sample * sample,
+                Detector::Peak => sample.abs(),
+            };
+            let coefficient = if detector_input > self.detector_state { self.attack_coefficient } else { self.release_coefficient };
+            self.detector_state += (detector_input - self.detector_state) * coefficient;
+
+            // true peak, the sample itself is phase 0
+            self.oversampling_history.pop_front();
+            self.oversampling_history.push_back(sample);
+            for phase in 0..4 {
+                let interpolated: f32 = self.oversampling_history.iter().rev().zip(&self.oversampling_taps).map(|(history, taps)| history * taps[phase]).sum();
+                block_peak = block_peak.max(interpolated.abs());
+            }
+
+            // short-term loudness
+            let weighted = self.k_weighting.iter_mut().fold(sample, |sample, stage| stage.process(sample));
+            self.loudness_block_sum += weighted * weighted;
+            self.loudness_block_count += 1;
+            if self.loudness_block_count >= self.loudness_block_length {
+                if self.loudness_blocks.len() >= 30 {
+                    self.loudness_blocks.pop_front();
+                }
+                self.loudness_blocks.push_back(self.loudness_block_sum / self.loudness_block_count as f32);
+                self.loudness_block_sum = 0.0;
+                self.loudness_block_count = 0;
+            }
+        }
+
+        let peak_db = to_db(block_peak);
+        if peak_db >= self.held_peak_db {
+            self.held_peak_db = peak_db;
+            self.held_peak_for = 0;
+        }
+        else {
+            self.held_peak_for += samples.len();
+            if self.held_peak_for > self.peak_hold_samples {
+                let falling_for = (self.held_peak_for - self.peak_hold_samples).min(samples.len());
+                self.held_peak_db = (self.held_peak_db - self.peak_fall_db_per_sample * falling_for as f32).max(peak_db);
+            }
+        }
+
+        let level = match self.ballistics.detector {
+            Detector::Rms => self.detector_state.sqrt(),
+            Detector::Peak => self.detector_state,
+        };
+        let loudness_mean_square = self.loudness_blocks.iter().sum::<f32>() / self.loudness_blocks.len().max(1) as f32;
+
+        self.levels = MeterLevels {
+            level_db: to_db(level),
+            true_peak_db: self.held_peak_db,
+            short_term_lufs: (-0.691 + 10.0 * loudness_mean_square.log10()).max(MeterLevels::FLOOR_DB),
+            clipped: self.levels.clipped || peak_db >= 0.0,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 1 kHz sine of amplitude (share of ADC full scale) after gain, a second of it
+    fn measure(gain: f32, amplitude: f32) -> MeterLevels {
+        let sample_rate = 48000;
+        let mut meter = Meter::new(Ballistics::VU, 1.0, Duration::from_secs(1), sample_rate);
+        meter.set_full_scale(gain);
+        let samples: Vec<f32> = (0..sample_rate).map(|i| (2.0 * PI * 1000.0 * i as f32 / sample_rate as f32).sin() * amplitude * gain).collect();
+        meter.measure(&samples);
+        meter.levels
+    }
+
+    #[test]
+    fn levels_dont_move_with_the_gain() {
+        for gain in [1.0, 3.0, 10.0] {
+            let levels = measure(gain, 0.5);
+            assert!((levels.true_peak_db + 6.02).abs() < 0.2, "gain {}: true peak {}", gain, levels.true_peak_db);
+            assert!((levels.level_db + 9.03).abs() < 0.2, "gain {}: level {}", gain, levels.level_db);
+            assert!(!levels.clipped);
+        }
+    }
+
+    #[test]
+    fn clips_at_adc_full_scale() {
+        for gain in [1.0, 10.0] {
+            assert!(measure(gain, 1.0).clipped, "gain {}", gain);
+            assert!(!measure(gain, 0.9).clipped, "gain {}", gain);
+        }
+    }
+}
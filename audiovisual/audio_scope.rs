@@ -0,0 +1,312 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+/*
+Oscilloscope capture: takes the filtered samples and cuts sweeps out of them that start on a trigger, so a periodic signal starts at the
+same point of its period every sweep and stands still on the display.
+
+The trigger is a rising edge: the signal going from below the level to the level or above. It only re-arms after the signal was a bit below
+the level again (the hysteresis), so noise around the level doesn't trigger on every wiggle. After a trigger the holdoff has to pass before
+the next one counts, for signals that cross the level more than once per period.
+
+Trigger modes like on a bench scope:
+ - Auto: triggered sweeps when there are triggers, a free running sweep when nothing triggered for a while, so there is always a trace
+ - Normal: only triggered sweeps, the last one stays on the display until the next trigger
+ - Single: one triggered sweep and then it stops, rearm() takes the next one
+
+The raw ADC samples (before gain and filters) go along to show what the filters hide: clipping at the ADC and DC on the input, the highpass
+takes that out of the filtered signal. Every sweep has the sample value of the ADC full scale after the gain at the time it was taken, the
+display goes to that so the trace is as wide for the same input whatever the gain knob is at.
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerMode {
+    Auto,
+    Normal,
+    Single,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScopeTrigger {
+    pub mode: TriggerMode,
+    pub level: f32, // in filtered sample values, 0.0 is the middle
+    pub holdoff: Duration, // from the start of a triggered sweep until a new trigger counts
+}
+
+impl ScopeTrigger {
+    // rising edge through 0, no holdoff beyond the sweep itself
+    pub const fn new(mode: TriggerMode) -> Self {
+        ScopeTrigger {
+            mode,
+            level: 0.0,
+            holdoff: Duration::ZERO,
+        }
+    }
+
+    pub const fn with_level(mut self, level: f32) -> Self {
+        self.level = level;
+        self
+    }
+
+    pub const fn with_holdoff(mut self, holdoff: Duration) -> Self {
+        self.holdoff = holdoff;
+        self
+    }
+}
+
+// For a setting or the command line: auto, normal or single, optionally with :LEVEL and :HOLDOFF_MS, like normal:0.1:5
+impl FromStr for ScopeTrigger {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let mode = match parts.next().unwrap_or("").to_ascii_lowercase().as_str() {
+            "auto" => TriggerMode::Auto,
+            "normal" => TriggerMode::Normal,
+            "single" => TriggerMode::Single,
+            mode => return Err(format!("unknown trigger mode: {} (auto, normal or single)", mode))
+        };
+        let mut trigger = ScopeTrigger::new(mode);
+        if let Some(level) = parts.next() {
+            trigger.level = level.parse().map_err(|_| format!("invalid trigger level: {}", level))?;
+        }
+        if let Some(milliseconds) = parts.next() {
+            trigger.holdoff = Duration::from_millis(milliseconds.parse().map_err(|_| format!("invalid trigger holdoff: {}", milliseconds))?);
+        }
+        Ok(trigger)
+    }
+}
+
+// One sweep over the display
+pub struct Sweep {
+    pub samples: Vec<f32>, // filtered
+    pub clipped: Vec<bool>, // raw sample at the end of the ADC range
+    pub dc_offset: f32, // mean of the raw samples, 1.0 is ADC full scale
+    pub full_scale: f32, // filtered sample value of ADC full scale
+    pub triggered: bool, // false for a free running sweep in auto mode
+}
+
+enum CaptureState {
+    Armed, // waiting for the signal to go below the level
+    Ready, // below the level, the next rising edge triggers
+    Capturing,
+    Stopped, // single sweep done
+}
+
+pub struct Scope {
+    trigger: ScopeTrigger,
+    sweep_length: usize, // samples
+    holdoff_samples: usize,
+    auto_timeout_samples: usize, // auto mode free runs after this many samples without a trigger
+    full_scale: f32,
+
+    state: CaptureState,
+    previous_sample: f32,
+    samples_since_sweep_start: usize, // for the holdoff and the auto timeout
+    capture_triggered: bool,
+    capture_samples: Vec<f32>,
+    capture_raw: Vec<f32>,
+
+    // output for comm with visual processor
+    pub sweep: Option<Sweep>,
+    pub new_sweep: bool, // set when sweep is a new one, whoever needs every sweep only once resets it
+}
+
+impl Scope {
+    // timebase is the time the sweep spans over the display
+    pub fn new(trigger: ScopeTrigger, timebase: Duration, sample_rate: u32) -> Self {
+        let to_samples = |duration: Duration| (duration.as_secs_f64() * sample_rate as f64).round() as usize;
+        let sweep_length = to_samples(timebase).max(1);
+
+        Scope {
+            trigger,
+            sweep_length,
+            holdoff_samples: to_samples(trigger.holdoff),
+            auto_timeout_samples: sweep_length * 4 + to_samples(Duration::from_millis(50)),
+            full_scale: 1.0,
+            state: CaptureState::Armed,
+            previous_sample: 0.0,
+            samples_since_sweep_start: 0,
+            capture_triggered: false,
+            capture_samples: Vec::with_capacity(sweep_length),
+            capture_raw: Vec::with_capacity(sweep_length),
+            sweep: None,
+            new_sweep: false,
+        }
+    }
+
+    pub fn trigger(&self) -> ScopeTrigger {
+        self.trigger
+    }
+
+    // the sample value the ADC full scale has after the gain, from the next sweep on
+    pub fn set_full_scale(&mut self, full_scale: f32) {
+        self.full_scale = full_scale;
+    }
+
+    // takes the next sweep in single mode, in the other modes it drops the sweep that is being captured and waits for a new trigger
+    pub fn rearm(&mut self) {
+        self.state = CaptureState::Armed;
+        self.samples_since_sweep_start = 0;
+    }
+
+    // samples after the filters and raw as it came from the ADC, sample for sample
+    pub fn capture(&mut self, samples: &[f32], raw: &[f32]) {
+        let hysteresis = 0.02; // below the level by this much before the trigger re-arms
+
+        for (&sample, &raw_sample) in samples.iter().zip(raw) {
+            self.samples_since_sweep_start += 1;
+
+            let past_holdoff = self.samples_since_sweep_start >= self.holdoff_samples.max(self.sweep_length);
+            match self.state {
+                CaptureState::Armed => {
+                    if sample < self.trigger.level - hysteresis {
+                        self.state = CaptureState::Ready;
+                    }
+                },
+                CaptureState::Ready => {
+                    if sample >= self.trigger.level && self.previous_sample < self.trigger.level && past_holdoff {
+                        self.start_capture(true);
+                    }
+                },
+                CaptureState::Capturing | CaptureState::Stopped => (),
+            }
+
+            // nothing triggered for a while, auto mode shows the signal anyway
+            let waiting = matches!(self.state, CaptureState::Armed | CaptureState::Ready);
+            if waiting && self.trigger.mode == TriggerMode::Auto && self.samples_since_sweep_start >= self.auto_timeout_samples {
+                self.start_capture(false);
+            }
+
+            if let CaptureState::Capturing = self.state {
+                self.capture_samples.push(sample);
+                self.capture_raw.push(raw_sample);
+                if self.capture_samples.len() >= self.sweep_length {
+                    self.finish_capture();
+                }
+            }
+            self.previous_sample = sample;
+        }
+    }
+
+    fn start_capture(&mut self, triggered: bool) {
+        self.state = CaptureState::Capturing;
+        self.capture_triggered = triggered;
+        self.samples_since_sweep_start = 0;
+        self.capture_samples.clear();
+        self.capture_raw.clear();
+    }
+
+    fn finish_capture(&mut self) {
+        let clip_level = 0.99; // of ADC full scale
+
+        self.sweep = Some(Sweep {
+            samples: self.capture_samples.clone(),
+            clipped: self.capture_raw.iter().map(|raw| raw.abs() >= clip_level).collect(),
+            dc_offset: self.capture_raw.iter().sum::<f32>() / self.capture_raw.len() as f32,
+            full_scale: self.full_scale,
+            triggered: self.capture_triggered,
+        });
+        self.new_sweep = true;
+
+        self.state = if self.trigger.mode == TriggerMode::Single && self.capture_triggered { CaptureState::Stopped } else { CaptureState::Armed };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::signals;
+
+    // 1 kHz keeps the numbers small: a sweep is 10 samples, auto free runs after 90
+    const SAMPLE_RATE: u32 = 1000;
+    const TIMEBASE: Duration = Duration::from_millis(10);
+
+    // sample by sample like a stream of tiny reads, the index of the first sample of every finished sweep
+    fn sweep_starts(scope: &mut Scope, samples: &[f32]) -> Vec<usize> {
+        let mut starts = vec![];
+        for (i, sample) in samples.iter().enumerate() {
+            scope.capture(&[*sample], &[*sample]);
+            if std::mem::take(&mut scope.new_sweep) {
+                starts.push(i + 1 - scope.sweep_length);
+            }
+        }
+        starts
+    }
+
+    // 4 samples at -0.5, 4 at 0.5, rising edges at 4, 12, 20, ...
+    fn square(num_samples: usize) -> Vec<f32> {
+        (0..num_samples).map(|i| if i % 8 < 4 { -0.5 } else { 0.5 }).collect()
+    }
+
+    #[test]
+    fn noise_around_the_level_does_not_retrigger() {
+        let mut scope = Scope::new(ScopeTrigger::new(TriggerMode::Normal), TIMEBASE, SAMPLE_RATE);
+
+        // below the level for the length of a sweep, up through it: one sweep from the edge on
+        let mut samples = vec![-0.1; 10];
+        samples.extend([0.1; 15]);
+        // wiggling within the hysteresis crosses the level every other sample but never re-arms
+        samples.extend((0..40).map(|i| if i % 2 == 0 { -0.01 } else { 0.01 }));
+        // far enough below re-arms, the next rising edge is a sweep again
+        samples.extend([-0.05, -0.05, 0.1]);
+        samples.extend([0.1; 10]);
+
+        assert_eq!(sweep_starts(&mut scope, &samples), vec![10, 67]);
+        assert!(scope.sweep.as_ref().unwrap().triggered);
+    }
+
+    #[test]
+    fn holdoff_skips_edges() {
+        // without holdoff a sweep waits for the first edge after its end (and after re-arming), every other edge
+        let mut scope = Scope::new(ScopeTrigger::new(TriggerMode::Normal), TIMEBASE, SAMPLE_RATE);
+        assert_eq!(sweep_starts(&mut scope, &square(80)), vec![12, 28, 44, 60]);
+
+        // 30 ms from the start of a sweep, the first edge after that is 32 samples on
+        let trigger = ScopeTrigger::new(TriggerMode::Normal).with_holdoff(Duration::from_millis(30));
+        let mut scope = Scope::new(trigger, TIMEBASE, SAMPLE_RATE);
+        assert_eq!(sweep_starts(&mut scope, &square(150)), vec![36, 68, 100, 132]);
+    }
+
+    #[test]
+    fn auto_free_runs_without_triggers() {
+        let silence = vec![0.0; 200];
+
+        let mut scope = Scope::new(ScopeTrigger::new(TriggerMode::Auto), TIMEBASE, SAMPLE_RATE);
+        assert_eq!(sweep_starts(&mut scope, &silence), vec![89, 179]);
+        assert!(!scope.sweep.as_ref().unwrap().triggered);
+
+        // when the signal comes, the sweeps are triggered again
+        let sine = signals::sine(50.0, 0.0, 0.5, 100, SAMPLE_RATE);
+        assert!(!sweep_starts(&mut scope, &sine).is_empty());
+        assert!(scope.sweep.as_ref().unwrap().triggered);
+
+        let mut scope = Scope::new(ScopeTrigger::new(TriggerMode::Normal), TIMEBASE, SAMPLE_RATE);
+        assert!(sweep_starts(&mut scope, &silence).is_empty());
+        assert!(scope.sweep.is_none());
+    }
+
+    #[test]
+    fn normal_keeps_the_last_sweep() {
+        let mut scope = Scope::new(ScopeTrigger::new(TriggerMode::Normal).with_level(0.1), TIMEBASE, SAMPLE_RATE);
+        let sine = signals::sine(50.0, 0.0, 0.5, 100, SAMPLE_RATE);
+        assert!(!sweep_starts(&mut scope, &sine).is_empty());
+        let last_samples = scope.sweep.as_ref().unwrap().samples.clone();
+        assert!(last_samples[0] >= 0.1);
+
+        assert!(sweep_starts(&mut scope, &vec![0.0; 1000]).is_empty());
+        let sweep = scope.sweep.as_ref().unwrap();
+        assert!(sweep.triggered);
+        assert_eq!(sweep.samples, last_samples);
+    }
+
+    #[test]
+    fn single_stops_until_rearmed() {
+        let mut scope = Scope::new(ScopeTrigger::new(TriggerMode::Single), TIMEBASE, SAMPLE_RATE);
+        assert_eq!(sweep_starts(&mut scope, &square(80)), vec![12]);
+        assert!(sweep_starts(&mut scope, &square(80)).is_empty());
+
+        scope.rearm();
+        assert_eq!(sweep_starts(&mut scope, &square(80)), vec![12]);
+    }
+}
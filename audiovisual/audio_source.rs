@@ -0,0 +1,137 @@
+/*
+An AudioSource is anything the audiovisual pipeline can pull samples from. On the board that's the I2S line jack (see esp32s3_hw), on a host
+machine it can be a recorded WAV file so AudioProcessor can be driven without hardware attached.
+
+Samples are mono f32, normalized between -1 and 1. read_frames blocks until it has samples; an empty vec means the source is exhausted. A
+source that breaks (a driver error, a corrupt file) returns its error instead, what to do with it is up to whoever reads.
+*/
+pub trait AudioSource {
+    type Error: std::fmt::Display;
+
+    fn read_frames(&mut self) -> Result<Vec<f32>, Self::Error>;
+    fn sample_rate(&self) -> u32;
+}
+
+#[cfg(feature = "host")]
+pub use wav_source::WavAudioSource;
+
+#[cfg(feature = "host")]
+mod wav_source {
+    use std::{fs::File, io::BufReader, path::Path};
+    use hound::{SampleFormat, WavReader};
+
+    use super::AudioSource;
+
+    /*
+    Reads a WAV file in chunks of frames_per_read. Multichannel files get downmixed to mono by averaging the channels, integer formats are
+    normalized by their bit depth. frames_per_read defaults to 768, the same amount of samples one I2S read on the board delivers.
+    A file that can't be decoded gives the decode error in read_frames instead of ending, the end of the file is the only empty read.
+    */
+    pub struct WavAudioSource {
+        reader: WavReader<BufReader<File>>,
+        sample_rate: u32,
+        channels: usize,
+        bits_per_sample: u16,
+        sample_format: SampleFormat,
+        frames_per_read: usize,
+    }
+
+    impl WavAudioSource {
+        pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, hound::Error> {
+            let reader = WavReader::open(path)?;
+            let spec = reader.spec();
+
+            Ok(WavAudioSource {
+                reader,
+                sample_rate: spec.sample_rate,
+                channels: spec.channels as usize,
+                bits_per_sample: spec.bits_per_sample,
+                sample_format: spec.sample_format,
+                frames_per_read: 768,
+            })
+        }
+
+        pub fn with_frames_per_read(mut self, frames_per_read: usize) -> Self {
+            self.frames_per_read = frames_per_read.max(1);
+            self
+        }
+
+        // read interleaved samples of all channels, normalized. A decode error stops right there, a corrupt file should not look like one
+        // that ended early and give plausible but wrong output.
+        fn read_interleaved(&mut self, amount: usize) -> Result<Vec<f32>, hound::Error> {
+            match self.sample_format {
+                SampleFormat::Float => {
+                    self.reader.samples::<f32>().take(amount).collect()
+                },
+                SampleFormat::Int => {
+                    let full_scale = (1i64 << (self.bits_per_sample - 1)) as f32;
+                    self.reader.samples::<i32>().take(amount).map(|x| x.map(|x| x as f32 / full_scale)).collect()
+                }
+            }
+        }
+    }
+
+    impl AudioSource for WavAudioSource {
+        type Error = hound::Error;
+
+        fn read_frames(&mut self) -> Result<Vec<f32>, hound::Error> {
+            let interleaved = self.read_interleaved(self.frames_per_read * self.channels)?;
+
+            // an incomplete last frame is dropped
+            Ok(interleaved.chunks_exact(self.channels)
+                .map(|frame| frame.iter().sum::<f32>() / self.channels as f32)
+                .collect())
+        }
+
+        fn sample_rate(&self) -> u32 {
+            self.sample_rate
+        }
+    }
+}
+
+#[cfg(all(test, feature = "host"))]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // 16 bit stereo, both channels a ramp, in the temp directory. Cut short after cut_at bytes when given, like a copy that broke off.
+    fn write_wav(name: &str, num_frames: usize, cut_at: Option<u64>) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("frequatuner_{}_{}.wav", name, std::process::id()));
+        let spec = hound::WavSpec { channels: 2, sample_rate: 48000, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for i in 0..num_frames {
+            writer.write_sample(i as i16).unwrap();
+            writer.write_sample(i as i16 + 2).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        if let Some(length) = cut_at {
+            std::fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(length).unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn reads_until_an_empty_read() {
+        let path = write_wav("whole", 1000, None);
+        let mut audio_source = WavAudioSource::open(&path).unwrap().with_frames_per_read(768);
+        assert_eq!(audio_source.sample_rate(), 48000);
+
+        let first = audio_source.read_frames().unwrap();
+        assert_eq!(first.len(), 768);
+        assert_eq!(first[10], 11.0 / 32768.0); // the channels averaged
+        assert_eq!(audio_source.read_frames().unwrap().len(), 232);
+        assert!(audio_source.read_frames().unwrap().is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn corrupt_file_gives_an_error() {
+        // the header promises 1000 frames, the data stops after 400 and half a sample
+        let path = write_wav("cut", 1000, Some(44 + 400 * 4 + 1));
+        let mut audio_source = WavAudioSource::open(&path).unwrap();
+
+        assert!(audio_source.read_frames().is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
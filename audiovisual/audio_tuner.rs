@@ -8,7 +8,14 @@ use pitch_detector::note::NoteDetectionResult;
 
 /*
 GiTuner runs the pitch detection process and supplies output.
+
+The pitch detector skips windows with less power (sum of the squares) than POWER_THRESHOLD, so noise and a fading string don't give random
+notes. The threshold is on the samples after the gain: turning the gain knob up lets softer notes through, and more noise with them. It
+was 0.00005 when the tuner amplified 2.5x on its own, the gain is all in the AudioProcessor now so that's 0.00005 / 2.5² for the same
+sensitivity at GAIN_DEFAULT_DB.
 */
+const POWER_THRESHOLD: f32 = 0.000008;
+
 pub struct GiTuner {
     samples_buffer: Vec<f32>,
     samples_max: usize,
@@ -37,10 +44,13 @@ impl GiTuner {
         }
     }
 
+    // every analysis window is this many samples, note_info gets updated once per window
+    pub fn samples_per_analysis(&self) -> usize {
+        self.samples_max
+    }
+
     pub fn tune(&mut self, samples: Vec<f32>, sample_rate: u32) {
-        let gain = 2.5; // raw guitar signal is pretty weak for tuning, amplify it some more
-        let mut samples_to_add = samples.into_iter().map(|x| x*gain ).collect();
-        self.samples_buffer.append(&mut samples_to_add);
+        self.samples_buffer.extend(samples);
 
         while self.samples_buffer.len() >= self.samples_max as usize {
             let samples_to_process: Vec<f32> = self.samples_buffer.splice(0..self.samples_max, []).collect();
@@ -72,7 +82,7 @@ impl RawBuffer {
     pub fn pitch_detection(self, detector: &mut McLeodDetector<f32>, recent_freqs: &mut VecDeque<f32>, sample_rate: u32) -> PitchDetermined {
         let mut mean_freq = None; // by default we have no new mean frequency
 
-        if let Some(pitch) = detector.get_pitch(&self.buffer, sample_rate as usize, 0.00005, 0.5) {
+        if let Some(pitch) = detector.get_pitch(&self.buffer, sample_rate as usize, POWER_THRESHOLD, 0.5) {
             let frequency = pitch.frequency;
             recent_freqs.push_back(frequency);
         }
@@ -130,4 +140,33 @@ impl PitchDetermined {
         }  
         None // we don't have a new mean or detection result, don't update the tuner output
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::config::{GAIN_CURVE, AUDIO_SAMPLE_RATE};
+    use super::super::signals;
+
+    // an A2 on the ADC, softer than the threshold at the bottom of the knob but not at the top, through the gain
+    fn tune_weak_note(gain_db: f32) -> Option<NoteDetectionResult> {
+        let gain = 10f32.powf(gain_db / 20.0);
+        let amplitude = 0.00003; // of ADC full scale
+        let note = signals::sine(110.0, 0.0, amplitude * gain, AUDIO_SAMPLE_RATE as usize, AUDIO_SAMPLE_RATE);
+
+        let mut tuner = GiTuner::new();
+        tuner.tune(note, AUDIO_SAMPLE_RATE);
+        tuner.note_info
+    }
+
+    #[test]
+    fn weak_note_not_picked_up_with_the_knob_down() {
+        assert!(tune_weak_note(GAIN_CURVE.min_db).is_none());
+    }
+
+    #[test]
+    fn weak_note_picked_up_with_the_knob_up() {
+        let note_info = tune_weak_note(GAIN_CURVE.max_db).expect("No note at the top of the gain curve");
+        assert_eq!(note_info.note_name.to_string(), "A");
+        assert_eq!(note_info.octave, 2);
+    }
+}
@@ -0,0 +1,296 @@
+use std::time::Duration;
+use super::tile_map::TileMap;
+use super::framebuffer::Framebuffer;
+use super::pixel_format::PixelFormat;
+use super::animation::KeyframeAnimation;
+use super::theme::Theme;
+use super::graphics::switch_animation;
+use super::mode::{Mode, ModeRegistry};
+use super::{audio_fft_binner, audio_tuner, audio_scope, audio_meter};
+use super::{visual_bins_to_animation, visual_tuner_painter, visual_spectrogram_painter, visual_scope_painter, visual_meter_painter};
+use super::config;
+
+/*
+The modes that come with the frequatuner, each is an analyzer from one of the audio_ modules with a painter from one of the visual_ modules.
+They start in config::DEFAULT_THEME, the ModeRegistry gives them its theme when they go in.
+
+The splashes walk in from alternating sides so a switch looks different from the one before, in the default order at least.
+*/
+
+// A registry with the built-in modes and their settings from config, in the order of MODE_ORDER
+pub fn registry(sample_rate: u32, tile_map: &TileMap, pixel_format: PixelFormat) -> ModeRegistry {
+    ModeRegistry::new()
+        .with_mode(EqualizerMode::new(sample_rate, tile_map, pixel_format))
+        .with_mode(TunerMode::new(sample_rate, tile_map, pixel_format))
+        .with_mode(SpectrogramMode::new(sample_rate, tile_map, pixel_format))
+        .with_mode(ScopeMode::new(sample_rate, tile_map, pixel_format))
+        .with_mode(MeterMode::new(sample_rate, tile_map, pixel_format))
+        .with_order(config::MODE_ORDER)
+}
+
+// Frequency bins as bars, one bin per row of the canvas
+pub struct EqualizerMode {
+    frequalizer: audio_fft_binner::AudioFrequalizer,
+    painter: visual_bins_to_animation::Painter,
+}
+
+impl EqualizerMode {
+    pub fn new(sample_rate: u32, tile_map: &TileMap, pixel_format: PixelFormat) -> Self {
+        EqualizerMode {
+            frequalizer: audio_fft_binner::AudioFrequalizer::new(tile_map.height(), sample_rate),
+            painter: visual_bins_to_animation::Painter::new(tile_map.clone(), pixel_format, config::DEFAULT_THEME),
+        }
+    }
+}
+
+impl Mode for EqualizerMode {
+    fn name(&self) -> &'static str {
+        "eq"
+    }
+
+    fn analyze(&mut self, samples: &[f32], _raw_samples: &[f32]) {
+        self.frequalizer.frequalize(samples.to_vec());
+    }
+
+    // the bars fade and the background pulses, so every paint is a new frame
+    fn paint(&mut self, now: Duration) -> bool {
+        self.painter.paint(&self.frequalizer.eq_bins, now);
+        true
+    }
+
+    fn frame(&self) -> &Framebuffer {
+        self.painter.frame()
+    }
+
+    fn set_theme(&mut self, theme: &'static Theme) {
+        self.painter.set_theme(theme);
+    }
+
+    fn splash(&self, tile_map: &TileMap, theme: &Theme) -> KeyframeAnimation {
+        switch_animation("EQ", theme.splash.equalizer_background, false, tile_map, theme)
+    }
+}
+
+// Pitch of the note played against the nearest note
+pub struct TunerMode {
+    sample_rate: u32,
+    tuner: audio_tuner::GiTuner,
+    painter: visual_tuner_painter::Painter,
+}
+
+impl TunerMode {
+    pub fn new(sample_rate: u32, tile_map: &TileMap, pixel_format: PixelFormat) -> Self {
+        TunerMode {
+            sample_rate,
+            tuner: audio_tuner::GiTuner::new(),
+            painter: visual_tuner_painter::Painter::new(tile_map.clone(), pixel_format, config::DEFAULT_THEME),
+        }
+    }
+}
+
+impl Mode for TunerMode {
+    fn name(&self) -> &'static str {
+        "tuner"
+    }
+
+    fn analyze(&mut self, samples: &[f32], _raw_samples: &[f32]) {
+        self.tuner.tune(samples.to_vec(), self.sample_rate);
+    }
+
+    // note_info is optional because the pitch detector is strict, the tuner keeps its last frame until there is a new note
+    fn paint(&mut self, _now: Duration) -> bool {
+        match &self.tuner.note_info {
+            Some(note_info) => {
+                self.painter.paint(note_info);
+                true
+            }
+            None => false
+        }
+    }
+
+    fn frame(&self) -> &Framebuffer {
+        self.painter.frame()
+    }
+
+    fn set_theme(&mut self, theme: &'static Theme) {
+        self.painter.set_theme(theme);
+    }
+
+    fn splash(&self, tile_map: &TileMap, theme: &Theme) -> KeyframeAnimation {
+        switch_animation("TU", theme.splash.tuner_background, true, tile_map, theme)
+    }
+}
+
+// The equalizer's bins over the last seconds, scrolling
+pub struct SpectrogramMode {
+    frequalizer: audio_fft_binner::AudioFrequalizer,
+    painter: visual_spectrogram_painter::Painter,
+}
+
+impl SpectrogramMode {
+    pub fn new(sample_rate: u32, tile_map: &TileMap, pixel_format: PixelFormat) -> Self {
+        SpectrogramMode {
+            frequalizer: audio_fft_binner::AudioFrequalizer::new(tile_map.height(), sample_rate),
+            painter: visual_spectrogram_painter::Painter::new(tile_map.clone(), pixel_format, config::DEFAULT_THEME),
+        }
+    }
+}
+
+impl Mode for SpectrogramMode {
+    fn name(&self) -> &'static str {
+        "spectrogram"
+    }
+
+    fn analyze(&mut self, samples: &[f32], _raw_samples: &[f32]) {
+        self.frequalizer.frequalize(samples.to_vec());
+    }
+
+    // every FFT frame is drawn once, the waterfall would otherwise count the same frame more than once. The levels are not normalized, silence
+    // stays dark and a note fades out like it sounds
+    fn paint(&mut self, now: Duration) -> bool {
+        if !std::mem::take(&mut self.frequalizer.new_frame) {
+            return false
+        }
+        self.painter.paint(&self.frequalizer.level_bins, now);
+        true
+    }
+
+    fn frame(&self) -> &Framebuffer {
+        self.painter.frame()
+    }
+
+    fn set_theme(&mut self, theme: &'static Theme) {
+        self.painter.set_theme(theme);
+    }
+
+    fn splash(&self, tile_map: &TileMap, theme: &Theme) -> KeyframeAnimation {
+        switch_animation("SP", theme.splash.spectrogram_background, false, tile_map, theme)
+    }
+}
+
+// The waveform with a trigger, and the clipping and DC of the raw input
+pub struct ScopeMode {
+    sample_rate: u32,
+    scope: audio_scope::Scope,
+    painter: visual_scope_painter::Painter,
+}
+
+impl ScopeMode {
+    pub fn new(sample_rate: u32, tile_map: &TileMap, pixel_format: PixelFormat) -> Self {
+        ScopeMode {
+            sample_rate,
+            scope: audio_scope::Scope::new(config::SCOPE_TRIGGER, config::SCOPE_TIMEBASE, sample_rate),
+            painter: visual_scope_painter::Painter::new(tile_map.clone(), pixel_format, config::DEFAULT_THEME),
+        }
+    }
+
+    // a trigger other than the SCOPE_TRIGGER from config
+    pub fn with_trigger(mut self, trigger: audio_scope::ScopeTrigger) -> Self {
+        self.scope = audio_scope::Scope::new(trigger, config::SCOPE_TIMEBASE, self.sample_rate);
+        self
+    }
+}
+
+impl Mode for ScopeMode {
+    fn name(&self) -> &'static str {
+        "scope"
+    }
+
+    // the scope shows clipping and DC of what came in, the filters hide both
+    fn analyze(&mut self, samples: &[f32], raw_samples: &[f32]) {
+        self.scope.capture(samples, raw_samples);
+    }
+
+    fn set_input_gain(&mut self, gain: f32) {
+        self.scope.set_full_scale(gain);
+    }
+
+    // in normal and single trigger mode the last sweep stays up until there is a new one
+    fn paint(&mut self, _now: Duration) -> bool {
+        if !std::mem::take(&mut self.scope.new_sweep) {
+            return false
+        }
+        let Some(sweep) = &self.scope.sweep else {
+            return false
+        };
+        self.painter.paint(sweep);
+        true
+    }
+
+    fn frame(&self) -> &Framebuffer {
+        self.painter.frame()
+    }
+
+    fn set_theme(&mut self, theme: &'static Theme) {
+        self.painter.set_theme(theme);
+    }
+
+    fn splash(&self, tile_map: &TileMap, theme: &Theme) -> KeyframeAnimation {
+        switch_animation("SC", theme.splash.scope_background, true, tile_map, theme)
+    }
+
+    // a fresh sweep, in single trigger mode that's the one that stays up
+    fn enter(&mut self) {
+        self.scope.rearm();
+    }
+}
+
+// Absolute levels as segmented bars
+pub struct MeterMode {
+    sample_rate: u32,
+    meter: audio_meter::Meter,
+    painter: visual_meter_painter::Painter,
+}
+
+impl MeterMode {
+    pub fn new(sample_rate: u32, tile_map: &TileMap, pixel_format: PixelFormat) -> Self {
+        MeterMode {
+            sample_rate,
+            meter: audio_meter::Meter::new(config::METER_BALLISTICS, 1.0, config::METER_PEAK_HOLD, sample_rate), // full scale follows the gain
+            painter: visual_meter_painter::Painter::new(tile_map.clone(), pixel_format, config::DEFAULT_THEME),
+        }
+    }
+
+    // VU or PPM instead of the METER_BALLISTICS from config
+    pub fn with_ballistics(mut self, ballistics: audio_meter::Ballistics) -> Self {
+        self.meter = audio_meter::Meter::new(ballistics, 1.0, config::METER_PEAK_HOLD, self.sample_rate);
+        self
+    }
+}
+
+impl Mode for MeterMode {
+    fn name(&self) -> &'static str {
+        "meter"
+    }
+
+    fn analyze(&mut self, samples: &[f32], _raw_samples: &[f32]) {
+        self.meter.measure(samples);
+    }
+
+    // 0 dBFS stays where the ADC clips, whatever the knob is at
+    fn set_input_gain(&mut self, gain: f32) {
+        self.meter.set_full_scale(gain);
+    }
+
+    fn paint(&mut self, _now: Duration) -> bool {
+        self.painter.paint(&self.meter.levels);
+        true
+    }
+
+    fn frame(&self) -> &Framebuffer {
+        self.painter.frame()
+    }
+
+    fn set_theme(&mut self, theme: &'static Theme) {
+        self.painter.set_theme(theme);
+    }
+
+    fn splash(&self, tile_map: &TileMap, theme: &Theme) -> KeyframeAnimation {
+        switch_animation("VU", theme.splash.meter_background, false, tile_map, theme)
+    }
+
+    // coming back to the meter clears the clip latch
+    fn enter(&mut self) {
+        self.meter.reset_clip();
+    }
+}
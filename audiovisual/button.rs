@@ -0,0 +1,464 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::time::Duration;
+use super::tile_map::TileMap;
+use super::mode::ModeRegistry;
+use super::VisualProcessor;
+use super::config::{BUTTON_DEBOUNCE, BUTTON_LONG_PRESS, BUTTON_DOUBLE_PRESS_GAP, BUTTON_HOLD_REPEAT};
+
+/*
+The mode button, from bouncing contacts to gestures. Nothing in here touches hardware: the board's interrupt pushes timestamped edges into
+an EdgeQueue, the main loop takes them out and feeds them to the GestureRecognizer, the simulator does the same with made up edges.
+
+Debounce: a level counts once it has been stable for BUTTON_DEBOUNCE, the bounces before that are dropped. The press or release counts
+from the last edge, so a bouncy contact is a few ms late at most.
+
+Gestures, on the debounced level:
+ - ShortPress: pressed and released before BUTTON_LONG_PRESS, and no second press within BUTTON_DOUBLE_PRESS_GAP. That means a short press
+   is only known once the gap has passed, the price of having double presses.
+ - DoublePress: two short presses with less than BUTTON_DOUBLE_PRESS_GAP in between
+ - LongPress: pressed for BUTTON_LONG_PRESS, comes while the button is still down
+ - Hold: after the long press, every BUTTON_HOLD_REPEAT for as long as the button stays down
+
+A press that is the second of a double press but turns into a long press is a ShortPress and then a LongPress.
+
+ButtonBindings says what each gesture does, see BUTTON_BINDINGS in config.
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtonEvent {
+    ShortPress,
+    LongPress,
+    DoublePress,
+    Hold,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtonAction {
+    NextMode,
+    PreviousMode,
+    Freeze, // keeps the frame of the mode on the display while it goes on analyzing, again to unfreeze
+    NextTheme, // the theme is the setting there is on the device, bound to Hold it goes through the themes while the button stays down
+}
+
+impl ButtonAction {
+    // switches show the splash of the new mode like the board always did, and unfreeze. So does a theme switch, a frozen frame would keep
+    // the old colours.
+    pub fn perform(self, modes: &mut ModeRegistry, visual_processor: &mut VisualProcessor, tile_map: &TileMap) {
+        match self {
+            ButtonAction::NextMode | ButtonAction::PreviousMode => {
+                if self == ButtonAction::NextMode { modes.next() } else { modes.previous() }
+                visual_processor.set_frozen(false);
+                visual_processor.play_animation(modes.splash(tile_map));
+            }
+            ButtonAction::Freeze => visual_processor.set_frozen(!visual_processor.frozen()),
+            ButtonAction::NextTheme => {
+                modes.set_theme(modes.theme().next());
+                visual_processor.set_frozen(false);
+            }
+        }
+    }
+}
+
+// What each gesture does, None does nothing
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ButtonBindings {
+    pub short_press: Option<ButtonAction>,
+    pub long_press: Option<ButtonAction>,
+    pub double_press: Option<ButtonAction>,
+    pub hold: Option<ButtonAction>,
+}
+
+impl ButtonBindings {
+    // nothing bound
+    pub const fn new() -> Self {
+        ButtonBindings {
+            short_press: None,
+            long_press: None,
+            double_press: None,
+            hold: None,
+        }
+    }
+
+    pub const fn with_binding(mut self, event: ButtonEvent, action: ButtonAction) -> Self {
+        match event {
+            ButtonEvent::ShortPress => self.short_press = Some(action),
+            ButtonEvent::LongPress => self.long_press = Some(action),
+            ButtonEvent::DoublePress => self.double_press = Some(action),
+            ButtonEvent::Hold => self.hold = Some(action),
+        }
+        self
+    }
+
+    pub fn action(&self, event: ButtonEvent) -> Option<ButtonAction> {
+        match event {
+            ButtonEvent::ShortPress => self.short_press,
+            ButtonEvent::LongPress => self.long_press,
+            ButtonEvent::DoublePress => self.double_press,
+            ButtonEvent::Hold => self.hold,
+        }
+    }
+}
+
+impl Default for ButtonBindings {
+    fn default() -> Self {
+        ButtonBindings::new()
+    }
+}
+
+/*
+Edges from the interrupt to the main loop. Single producer single consumer without locks, so the interrupt can push without waiting for
+anything: an edge is a u32 with the level in the top bit and the milliseconds of the timestamp in the rest. That wraps after 24 days, pop
+takes the time of the main loop to put it back on the same clock. N has to be a power of two. Edges that don't fit are dropped, which
+only happens when the main loop doesn't come by for a while during a lot of bouncing. The GestureRecognizer picks the level up again from
+GestureRecognizer::level then.
+*/
+pub struct EdgeQueue<const N: usize> {
+    slots: [AtomicU32; N],
+    pushed: AtomicUsize, // counts, wrapping
+    popped: AtomicUsize,
+}
+
+impl<const N: usize> EdgeQueue<N> {
+    const PRESSED_BIT: u32 = 1 << 31;
+
+    pub const fn new() -> Self {
+        EdgeQueue {
+            slots: [const { AtomicU32::new(0) }; N],
+            pushed: AtomicUsize::new(0),
+            popped: AtomicUsize::new(0),
+        }
+    }
+
+    // from the interrupt, at is the time of the edge and pressed the level after it
+    pub fn push(&self, at: Duration, pressed: bool) {
+        let pushed = self.pushed.load(Ordering::Relaxed);
+        if pushed.wrapping_sub(self.popped.load(Ordering::Acquire)) >= N {
+            return
+        }
+        let millis = at.as_millis() as u32 & !Self::PRESSED_BIT;
+        self.slots[pushed % N].store(if pressed { millis | Self::PRESSED_BIT } else { millis }, Ordering::Relaxed);
+        self.pushed.store(pushed.wrapping_add(1), Ordering::Release);
+    }
+
+    // the oldest edge, now is the time on the clock the edges were pushed with
+    pub fn pop(&self, now: Duration) -> Option<(Duration, bool)> {
+        let popped = self.popped.load(Ordering::Relaxed);
+        if popped == self.pushed.load(Ordering::Acquire) {
+            return None
+        }
+        let slot = self.slots[popped % N].load(Ordering::Relaxed);
+        self.popped.store(popped.wrapping_add(1), Ordering::Release);
+
+        let now_millis = now.as_millis() as u32 & !Self::PRESSED_BIT;
+        let age = now_millis.wrapping_sub(slot & !Self::PRESSED_BIT) & !Self::PRESSED_BIT;
+        Some((now.saturating_sub(Duration::from_millis(age as u64)), slot & Self::PRESSED_BIT != 0))
+    }
+}
+
+impl<const N: usize> Default for EdgeQueue<N> {
+    fn default() -> Self {
+        EdgeQueue::new()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum GestureState {
+    Idle,
+    Down {
+        since: Duration,
+        second: bool, // within the gap after a short press, a double press if released in time
+        long_pressed: bool,
+        next_hold: Duration,
+    },
+    WaitingForSecond {
+        released_at: Duration,
+    },
+}
+
+pub struct GestureRecognizer {
+    debounce: Duration,
+    long_press: Duration,
+    double_press_gap: Duration,
+    hold_repeat: Duration,
+
+    raw_pressed: bool, // the level after the last edge
+    raw_since: Duration,
+    pressed: bool, // debounced
+    state: GestureState,
+    events: VecDeque<ButtonEvent>,
+}
+
+impl GestureRecognizer {
+    // the timings from config
+    pub fn new() -> Self {
+        GestureRecognizer {
+            debounce: BUTTON_DEBOUNCE,
+            long_press: BUTTON_LONG_PRESS,
+            double_press_gap: BUTTON_DOUBLE_PRESS_GAP,
+            hold_repeat: BUTTON_HOLD_REPEAT,
+            raw_pressed: false,
+            raw_since: Duration::ZERO,
+            pressed: false,
+            state: GestureState::Idle,
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    pub fn with_long_press(mut self, long_press: Duration) -> Self {
+        self.long_press = long_press;
+        self
+    }
+
+    pub fn with_double_press_gap(mut self, double_press_gap: Duration) -> Self {
+        self.double_press_gap = double_press_gap;
+        self
+    }
+
+    pub fn with_hold_repeat(mut self, hold_repeat: Duration) -> Self {
+        self.hold_repeat = hold_repeat.max(Duration::from_millis(1)); // 0 would repeat forever
+        self
+    }
+
+    // an edge, in the order they happened. at is on the same clock as poll's now.
+    pub fn edge(&mut self, at: Duration, pressed: bool) {
+        if pressed == self.raw_pressed {
+            return // the edge in between got lost, nothing changed
+        }
+        self.advance(at);
+        self.raw_pressed = pressed;
+        self.raw_since = at;
+    }
+
+    // the level as it is now, for edges that got lost on the way (a full EdgeQueue): a level that differs from the last edge is an edge now
+    pub fn level(&mut self, now: Duration, pressed: bool) {
+        self.edge(now, pressed);
+    }
+
+    // the next gesture that has happened by now, None when there are no more
+    pub fn poll(&mut self, now: Duration) -> Option<ButtonEvent> {
+        self.advance(now);
+        self.events.pop_front()
+    }
+
+    // everything that happened up to this time: the debounced level when the last edge has settled, and the timers of the gestures
+    fn advance(&mut self, to: Duration) {
+        if self.raw_pressed != self.pressed {
+            if to < self.raw_since + self.debounce {
+                self.run_timers(to.min(self.raw_since)); // may still bounce back, only what happened before the edge
+                return
+            }
+            self.run_timers(self.raw_since);
+            self.settled(self.raw_pressed, self.raw_since);
+        }
+        self.run_timers(to);
+    }
+
+    fn settled(&mut self, pressed: bool, at: Duration) {
+        self.pressed = pressed;
+        self.state = match (self.state, pressed) {
+            (GestureState::Idle, true) => GestureState::Down { since: at, second: false, long_pressed: false, next_hold: Duration::ZERO },
+            (GestureState::WaitingForSecond { .. }, true) => GestureState::Down { since: at, second: true, long_pressed: false, next_hold: Duration::ZERO },
+            (GestureState::Down { long_pressed: true, .. }, false) => GestureState::Idle,
+            (GestureState::Down { second: true, .. }, false) => {
+                self.events.push_back(ButtonEvent::DoublePress);
+                GestureState::Idle
+            }
+            (GestureState::Down { .. }, false) => GestureState::WaitingForSecond { released_at: at },
+            (state, _) => state,
+        };
+    }
+
+    fn run_timers(&mut self, to: Duration) {
+        loop {
+            self.state = match self.state {
+                GestureState::Down { since, second, long_pressed: false, .. } if to >= since + self.long_press => {
+                    if second {
+                        self.events.push_back(ButtonEvent::ShortPress);
+                    }
+                    self.events.push_back(ButtonEvent::LongPress);
+                    GestureState::Down { since, second: false, long_pressed: true, next_hold: since + self.long_press + self.hold_repeat }
+                }
+                GestureState::Down { since, long_pressed: true, next_hold, .. } if to >= next_hold => {
+                    self.events.push_back(ButtonEvent::Hold);
+                    GestureState::Down { since, second: false, long_pressed: true, next_hold: next_hold + self.hold_repeat }
+                }
+                GestureState::WaitingForSecond { released_at } if to >= released_at + self.double_press_gap => {
+                    self.events.push_back(ButtonEvent::ShortPress);
+                    GestureState::Idle
+                }
+                _ => break
+            };
+        }
+    }
+}
+
+impl Default for GestureRecognizer {
+    fn default() -> Self {
+        GestureRecognizer::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::theme;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    // the edges of a press from down to up, with bouncing contacts on both like the simulator presses. It settles 9 ms after down and
+    // 5 ms after up.
+    fn press(down: u64, up: u64) -> Vec<(u64, bool)> {
+        let bounces = [(0, true), (2, false), (4, true), (7, false), (9, true)].map(|(at, pressed)| (down + at, pressed));
+        let release = [(0, false), (3, true), (5, false)].map(|(at, pressed)| (up + at, pressed));
+        bounces.into_iter().chain(release).collect()
+    }
+
+    // every ms like the main loop: the edges up to now go in, then the gestures come out with the ms they came at
+    fn gestures(edges: &[(u64, bool)], until: u64) -> Vec<(u64, ButtonEvent)> {
+        let mut recognizer = GestureRecognizer::new()
+            .with_debounce(ms(30))
+            .with_long_press(ms(600))
+            .with_double_press_gap(ms(300))
+            .with_hold_repeat(ms(200));
+        let mut edges = edges.iter().peekable();
+        let mut gestures = vec![];
+        for now in 0..=until {
+            while let Some(&(at, pressed)) = edges.next_if(|(at, _)| *at <= now) {
+                recognizer.edge(ms(at), pressed);
+            }
+            while let Some(event) = recognizer.poll(ms(now)) {
+                gestures.push((now, event));
+            }
+        }
+        gestures
+    }
+
+    #[test]
+    fn bounces_are_one_press() {
+        // released at 205 after the last bounce, it's a short press once the gap has passed
+        assert_eq!(gestures(&press(100, 200), 1000), vec![(505, ButtonEvent::ShortPress)]);
+    }
+
+    #[test]
+    fn glitch_shorter_than_debounce_is_ignored() {
+        assert_eq!(gestures(&[(100, true), (110, false)], 1000), vec![]);
+    }
+
+    #[test]
+    fn double_press_just_within_the_gap() {
+        // the second press settles at 504, one ms before the gap after the release at 205 is over
+        let edges = [press(100, 200), press(495, 600)].concat();
+        assert_eq!(gestures(&edges, 1500), vec![(635, ButtonEvent::DoublePress)]);
+    }
+
+    #[test]
+    fn two_short_presses_at_the_gap() {
+        // settles at 505, the first press already was a short press
+        let edges = [press(100, 200), press(496, 600)].concat();
+        assert_eq!(gestures(&edges, 1500), vec![(505, ButtonEvent::ShortPress), (905, ButtonEvent::ShortPress)]);
+    }
+
+    #[test]
+    fn long_press_then_hold_repeats() {
+        // down since 109, the hold at 1309 doesn't come because the release at 1300 was before it
+        assert_eq!(gestures(&press(100, 1300), 2000), vec![
+            (709, ButtonEvent::LongPress),
+            (909, ButtonEvent::Hold),
+            (1109, ButtonEvent::Hold),
+        ]);
+    }
+
+    #[test]
+    fn second_press_of_a_double_turning_long() {
+        let edges = [press(100, 200), press(400, 1100)].concat();
+        assert_eq!(gestures(&edges, 1500), vec![(1009, ButtonEvent::ShortPress), (1009, ButtonEvent::LongPress)]);
+    }
+
+    #[test]
+    fn level_picks_up_a_lost_edge() {
+        let mut recognizer = GestureRecognizer::new();
+        recognizer.edge(ms(100), true);
+        recognizer.level(ms(200), false); // the release edge never came
+        assert_eq!(recognizer.poll(ms(200 + BUTTON_DOUBLE_PRESS_GAP.as_millis() as u64 - 1)), None);
+        assert_eq!(recognizer.poll(ms(200 + BUTTON_DOUBLE_PRESS_GAP.as_millis() as u64)), Some(ButtonEvent::ShortPress));
+    }
+
+    #[test]
+    fn hold_goes_through_the_themes() {
+        let tile_map = TileMap::new(super::super::config::PANELS);
+        let mut modes = ModeRegistry::new();
+        let mut visual_processor = VisualProcessor::new(tile_map.clone(), super::super::config::PIXEL_FORMAT);
+        let action = super::super::config::BUTTON_BINDINGS.action(ButtonEvent::Hold).expect("Hold should be bound");
+
+        let first = modes.theme().name;
+        let mut seen = vec![first];
+        for _ in 1..theme::THEMES.len() {
+            action.perform(&mut modes, &mut visual_processor, &tile_map);
+            seen.push(modes.theme().name);
+        }
+        action.perform(&mut modes, &mut visual_processor, &tile_map);
+        assert_eq!(modes.theme().name, first, "back to the first theme after all of them");
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), theme::THEMES.len());
+    }
+
+    #[test]
+    fn queue_keeps_order_and_levels() {
+        let queue: EdgeQueue<4> = EdgeQueue::new();
+        queue.push(ms(10), true);
+        queue.push(ms(12), false);
+        assert_eq!(queue.pop(ms(20)), Some((ms(10), true)));
+        assert_eq!(queue.pop(ms(20)), Some((ms(12), false)));
+        assert_eq!(queue.pop(ms(20)), None);
+    }
+
+    #[test]
+    fn queue_drops_edges_that_dont_fit() {
+        let queue: EdgeQueue<4> = EdgeQueue::new();
+        for at in 0..6 {
+            queue.push(ms(at), at % 2 == 0);
+        }
+        let popped: Vec<_> = std::iter::from_fn(|| queue.pop(ms(10))).collect();
+        assert_eq!(popped, vec![(ms(0), true), (ms(1), false), (ms(2), true), (ms(3), false)]);
+
+        // room again after popping
+        queue.push(ms(20), true);
+        assert_eq!(queue.pop(ms(30)), Some((ms(20), true)));
+    }
+
+    #[test]
+    fn queue_millis_wrap_around() {
+        // the milliseconds wrap at 2^31, edges from before that popped after
+        let wrap = 1u64 << 31;
+        let queue: EdgeQueue<4> = EdgeQueue::new();
+        queue.push(ms(wrap - 5), true);
+        queue.push(ms(wrap + 3), false);
+        assert_eq!(queue.pop(ms(wrap + 10)), Some((ms(wrap - 5), true)));
+        assert_eq!(queue.pop(ms(wrap + 10)), Some((ms(wrap + 3), false)));
+
+        // a few wraps in they still land on the clock of the main loop
+        queue.push(ms(3 * wrap + 7), true);
+        assert_eq!(queue.pop(ms(3 * wrap + 9)), Some((ms(3 * wrap + 7), true)));
+    }
+
+    #[test]
+    fn queue_counters_wrap_around() {
+        let queue: EdgeQueue<4> = EdgeQueue::new();
+        queue.pushed.store(usize::MAX - 1, Ordering::Relaxed);
+        queue.popped.store(usize::MAX - 1, Ordering::Relaxed);
+        for at in 0..5 {
+            queue.push(ms(at), true); // the fifth doesn't fit
+        }
+        let popped: Vec<_> = std::iter::from_fn(|| queue.pop(ms(10))).map(|(at, _)| at).collect();
+        assert_eq!(popped, vec![ms(0), ms(1), ms(2), ms(3)]);
+    }
+}
@@ -0,0 +1,35 @@
+use super::theme::Gradient;
+
+/*
+Colour maps for magnitudes, 0.0 is silent and 1.0 the loudest. These are the perceptually uniform maps from matplotlib: equal steps in
+magnitude look like equal steps in colour, and they still read as dark to light in grayscale or with a colour vision deficiency. Sampled
+at 9 points, linear in between is close enough on a few hundred leds.
+
+Values are full range RGB, painters scale them down to what is bright enough on the leds.
+*/
+
+// black through purple and orange to pale yellow, dark end is black so silence is off
+pub const INFERNO: Gradient = Gradient::new(&[
+    (0.0, [0.0, 0.0, 4.0]),
+    (0.125, [31.0, 12.0, 72.0]),
+    (0.25, [85.0, 15.0, 109.0]),
+    (0.375, [136.0, 34.0, 106.0]),
+    (0.5, [186.0, 54.0, 85.0]),
+    (0.625, [227.0, 89.0, 51.0]),
+    (0.75, [249.0, 140.0, 10.0]),
+    (0.875, [249.0, 201.0, 50.0]),
+    (1.0, [252.0, 255.0, 164.0]),
+]);
+
+// dark purple through blue and green to yellow
+pub const VIRIDIS: Gradient = Gradient::new(&[
+    (0.0, [68.0, 1.0, 84.0]),
+    (0.125, [71.0, 44.0, 122.0]),
+    (0.25, [59.0, 81.0, 139.0]),
+    (0.375, [44.0, 113.0, 142.0]),
+    (0.5, [33.0, 144.0, 141.0]),
+    (0.625, [39.0, 173.0, 129.0]),
+    (0.75, [92.0, 200.0, 99.0]),
+    (0.875, [170.0, 220.0, 50.0]),
+    (1.0, [253.0, 231.0, 37.0]),
+]);
@@ -0,0 +1,92 @@
+use super::matrix_layout::MatrixLayout;
+use super::tile_map::Tile;
+use super::pixel_format::{ColorOrder, PixelFormat};
+use super::output_stage::PowerBudget;
+use super::transition::{Transition, TransitionKind};
+use super::theme::{self, Theme};
+use super::audio_scope::{ScopeTrigger, TriggerMode};
+use super::audio_meter::Ballistics;
+use super::button::{ButtonBindings, ButtonEvent, ButtonAction};
+use super::gain_knob::GainCurve;
+
+use std::time::Duration;
+
+// Fixed settings of the audiovisual pipeline that don't depend on the board it runs on
+
+// Default geometry: my AliExpress 8x32 matrix placed vertically, serpentine from the bottom left.
+pub const MATRIX_LAYOUT: MatrixLayout = MatrixLayout::new(8, 32);
+
+// The panels on the data line in chain order, with their position on the virtual canvas (see TileMap). The equalizer shows one frequency bin
+// per row of the canvas. Four of the 8x32 panels side by side make a 32x32 wall with 32 bars of 32 leds:
+//   &[Tile::new(MATRIX_LAYOUT, 0, 0), Tile::new(MATRIX_LAYOUT, 8, 0), Tile::new(MATRIX_LAYOUT, 16, 0), Tile::new(MATRIX_LAYOUT, 24, 0)]
+pub const PANELS: &[Tile] = &[Tile::new(MATRIX_LAYOUT, 0, 0)];
+
+// Byte order the leds take, the AliExpress matrix is GRB. An SK6812 RGBW strip would be PixelFormat::new(ColorOrder::GRB).with_white_channel()
+pub const PIXEL_FORMAT: PixelFormat = PixelFormat::new(ColorOrder::GRB);
+
+// Gamma correction and global brightness of the leds (see GammaCorrection in output_stage.rs). The painters and themes pick their colours
+// for this gamma: with 2.2 a step of 20 looks like a step of 20 whether it's dim or bright. 1.0 turns it off.
+pub const OUTPUT_GAMMA: f32 = 2.2;
+pub const OUTPUT_BRIGHTNESS: f32 = 1.0;
+// Frames are scaled down when the leds would draw more than this. A USB port gives 0.5 - 3 A, some of it goes to the ESP32 itself.
+pub const LED_POWER_BUDGET: PowerBudget = PowerBudget::new(1500.0);
+
+// Equalizer peak dots: how long they stay at the highest magnitude, then how fast they fall (leds per second, per second)
+pub const EQ_PEAK_HOLD: Duration = Duration::from_millis(600);
+pub const EQ_PEAK_GRAVITY: f32 = 30.0;
+
+// How far back the spectrogram goes, spread over the width of the canvas, and how many dB below full scale (of the samples after the gain)
+// its colour map starts. Quieter than that is the bottom colour, the gain knob moves the whole range.
+pub const SPECTROGRAM_HISTORY: Duration = Duration::from_secs(4);
+pub const SPECTROGRAM_RANGE_DB: f32 = 60.0;
+
+// Oscilloscope: the time one sweep spans over the canvas, and when it starts (see audio_scope.rs). 20ms is a bit more than one period of the
+// low E of a guitar. Auto always shows a trace, TriggerMode::Normal keeps the last triggered sweep and TriggerMode::Single stops after one.
+pub const SCOPE_TIMEBASE: Duration = Duration::from_millis(20);
+pub const SCOPE_TRIGGER: ScopeTrigger = ScopeTrigger::new(TriggerMode::Auto).with_level(0.05);
+
+// Level meter (see audio_meter.rs): Ballistics::VU or Ballistics::PPM, the dBFS range over the height of the canvas, where the yellow and red
+// zones start, and how long the true peak stays up before it falls
+pub const METER_BALLISTICS: Ballistics = Ballistics::VU;
+pub const METER_RANGE_DB: f32 = 60.0;
+pub const METER_YELLOW_DB: f32 = -18.0;
+pub const METER_RED_DB: f32 = -6.0;
+pub const METER_PEAK_HOLD: Duration = Duration::from_secs(1);
+
+// Input gain of the AudioProcessor, the ADC I2S module gives a low amplitude. The gain knob goes from GAIN_CURVE's min to its max in dB, the
+// middle of the travel is about the x3 the gain always was. Without a knob (simulator) the gain is GAIN_DEFAULT_DB, that x3.
+pub const GAIN_CURVE: GainCurve = GainCurve::new(0.0, 20.0);
+pub const GAIN_DEFAULT_DB: f32 = 9.54;
+
+// The gain knob (see gain_knob.rs): how often it is read, the time constant of the filter on the readings, how far (share of the travel) it
+// has to turn before the gain follows, and how long the gain stays up on the display after the knob stopped turning
+pub const GAIN_KNOB_INTERVAL: Duration = Duration::from_millis(20);
+pub const GAIN_KNOB_SMOOTHING: Duration = Duration::from_millis(80);
+pub const GAIN_KNOB_HYSTERESIS: f32 = 0.01;
+pub const GAIN_OVERLAY_TIME: Duration = Duration::from_millis(1500);
+
+// The modes the button cycles through, by name (see builtin_modes.rs). Leaving one out leaves it off the board, the first is the one at boot.
+pub const MODE_ORDER: &[&str] = &["eq", "tuner", "spectrogram", "scope", "meter"];
+
+// The mode button (see button.rs): how long the contacts may bounce, how long a press is long, the most time between the two presses of a
+// double press (a short press waits this long to be sure it isn't one), and how often Hold repeats after the long press
+pub const BUTTON_DEBOUNCE: Duration = Duration::from_millis(30);
+pub const BUTTON_LONG_PRESS: Duration = Duration::from_millis(600);
+pub const BUTTON_DOUBLE_PRESS_GAP: Duration = Duration::from_millis(300);
+pub const BUTTON_HOLD_REPEAT: Duration = Duration::from_millis(800); // slow enough to let go at the theme you want
+
+// What the gestures do. Holding on after the long press goes through the themes, one every BUTTON_HOLD_REPEAT.
+pub const BUTTON_BINDINGS: ButtonBindings = ButtonBindings::new()
+    .with_binding(ButtonEvent::ShortPress, ButtonAction::NextMode)
+    .with_binding(ButtonEvent::DoublePress, ButtonAction::PreviousMode)
+    .with_binding(ButtonEvent::LongPress, ButtonAction::Freeze)
+    .with_binding(ButtonEvent::Hold, ButtonAction::NextTheme);
+
+// Colours of the painters and splashes at boot, see theme.rs for the others (theme::COLOR_BLIND_SAFE, ...)
+pub const DEFAULT_THEME: &Theme = &theme::CLASSIC;
+
+// How the display goes from one mode to the other, and in and out of the switch splash. TransitionKind::Cut for the old jump.
+pub const MODE_TRANSITION: Transition = Transition::new(TransitionKind::Crossfade, Duration::from_millis(400));
+
+// The I2S input on the board runs at this rate and the filters / tuner were tuned for it
+pub const AUDIO_SAMPLE_RATE: u32 = 48000;
@@ -0,0 +1,55 @@
+use super::graphics::{FineRGB, RGB};
+
+/*
+Temporal dithering: the leds take 8 bits per colour, at the bottom of that range one step is a big jump in brightness (1 -> 2 is double).
+Every pixel keeps the part of its FineRGB that didn't fit in the last RGB it showed and adds it to the next frame. A pixel at 1.25 shows
+1, 1, 1, 2, 1, 1, 1, 2, ... which averages out to 1.25, so dim gradients and fades get the in between steps instead of bands.
+
+The leftovers of the pixels start spread out, otherwise a whole background on the same colour steps up on the same frame and the screen
+flickers as a whole.
+
+Keeps state between frames, so a painter holds on to one for as long as it paints the same canvas.
+*/
+
+pub struct TemporalDither {
+    residuals: Vec<[u16; 3]>, // per pixel and channel, the fraction of a step that still has to be shown
+}
+
+impl TemporalDither {
+    pub fn new(num_pixels: usize) -> Self {
+        let mut residuals = Vec::with_capacity(num_pixels);
+        for i in 0..num_pixels {
+            // a multiplier coprime with 256 spreads the start phase of neighbouring pixels over the whole step
+            let start = ((i * 97) % FineRGB::ONE_STEP as usize) as u16;
+            residuals.push([start, (start + 85) % FineRGB::ONE_STEP, (start + 170) % FineRGB::ONE_STEP]);
+        }
+
+        TemporalDither {
+            residuals
+        }
+    }
+
+    // one frame of pixels to RGB, pixels is in the same order every frame
+    pub fn apply(&mut self, pixels: &[FineRGB]) -> Vec<RGB> {
+        if self.residuals.len() < pixels.len() {
+            *self = TemporalDither::new(pixels.len());
+        }
+
+        pixels
+            .iter()
+            .zip(self.residuals.iter_mut())
+            .map(|(pixel, residual)| {
+                let dither_channel = |value: u16, residual: &mut u16| {
+                    let total = value as u32 + *residual as u32;
+                    *residual = (total % FineRGB::ONE_STEP as u32) as u16;
+                    (total / FineRGB::ONE_STEP as u32).min(255) as u8
+                };
+                RGB {
+                    r: dither_channel(pixel.r, &mut residual[0]),
+                    g: dither_channel(pixel.g, &mut residual[1]),
+                    b: dither_channel(pixel.b, &mut residual[2])
+                }
+            })
+            .collect()
+    }
+}
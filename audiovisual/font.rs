@@ -0,0 +1,367 @@
+use super::framebuffer::Framebuffer;
+use super::graphics::RGB;
+use super::matrix_layout::Rotation;
+
+/*
+Bitmap fonts and text drawing, so labels don't need a hand drawn Vec<Vec<bool>> every time.
+
+Two fonts: 3x5, which fits two characters side by side on the 8 wide portrait matrix, and 4x6 for when there's room. Both have digits,
+letters, # and b for sharp and flat, and the usual punctuation. Lowercase letters are drawn as uppercase, except b which is the flat sign
+(A# / Bb). Characters without a glyph are drawn as ?.
+
+Glyphs are rows top to bottom, one bit per pixel with the leftmost pixel in the highest bit of the glyph width.
+
+Text is positioned by the bottom left corner of its box on the canvas, after rotation. Rotating 90 degrees clockwise runs text from top to
+bottom along the long side of the portrait matrix, 270 from bottom to top. Everything is drawn through Framebuffer::set_pixel so text
+outside of the canvas is clipped.
+*/
+
+pub struct Font {
+    pub glyph_width: usize,
+    pub glyph_height: usize,
+    pub spacing: usize, // empty columns between characters
+    glyphs: fn(char) -> Option<&'static [u8]>,
+}
+
+impl Font {
+    pub fn glyph(&self, c: char) -> &'static [u8] {
+        let c = if c == 'b' || c == '♭' { 'b' } else if c == '♯' { '#' } else { c.to_ascii_uppercase() };
+        (self.glyphs)(c).or_else(|| (self.glyphs)('?')).expect("every font has a ?")
+    }
+
+    // width and height of the text before rotation
+    pub fn text_size(&self, text: &str) -> (usize, usize) {
+        let num_chars = text.chars().count();
+        if num_chars == 0 {
+            return (0, 0)
+        }
+        (num_chars * self.glyph_width + (num_chars - 1) * self.spacing, self.glyph_height)
+    }
+}
+
+pub const FONT_3X5: Font = Font {
+    glyph_width: 3,
+    glyph_height: 5,
+    spacing: 1,
+    glyphs: glyphs_3x5,
+};
+
+pub const FONT_4X6: Font = Font {
+    glyph_width: 4,
+    glyph_height: 6,
+    spacing: 1,
+    glyphs: glyphs_4x6,
+};
+
+pub struct TextStyle {
+    pub font: &'static Font,
+    pub rotation: Rotation,
+}
+
+impl TextStyle {
+    // 3x5, not rotated
+    pub const fn new() -> Self {
+        TextStyle {
+            font: &FONT_3X5,
+            rotation: Rotation::None,
+        }
+    }
+
+    pub const fn with_font(mut self, font: &'static Font) -> Self {
+        self.font = font;
+        self
+    }
+
+    pub const fn with_rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    // width and height of the text on the canvas, after rotation
+    pub fn text_size(&self, text: &str) -> (usize, usize) {
+        let (width, height) = self.font.text_size(text);
+        match self.rotation {
+            Rotation::None | Rotation::Clockwise180 => (width, height),
+            Rotation::Clockwise90 | Rotation::Clockwise270 => (height, width),
+        }
+    }
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle::new()
+    }
+}
+
+// Draws text in the 3x5 font with its bottom left corner at x, y
+pub fn draw_text(framebuffer: &mut Framebuffer, text: &str, x: i32, y: i32, color: &RGB) {
+    draw_text_styled(framebuffer, &TextStyle::new(), text, x, y, color);
+}
+
+pub fn draw_text_styled(framebuffer: &mut Framebuffer, style: &TextStyle, text: &str, x: i32, y: i32, color: &RGB) {
+    let font = style.font;
+    let (text_width, text_height) = font.text_size(text);
+    let (text_width, text_height) = (text_width as i32, text_height as i32);
+
+    for (char_index, c) in text.chars().enumerate() {
+        let char_x = (char_index * (font.glyph_width + font.spacing)) as i32;
+
+        for (row, bits) in font.glyph(c).iter().enumerate() {
+            let text_y = text_height - 1 - row as i32; // glyph rows are top-down, the canvas is bottom-up
+            for col in 0..font.glyph_width {
+                if (bits >> (font.glyph_width - 1 - col)) & 1 == 0 {
+                    continue
+                }
+                let text_x = char_x + col as i32;
+
+                // position in the box of the unrotated text to the box on the canvas
+                let (box_x, box_y) = match style.rotation {
+                    Rotation::None => (text_x, text_y),
+                    Rotation::Clockwise90 => (text_y, text_width - 1 - text_x),
+                    Rotation::Clockwise180 => (text_width - 1 - text_x, text_height - 1 - text_y),
+                    Rotation::Clockwise270 => (text_height - 1 - text_y, text_x),
+                };
+                framebuffer.set_pixel(x + box_x, y + box_y, color);
+            }
+        }
+    }
+}
+
+fn glyphs_3x5(c: char) -> Option<&'static [u8]> {
+    let glyph: &'static [u8] = match c {
+        '0' => &[0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => &[0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => &[0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => &[0b111, 0b001, 0b011, 0b001, 0b111],
+        '4' => &[0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => &[0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => &[0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => &[0b111, 0b001, 0b010, 0b010, 0b010],
+        '8' => &[0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => &[0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => &[0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => &[0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => &[0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => &[0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => &[0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => &[0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => &[0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => &[0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => &[0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => &[0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => &[0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => &[0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => &[0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => &[0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => &[0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => &[0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => &[0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => &[0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => &[0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => &[0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => &[0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => &[0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => &[0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => &[0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => &[0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => &[0b111, 0b001, 0b010, 0b100, 0b111],
+        '#' => &[0b101, 0b111, 0b101, 0b111, 0b101],
+        'b' => &[0b100, 0b100, 0b110, 0b101, 0b110], // flat
+        ' ' => &[0b000, 0b000, 0b000, 0b000, 0b000],
+        '.' => &[0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => &[0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => &[0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => &[0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => &[0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => &[0b000, 0b111, 0b000, 0b111, 0b000],
+        '/' => &[0b001, 0b001, 0b010, 0b100, 0b100],
+        '!' => &[0b010, 0b010, 0b010, 0b000, 0b010],
+        '?' => &[0b110, 0b001, 0b010, 0b000, 0b010],
+        '\'' => &[0b010, 0b010, 0b000, 0b000, 0b000],
+        '(' => &[0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => &[0b100, 0b010, 0b010, 0b010, 0b100],
+        '<' => &[0b001, 0b010, 0b100, 0b010, 0b001],
+        '>' => &[0b100, 0b010, 0b001, 0b010, 0b100],
+        '%' => &[0b101, 0b001, 0b010, 0b100, 0b101],
+        '*' => &[0b000, 0b101, 0b010, 0b101, 0b000],
+        '_' => &[0b000, 0b000, 0b000, 0b000, 0b111],
+        _ => return None
+    };
+    Some(glyph)
+}
+
+fn glyphs_4x6(c: char) -> Option<&'static [u8]> {
+    let glyph: &'static [u8] = match c {
+        '0' => &[0b0110, 0b1001, 0b1011, 0b1101, 0b1001, 0b0110],
+        '1' => &[0b0010, 0b0110, 0b0010, 0b0010, 0b0010, 0b0111],
+        '2' => &[0b0110, 0b1001, 0b0001, 0b0010, 0b0100, 0b1111],
+        '3' => &[0b1110, 0b0001, 0b0110, 0b0001, 0b0001, 0b1110],
+        '4' => &[0b0010, 0b0110, 0b1010, 0b1111, 0b0010, 0b0010],
+        '5' => &[0b1111, 0b1000, 0b1110, 0b0001, 0b0001, 0b1110],
+        '6' => &[0b0110, 0b1000, 0b1110, 0b1001, 0b1001, 0b0110],
+        '7' => &[0b1111, 0b0001, 0b0010, 0b0100, 0b0100, 0b0100],
+        '8' => &[0b0110, 0b1001, 0b0110, 0b1001, 0b1001, 0b0110],
+        '9' => &[0b0110, 0b1001, 0b1001, 0b0111, 0b0001, 0b0110],
+        'A' => &[0b0110, 0b1001, 0b1001, 0b1111, 0b1001, 0b1001],
+        'B' => &[0b1110, 0b1001, 0b1110, 0b1001, 0b1001, 0b1110],
+        'C' => &[0b0111, 0b1000, 0b1000, 0b1000, 0b1000, 0b0111],
+        'D' => &[0b1110, 0b1001, 0b1001, 0b1001, 0b1001, 0b1110],
+        'E' => &[0b1111, 0b1000, 0b1110, 0b1000, 0b1000, 0b1111],
+        'F' => &[0b1111, 0b1000, 0b1110, 0b1000, 0b1000, 0b1000],
+        'G' => &[0b0111, 0b1000, 0b1000, 0b1011, 0b1001, 0b0111],
+        'H' => &[0b1001, 0b1001, 0b1111, 0b1001, 0b1001, 0b1001],
+        'I' => &[0b0111, 0b0010, 0b0010, 0b0010, 0b0010, 0b0111],
+        'J' => &[0b0001, 0b0001, 0b0001, 0b0001, 0b1001, 0b0110],
+        'K' => &[0b1001, 0b1010, 0b1100, 0b1100, 0b1010, 0b1001],
+        'L' => &[0b1000, 0b1000, 0b1000, 0b1000, 0b1000, 0b1111],
+        'M' => &[0b1001, 0b1111, 0b1111, 0b1001, 0b1001, 0b1001],
+        'N' => &[0b1001, 0b1101, 0b1101, 0b1011, 0b1011, 0b1001],
+        'O' => &[0b0110, 0b1001, 0b1001, 0b1001, 0b1001, 0b0110],
+        'P' => &[0b1110, 0b1001, 0b1001, 0b1110, 0b1000, 0b1000],
+        'Q' => &[0b0110, 0b1001, 0b1001, 0b1001, 0b1010, 0b0101],
+        'R' => &[0b1110, 0b1001, 0b1001, 0b1110, 0b1010, 0b1001],
+        'S' => &[0b0111, 0b1000, 0b0110, 0b0001, 0b0001, 0b1110],
+        'T' => &[0b1110, 0b0100, 0b0100, 0b0100, 0b0100, 0b0100],
+        'U' => &[0b1001, 0b1001, 0b1001, 0b1001, 0b1001, 0b0110],
+        'V' => &[0b1001, 0b1001, 0b1001, 0b1001, 0b0110, 0b0110],
+        'W' => &[0b1001, 0b1001, 0b1001, 0b1111, 0b1111, 0b1001],
+        'X' => &[0b1001, 0b1001, 0b0110, 0b0110, 0b1001, 0b1001],
+        'Y' => &[0b1010, 0b1010, 0b1010, 0b0100, 0b0100, 0b0100],
+        'Z' => &[0b1111, 0b0001, 0b0010, 0b0100, 0b1000, 0b1111],
+        '#' => &[0b0101, 0b1111, 0b0101, 0b0101, 0b1111, 0b0101],
+        'b' => &[0b1000, 0b1000, 0b1110, 0b1001, 0b1010, 0b1100], // flat
+        ' ' => &[0b0000, 0b0000, 0b0000, 0b0000, 0b0000, 0b0000],
+        '.' => &[0b0000, 0b0000, 0b0000, 0b0000, 0b0000, 0b0100],
+        ',' => &[0b0000, 0b0000, 0b0000, 0b0000, 0b0100, 0b1000],
+        ':' => &[0b0000, 0b0100, 0b0000, 0b0000, 0b0100, 0b0000],
+        '-' => &[0b0000, 0b0000, 0b1110, 0b0000, 0b0000, 0b0000],
+        '+' => &[0b0000, 0b0100, 0b1110, 0b0100, 0b0000, 0b0000],
+        '=' => &[0b0000, 0b1111, 0b0000, 0b1111, 0b0000, 0b0000],
+        '/' => &[0b0001, 0b0001, 0b0010, 0b0100, 0b1000, 0b1000],
+        '!' => &[0b0100, 0b0100, 0b0100, 0b0100, 0b0000, 0b0100],
+        '?' => &[0b0110, 0b1001, 0b0010, 0b0100, 0b0000, 0b0100],
+        '\'' => &[0b0100, 0b0100, 0b0000, 0b0000, 0b0000, 0b0000],
+        '(' => &[0b0010, 0b0100, 0b0100, 0b0100, 0b0100, 0b0010],
+        ')' => &[0b0100, 0b0010, 0b0010, 0b0010, 0b0010, 0b0100],
+        '<' => &[0b0001, 0b0010, 0b0100, 0b0010, 0b0001, 0b0000],
+        '>' => &[0b1000, 0b0100, 0b0010, 0b0100, 0b1000, 0b0000],
+        '%' => &[0b1001, 0b0001, 0b0010, 0b0100, 0b1000, 0b1001],
+        '*' => &[0b0000, 0b1001, 0b0110, 0b0110, 0b1001, 0b0000],
+        '_' => &[0b0000, 0b0000, 0b0000, 0b0000, 0b0000, 0b1111],
+        _ => return None
+    };
+    Some(glyph)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::matrix_layout::MatrixLayout;
+    use super::super::pixel_format::{ColorOrder, PixelFormat};
+    use super::super::tile_map::TileMap;
+
+    const WHITE: RGB = RGB { r: 255, g: 255, b: 255 };
+
+    // draws the text at x, y on a canvas of width x height, gives back the canvas as rows of # and . from the top
+    fn drawn(style: &TextStyle, text: &str, x: i32, y: i32, width: usize, height: usize) -> Vec<String> {
+        let mut framebuffer = Framebuffer::new(&TileMap::single(MatrixLayout::new(width, height)), PixelFormat::new(ColorOrder::GRB));
+        draw_text_styled(&mut framebuffer, style, text, x, y, &WHITE);
+
+        (0..height as i32).rev()
+            .map(|y| (0..width as i32).map(|x| if framebuffer.pixel(x, y).unwrap().to_rgb() == WHITE { '#' } else { '.' }).collect())
+            .collect()
+    }
+
+    #[test]
+    fn glyphs_3x5() {
+        let style = TextStyle::new();
+        assert_eq!(style.text_size("A7"), (7, 5));
+        assert_eq!(drawn(&style, "A7", 0, 0, 7, 5), [
+            ".#..###",
+            "#.#...#",
+            "###..#.",
+            "#.#..#.",
+            "#.#..#.",
+        ]);
+    }
+
+    #[test]
+    fn glyphs_4x6() {
+        let style = TextStyle::new().with_font(&FONT_4X6);
+        assert_eq!(style.text_size("Bb"), (9, 6));
+        assert_eq!(drawn(&style, "Bb", 0, 0, 9, 6), [
+            "###..#...",
+            "#..#.#...",
+            "###..###.",
+            "#..#.#..#",
+            "#..#.#.#.",
+            "###..##..",
+        ]);
+    }
+
+    #[test]
+    fn rotations() {
+        let style = |rotation| TextStyle::new().with_rotation(rotation);
+
+        assert_eq!(drawn(&style(Rotation::None), "F1", 0, 0, 7, 5), [
+            "###..#.",
+            "#...##.",
+            "##...#.",
+            "#....#.",
+            "#...###",
+        ]);
+
+        // clockwise: the start of the text at the top, its bottom to the left
+        assert_eq!(style(Rotation::Clockwise90).text_size("F1"), (5, 7));
+        assert_eq!(drawn(&style(Rotation::Clockwise90), "F1", 0, 0, 5, 7), [
+            "#####",
+            "..#.#",
+            "....#",
+            ".....",
+            "#..#.",
+            "#####",
+            "#....",
+        ]);
+
+        assert_eq!(drawn(&style(Rotation::Clockwise180), "F1", 0, 0, 7, 5), [
+            "###...#",
+            ".#....#",
+            ".#...##",
+            ".##...#",
+            ".#..###",
+        ]);
+
+        // counterclockwise: the start of the text at the bottom, its bottom to the right
+        assert_eq!(drawn(&style(Rotation::Clockwise270), "F1", 0, 0, 5, 7), [
+            "....#",
+            "#####",
+            ".#..#",
+            ".....",
+            "#....",
+            "#.#..",
+            "#####",
+        ]);
+    }
+
+    #[test]
+    fn position_and_clipping() {
+        // bottom left corner of the box at -1, 1: the first column and the top row fall off
+        assert_eq!(drawn(&TextStyle::new(), "F1", -1, 1, 6, 5), [
+            "...##.",
+            "#...#.",
+            "....#.",
+            "...###",
+            "......",
+        ]);
+    }
+
+    #[test]
+    fn characters_without_glyph() {
+        assert_eq!(FONT_3X5.glyph('a'), FONT_3X5.glyph('A'));
+        assert_ne!(FONT_3X5.glyph('b'), FONT_3X5.glyph('B'));
+        assert_eq!(FONT_4X6.glyph('♭'), FONT_4X6.glyph('b'));
+        assert_eq!(FONT_4X6.glyph('♯'), FONT_4X6.glyph('#'));
+        assert_eq!(FONT_3X5.glyph('€'), FONT_3X5.glyph('?'));
+        assert_eq!(FONT_4X6.text_size(""), (0, 0));
+    }
+}
@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+/*
+Limits how often a frame is pushed to the ledmatrix. The ledmatrix starts glitching if frames come in too fast, and the painters would otherwise
+run at whatever speed the audio processing happens to have.
+
+Timestamps are passed in by the caller so the same throttle works on the board (time since boot) and in the host simulator (time in samples
+played, which can run faster than real time).
+*/
+pub struct FrameThrottle {
+    frame_duration: Duration,
+    last_frame: Option<Duration>,
+}
+
+impl FrameThrottle {
+    pub fn new(frame_duration: Duration) -> Self {
+        FrameThrottle {
+            frame_duration,
+            last_frame: None,
+        }
+    }
+
+    pub fn frame_duration(&self) -> Duration {
+        self.frame_duration
+    }
+
+    // true if a frame may be shown at timestamp now, which then counts as the last shown frame
+    pub fn ready(&mut self, now: Duration) -> bool {
+        let elapsed = match self.last_frame {
+            Some(last_frame) => now.saturating_sub(last_frame),
+            None => self.frame_duration,
+        };
+
+        if elapsed >= self.frame_duration {
+            self.last_frame = Some(now);
+            true
+        }
+        else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(50);
+
+    #[test]
+    fn first_frame_goes_straight_through() {
+        let mut throttle = FrameThrottle::new(FRAME);
+        assert!(throttle.ready(Duration::from_secs(3)));
+        assert!(!throttle.ready(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn one_frame_per_frame_duration() {
+        let mut throttle = FrameThrottle::new(FRAME);
+        let shown: Vec<u64> = (0..=200).filter(|&ms| throttle.ready(Duration::from_millis(ms))).collect();
+        assert_eq!(shown, vec![0, 50, 100, 150, 200]);
+    }
+
+    #[test]
+    fn late_frame_counts_from_when_it_was_shown() {
+        let mut throttle = FrameThrottle::new(FRAME);
+        assert!(throttle.ready(Duration::ZERO));
+        assert!(throttle.ready(Duration::from_millis(130)));
+
+        // no catching up on the time that was missed
+        assert!(!throttle.ready(Duration::from_millis(150)));
+        assert!(!throttle.ready(Duration::from_millis(179)));
+        assert!(throttle.ready(Duration::from_millis(180)));
+    }
+
+    #[test]
+    fn clock_going_back_waits() {
+        let mut throttle = FrameThrottle::new(FRAME);
+        assert!(throttle.ready(Duration::from_millis(500)));
+        assert!(!throttle.ready(Duration::from_millis(100)));
+        assert!(throttle.ready(Duration::from_millis(550)));
+    }
+}
@@ -0,0 +1,249 @@
+use super::graphics::{FineRGB, RGB};
+use super::dither::TemporalDither;
+use super::sprites::StaticSprite;
+use super::pixel_format::PixelFormat;
+use super::tile_map::TileMap;
+
+/*
+One frame of the virtual canvas. Everything that ends up on the leds is drawn through this: the painters, the switch animation. Positions
+are x to the right and y up from the bottom left, anything drawn outside of the canvas is clipped.
+
+The position -> index math lives in VirtualCanvas::index and the canvas -> data line order in the TileMap, this is the only place that uses
+them to draw. to_wire_bytes reorders for the chain and encodes every led with the PixelFormat in one go.
+
+Pixels are kept as FineRGB so painters can draw dim colours and fades in between the 8 bit steps. Sprites and plain colours are RGB. A frame
+goes out rounded to the nearest RGB, or through a TemporalDither to show the in between steps over time.
+*/
+
+// Graphics are rows of pixels, defined top-down the way they look. None pixels are transparent.
+pub type Sprite = Vec<Vec<Option<RGB>>>;
+
+pub struct Framebuffer {
+    tile_map: TileMap,
+    pixel_format: PixelFormat,
+    pixels: Vec<FineRGB>,
+}
+
+impl Framebuffer {
+    // all black
+    pub fn new(tile_map: &TileMap, pixel_format: PixelFormat) -> Self {
+        Framebuffer {
+            tile_map: tile_map.clone(),
+            pixel_format,
+            pixels: vec![FineRGB::default(); tile_map.canvas().num_pixels()],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.tile_map.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.tile_map.height()
+    }
+
+    // index of position x, y in pixels(), for state that painters keep per pixel. None when outside of the canvas.
+    pub fn index(&self, x: i32, y: i32) -> Option<usize> {
+        self.tile_map.canvas().index(x, y)
+    }
+
+    pub fn pixels(&self) -> &[FineRGB] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [FineRGB] {
+        &mut self.pixels
+    }
+
+    pub fn pixel(&self, x: i32, y: i32) -> Option<FineRGB> {
+        self.index(x, y).map(|index| self.pixels[index])
+    }
+
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: &RGB) {
+        self.set_pixel_fine(x, y, FineRGB::from_rgb(color));
+    }
+
+    pub fn set_pixel_fine(&mut self, x: i32, y: i32, color: FineRGB) {
+        if let Some(index) = self.index(x, y) {
+            self.pixels[index] = color;
+        }
+    }
+
+    pub fn fill(&mut self, color: &RGB) {
+        self.fill_fine(FineRGB::from_rgb(color));
+    }
+
+    pub fn fill_fine(&mut self, color: FineRGB) {
+        self.pixels.fill(color);
+    }
+
+    // Draws sprite with its bottom left corner at x, y. Transparent pixels and pixels outside of the canvas are skipped.
+    pub fn blit(&mut self, sprite: &[Vec<Option<RGB>>], x: i32, y: i32) {
+        for (sprite_y, row) in sprite.iter().rev().enumerate() { // start at the bottom of the sprite, sprites are defined top-down
+            for (sprite_x, pixel) in row.iter().enumerate() {
+                if let Some(color) = pixel {
+                    self.set_pixel(x + sprite_x as i32, y + sprite_y as i32, color);
+                }
+            }
+        }
+    }
+
+    // Draws a sprite from assets/ with its bottom left corner at x, y, blended with what's under it by its alpha
+    pub fn blit_sprite(&mut self, sprite: &StaticSprite, x: i32, y: i32) {
+        for sprite_y in 0..sprite.height {
+            for sprite_x in 0..sprite.width {
+                let [r, g, b, alpha] = sprite.pixel(sprite_x, sprite_y);
+                let canvas_y = y + (sprite.height - 1 - sprite_y) as i32; // sprite rows are top-down
+                let (Some(index), true) = (self.index(x + sprite_x as i32, canvas_y), alpha > 0) else {
+                    continue
+                };
+
+                let under = self.pixels[index];
+                let blend = |under: u16, over: u8| {
+                    let over = over as u32 * FineRGB::ONE_STEP as u32;
+                    ((under as u32 * (255 - alpha as u32) + over * alpha as u32) / 255) as u16
+                };
+                self.pixels[index] = FineRGB {
+                    r: blend(under.r, r),
+                    g: blend(under.g, g),
+                    b: blend(under.b, b)
+                };
+            }
+        }
+    }
+
+    // The frame as the leds take it: every led on the chain in data line order, bytes as described by the PixelFormat
+    pub fn to_wire_bytes(&self) -> Vec<u8> {
+        let rgb_pixels: Vec<RGB> = self.pixels.iter().map(|pixel| pixel.to_rgb()).collect();
+        self.encode_chain(&rgb_pixels)
+    }
+
+    // Same, with the fractions shown over time. dither has to be the same one every frame.
+    pub fn to_wire_bytes_dithered(&self, dither: &mut TemporalDither) -> Vec<u8> {
+        let rgb_pixels = dither.apply(&self.pixels);
+        self.encode_chain(&rgb_pixels)
+    }
+
+    fn encode_chain(&self, rgb_pixels: &[RGB]) -> Vec<u8> {
+        let bytes_per_led = self.pixel_format.bytes_per_led();
+        let mut wire_bytes = vec![0u8; self.tile_map.num_leds() * bytes_per_led];
+
+        for (&canvas_index, led_bytes) in self.tile_map.chain_order().iter().zip(wire_bytes.chunks_exact_mut(bytes_per_led)) {
+            self.pixel_format.encode(&rgb_pixels[canvas_index], led_bytes);
+        }
+        wire_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::matrix_layout::MatrixLayout;
+    use super::super::pixel_format::ColorOrder;
+
+    const BLACK: RGB = RGB { r: 0, g: 0, b: 0 };
+
+    fn canvas(width: usize, height: usize) -> Framebuffer {
+        Framebuffer::new(&TileMap::single(MatrixLayout::new(width, height)), PixelFormat::new(ColorOrder::GRB))
+    }
+
+    // 3x3, every pixel its own colour: r is the column, g the row from the top, the middle one transparent
+    fn numbered_sprite() -> Sprite {
+        (0..3u8)
+            .map(|row| (0..3u8).map(|column| ((row, column) != (1, 1)).then_some(RGB { r: column + 1, g: row + 1, b: 9 })).collect())
+            .collect()
+    }
+
+    // what the numbered sprite leaves at canvas x, y when its bottom left corner is at left, bottom
+    fn numbered_at(x: i32, y: i32, left: i32, bottom: i32) -> RGB {
+        let (column, row_from_bottom) = (x - left, y - bottom);
+        if !(0..3).contains(&column) || !(0..3).contains(&row_from_bottom) || (column, row_from_bottom) == (1, 1) {
+            return BLACK
+        }
+        RGB { r: column as u8 + 1, g: (2 - row_from_bottom) as u8 + 1, b: 9 }
+    }
+
+    #[test]
+    fn blit_clips_at_every_edge() {
+        // past the left, bottom, right and top edge and past two at once
+        for (left, bottom) in [(-1, 1), (1, -2), (3, 0), (0, 3), (-2, -2), (2, 2), (-1, 3), (4, -1)] {
+            let mut framebuffer = canvas(5, 5);
+            framebuffer.blit(&numbered_sprite(), left, bottom);
+
+            for y in 0..5 {
+                for x in 0..5 {
+                    let expected = FineRGB::from_rgb(&numbered_at(x, y, left, bottom));
+                    assert_eq!(framebuffer.pixel(x, y), Some(expected), "sprite at {}, {}: pixel {}, {}", left, bottom, x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn blit_off_the_canvas_draws_nothing() {
+        let mut framebuffer = canvas(5, 5);
+        for (left, bottom) in [(-3, 0), (5, 0), (0, -3), (0, 5), (i32::MIN / 2, i32::MAX / 2)] {
+            framebuffer.blit(&numbered_sprite(), left, bottom);
+        }
+        assert!(framebuffer.pixels().iter().all(|pixel| *pixel == FineRGB::default()));
+    }
+
+    #[test]
+    fn blit_sprite_blends_by_alpha() {
+        static PIXELS: [[u8; 4]; 4] = [
+            [0, 0, 100, 255], [0, 0, 100, 51],
+            [0, 0, 100, 0], [0, 0, 100, 51],
+        ];
+        let sprite = StaticSprite { width: 2, height: 2, pixels: &PIXELS };
+
+        let mut framebuffer = canvas(3, 3);
+        framebuffer.fill(&RGB { r: 200, g: 0, b: 0 });
+        framebuffer.blit_sprite(&sprite, -1, 1);
+
+        // only the right column is on the canvas, at x 0: top row 20% blue over the red, bottom row the same
+        let blended = FineRGB::from_rgb(&RGB { r: 160, g: 0, b: 20 });
+        assert_eq!(framebuffer.pixel(0, 2), Some(blended));
+        assert_eq!(framebuffer.pixel(0, 1), Some(blended));
+
+        framebuffer.fill(&RGB { r: 200, g: 0, b: 0 });
+        framebuffer.blit_sprite(&sprite, 2, -1);
+
+        // the top row of the sprite is the bottom row of the canvas: opaque at x 2, then off the right edge
+        assert_eq!(framebuffer.pixel(2, 0), Some(FineRGB::from_rgb(&RGB { r: 0, g: 0, b: 100 })));
+        assert_eq!(framebuffer.pixel(1, 0), Some(FineRGB::from_rgb(&RGB { r: 200, g: 0, b: 0 })));
+
+        // fully transparent leaves what's under it, fractions included
+        let mut framebuffer = canvas(2, 2);
+        framebuffer.fill_fine(FineRGB { r: 300, g: 5, b: 1000 });
+        framebuffer.blit_sprite(&sprite, 0, 0);
+        assert_eq!(framebuffer.pixel(0, 0), Some(FineRGB { r: 300, g: 5, b: 1000 }));
+    }
+
+    #[test]
+    fn wire_bytes_follow_the_serpentine() {
+        // 3x2 serpentine from the bottom left: the bottom row to the right, the top row back to the left
+        let mut framebuffer = canvas(3, 2);
+        for y in 0..2 {
+            for x in 0..3 {
+                framebuffer.set_pixel(x, y, &RGB { r: x as u8, g: y as u8, b: 7 });
+            }
+        }
+
+        // GRB on the wire
+        assert_eq!(framebuffer.to_wire_bytes(), vec![
+            0, 0, 7,  0, 1, 7,  0, 2, 7,
+            1, 2, 7,  1, 1, 7,  1, 0, 7,
+        ]);
+    }
+
+    #[test]
+    fn wire_bytes_round_fine_pixels() {
+        let tile_map = TileMap::single(MatrixLayout::new(2, 1));
+        let mut framebuffer = Framebuffer::new(&tile_map, PixelFormat::new(ColorOrder::RGB).with_white_channel());
+        framebuffer.set_pixel_fine(0, 0, FineRGB { r: 10 * 256 + 127, g: 10 * 256 + 128, b: 20 * 256 });
+        framebuffer.set_pixel(1, 0, &RGB { r: 1, g: 2, b: 3 });
+
+        // 4 bytes a led, the white both have in common on the white byte
+        assert_eq!(framebuffer.to_wire_bytes(), vec![0, 1, 10, 10, 0, 1, 2, 1]);
+    }
+}
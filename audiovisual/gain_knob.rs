@@ -0,0 +1,215 @@
+use std::time::Duration;
+use super::config::{GAIN_CURVE, GAIN_KNOB_INTERVAL, GAIN_KNOB_SMOOTHING, GAIN_KNOB_HYSTERESIS};
+
+/*
+The gain knob, from ADC readings to the gain of the AudioProcessor in dB. Nothing in here touches hardware: the board hands over a closure
+that reads the ADC, the simulator one that returns where its knob is.
+
+ - Sampled every GAIN_KNOB_INTERVAL, reading the ADC every loop is a waste and the knob doesn't move that fast.
+ - Filtered: a one pole lowpass with GAIN_KNOB_SMOOTHING as its time constant takes out the ADC noise. The first reading goes straight
+   through so the gain at boot is where the knob is.
+ - Hysteresis: the knob position only moves when the filtered reading is GAIN_KNOB_HYSTERESIS (share of the travel) away from it, so noise
+   that gets through the filter doesn't keep nudging the gain and popping up the overlay.
+ - The ends of the travel are a bit of dead zone, pots don't quite get to 0 and full scale.
+ - The position goes through the GainCurve: linear in dB, which is how a gain knob should feel.
+*/
+
+// Gain in dB over the travel of the knob
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GainCurve {
+    pub min_db: f32, // knob all the way down
+    pub max_db: f32,
+}
+
+impl GainCurve {
+    pub const fn new(min_db: f32, max_db: f32) -> Self {
+        GainCurve {
+            min_db,
+            max_db,
+        }
+    }
+
+    // position from 0.0 to 1.0
+    pub fn db_at(&self, position: f32) -> f32 {
+        self.min_db + position.clamp(0.0, 1.0) * (self.max_db - self.min_db)
+    }
+
+    // where the knob would be for this gain
+    pub fn position_of(&self, db: f32) -> f32 {
+        if self.max_db == self.min_db {
+            return 0.0
+        }
+        ((db - self.min_db) / (self.max_db - self.min_db)).clamp(0.0, 1.0)
+    }
+}
+
+pub struct GainKnob {
+    full_scale: u16, // reading at the end of the travel
+    curve: GainCurve,
+    interval: Duration,
+    smoothing: Duration,
+    hysteresis: f32,
+    dead_zone: f32, // at both ends, share of the travel
+
+    next_reading: Duration,
+    filtered: Option<f32>, // 0.0 to 1.0 of full_scale, None until the first reading
+    position: f32, // after hysteresis and dead zone
+}
+
+impl GainKnob {
+    // full_scale is what the ADC reads with the knob all the way up. The settings from config.
+    pub fn new(full_scale: u16) -> Self {
+        GainKnob {
+            full_scale: full_scale.max(1),
+            curve: GAIN_CURVE,
+            interval: GAIN_KNOB_INTERVAL,
+            smoothing: GAIN_KNOB_SMOOTHING,
+            hysteresis: GAIN_KNOB_HYSTERESIS,
+            dead_zone: 0.02,
+            next_reading: Duration::ZERO,
+            filtered: None,
+            position: 0.0,
+        }
+    }
+
+    pub fn with_curve(mut self, curve: GainCurve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    pub fn with_hysteresis(mut self, hysteresis: f32) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    pub fn with_smoothing(mut self, smoothing: Duration) -> Self {
+        self.smoothing = smoothing;
+        self
+    }
+
+    // Reads the knob when a reading is due, read returns None when the ADC had an error and the reading is skipped.
+    // Some with the new gain in dB when the knob moved, and on the first reading.
+    pub fn update(&mut self, now: Duration, read: impl FnOnce() -> Option<u16>) -> Option<f32> {
+        if now < self.next_reading {
+            return None
+        }
+        self.next_reading = now + self.interval;
+        let reading = (read()? as f32 / self.full_scale as f32).clamp(0.0, 1.0);
+
+        let filtered = match self.filtered {
+            Some(filtered) => {
+                let coefficient = 1.0 - (-self.interval.as_secs_f32() / self.smoothing.as_secs_f32().max(f32::EPSILON)).exp();
+                filtered + (reading - filtered) * coefficient
+            }
+            None => reading
+        };
+        let first = self.filtered.is_none();
+        self.filtered = Some(filtered);
+
+        let position = ((filtered - self.dead_zone) / (1.0 - 2.0 * self.dead_zone)).clamp(0.0, 1.0);
+        // the ends always count, or the hysteresis could keep the knob from getting there
+        let at_end = (position == 0.0 || position == 1.0) && position != self.position;
+        if first || at_end || (position - self.position).abs() >= self.hysteresis {
+            self.position = position;
+            return Some(self.gain_db())
+        }
+        None
+    }
+
+    // 0.0 to 1.0
+    pub fn position(&self) -> f32 {
+        self.position
+    }
+
+    pub fn gain_db(&self) -> f32 {
+        self.curve.db_at(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FULL_SCALE: u16 = 1000;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn knob() -> GainKnob {
+        GainKnob::new(FULL_SCALE)
+            .with_curve(GainCurve::new(0.0, 20.0))
+            .with_hysteresis(0.01)
+            .with_smoothing(ms(80))
+    }
+
+    // one reading every GAIN_KNOB_INTERVAL from from until until, the gains that came out
+    fn turn(knob: &mut GainKnob, reading: u16, from: u64, until: u64) -> Vec<f32> {
+        let interval = GAIN_KNOB_INTERVAL.as_millis() as u64;
+        (from..until).step_by(interval as usize).filter_map(|now| knob.update(ms(now), || Some(reading))).collect()
+    }
+
+    #[test]
+    fn first_reading_goes_straight_through() {
+        let mut knob = knob();
+        assert_eq!(knob.update(ms(0), || Some(750)), Some(knob.gain_db()));
+        let expected_position = (0.75 - 0.02) / 0.96;
+        assert!((knob.position() - expected_position).abs() < 1e-4, "{}", knob.position());
+    }
+
+    #[test]
+    fn reads_once_per_interval() {
+        let mut knob = knob();
+        let mut reads = 0;
+        for now in 0..GAIN_KNOB_INTERVAL.as_millis() as u64 * 5 {
+            knob.update(ms(now), || { reads += 1; Some(500) });
+        }
+        assert_eq!(reads, 5);
+    }
+
+    #[test]
+    fn failed_reading_is_skipped() {
+        let mut knob = knob();
+        knob.update(ms(0), || Some(500));
+        assert_eq!(knob.update(GAIN_KNOB_INTERVAL, || None), None);
+        assert!((knob.position() - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn noise_within_the_hysteresis_is_ignored() {
+        let mut knob = knob();
+        knob.update(ms(0), || Some(500));
+        let interval = GAIN_KNOB_INTERVAL.as_millis() as u64;
+        let changes = (1..100).filter_map(|i| {
+            let noisy = if i % 2 == 0 { 508 } else { 492 };
+            knob.update(ms(i * interval), || Some(noisy))
+        }).count();
+        assert_eq!(changes, 0);
+        assert!((knob.position() - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn filtered_turn_follows_after_a_while() {
+        let mut knob = knob();
+        knob.update(ms(0), || Some(200));
+        // the filter doesn't jump to the new reading, it gets there over a few time constants
+        let first = knob.update(GAIN_KNOB_INTERVAL, || Some(800)).expect("A big turn should move the gain");
+        assert!(first < GainCurve::new(0.0, 20.0).db_at((0.8 - 0.02) / 0.96) - 5.0, "{}", first);
+        turn(&mut knob, 800, 40, 1000);
+        assert!((knob.position() - (0.8 - 0.02) / 0.96).abs() < 0.011, "{}", knob.position());
+    }
+
+    #[test]
+    fn ends_are_reached() {
+        let mut knob = knob();
+        knob.update(ms(0), || Some(500));
+        // a pot that doesn't quite get to full scale still ends up all the way, the hysteresis doesn't hold it back from the end
+        let gains = turn(&mut knob, 985, 20, 2000);
+        assert_eq!(gains.last(), Some(&20.0));
+        assert_eq!(knob.position(), 1.0);
+
+        let gains = turn(&mut knob, 12, 2000, 4000);
+        assert_eq!(gains.last(), Some(&0.0));
+        assert_eq!(knob.position(), 0.0);
+    }
+}
@@ -1,18 +1,20 @@
 use pitch_detector::core::NoteName;
-use crate::EqTunerModeEnum;
-use crate::LEDS_MAX_X;
-use crate::LEDS_MAX_Y;
-use crate::HwCommander;
-use esp_idf_hal::delay::FreeRtos;
+use std::time::Duration;
+use super::tile_map::TileMap;
+use super::animation::{Easing, KeyframeAnimation, Layer, Track};
+use super::font::TextStyle;
+use super::sprites::ONE_UP;
+use super::theme::Theme;
 
 /*
-Hodgepodge of graphical elements and their rendering. Defined some elements differently than others, depending on their origin (self drawn or lifted from image). 
-This module has conversion functions for the different definitions so the paint function can work with 1 input type.
+Hodgepodge of graphical elements and their rendering. Self drawn elements are defined here, images live in assets/ and come in through
+build.rs as sprites (see sprites.rs). convert_vecvecbool_to_xy_rgb_vec turns bool masks into something Framebuffer::blit takes.
 
 Could make this more fancy with a better interface but that wouldn't help current demands. If 2d rendering demands increase a point will
 be reached where using a full featured public 2D crate is the better choice.
 */
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct RGB {
     pub r: u8,
     pub g: u8,
@@ -41,90 +43,61 @@ impl RGB {
     }
 }
 
-pub fn paint_element(pixelcolors: &mut Vec<u8>, graphic: &Vec<Vec<Option<RGB>>>, x_offset: i32, y_offset: i32) {
-    let graphic_width = graphic[0].len();
-    let graphic_height = graphic.len();
-
-    if x_offset > LEDS_MAX_X as i32 || y_offset > LEDS_MAX_Y as i32 {
-        return //placing the graphic outside of the matrix on the right or top
-    }
-    if (x_offset + graphic_width as i32) < 0 || (y_offset + graphic_height as i32) < 0 {
-        return
+/*
+RGB with 8 more bits of precision: 256 is one step of RGB, so 1.5 is 384. Dim colours and slow fades are painted with this, the leds only
+take 8 bits so TemporalDither alternates between the two nearest RGB values over frames to show the steps in between.
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FineRGB {
+    pub r: u16,
+    pub g: u16,
+    pub b: u16,
+}
+impl FineRGB {
+    pub const ONE_STEP: u16 = 256;
+
+    // values in RGB steps, fractions are kept
+    pub fn from_f32(r: f32, g: f32, b: f32) -> FineRGB {
+        let to_fine = |value: f32| (value.clamp(0.0, 255.0) * Self::ONE_STEP as f32).round() as u16;
+        FineRGB {
+            r: to_fine(r),
+            g: to_fine(g),
+            b: to_fine(b)
+        }
     }
 
-    let room_for_drawing_x = LEDS_MAX_X as i32 - x_offset;
-    let room_for_drawing_y = LEDS_MAX_Y as i32 - y_offset;
-
-    for (i, row) in graphic.iter().rev().enumerate() { //start rendering at bottom of graphic while all current elements are defined top-down
-        for col in 0.. (graphic_width) { //each x
-            let mut serpentine = false;
-            if (i as i32+y_offset) % 2 == 1 {
-                serpentine = true;
-            }
-
-            let mut matrix_x = x_offset + col as i32;
-            let matrix_y = y_offset + i as i32;
-
-            if (col+1) > room_for_drawing_x as usize || (i+1) > room_for_drawing_y as usize {
-                continue
-            }
-            if matrix_x < 0 || matrix_y < 0 {
-                continue
-            }
-
-            if serpentine { // serpentine row
-                matrix_x = LEDS_MAX_X as i32 - 1 - x_offset - col as i32;
-            }
-
-            if let Some(rgb) = &row[col] {
-                let index_in_color_vec = (matrix_x*3 + (matrix_y*LEDS_MAX_X as i32)*3) as usize;
-                pixelcolors[index_in_color_vec] = rgb.g;
-                pixelcolors[index_in_color_vec+1] = rgb.r;
-                pixelcolors[index_in_color_vec+2] = rgb.b;
-            }
+    pub fn from_rgb(rgb: &RGB) -> FineRGB {
+        FineRGB {
+            r: rgb.r as u16 * Self::ONE_STEP,
+            g: rgb.g as u16 * Self::ONE_STEP,
+            b: rgb.b as u16 * Self::ONE_STEP
         }
     }
-}
 
-pub fn paint_element_rgb(pixelcolors: &mut Vec<RGB>, graphic: &Vec<Vec<Option<RGB>>>, x_offset: i32, y_offset: i32) {
-    let graphic_width = graphic[0].len();
-    let graphic_height = graphic.len();
-
-    if x_offset > LEDS_MAX_X as i32 || y_offset > LEDS_MAX_Y as i32 {
-        return //placing the graphic outside of the matrix on the right or top
-    }
-    if (x_offset + graphic_width as i32) < 0 || (y_offset + graphic_height as i32) < 0 {
-        return
+    // nearest RGB, without dithering
+    pub fn to_rgb(&self) -> RGB {
+        let to_step = |value: u16| ((value as u32 + Self::ONE_STEP as u32 / 2) / Self::ONE_STEP as u32).min(255) as u8;
+        RGB {
+            r: to_step(self.r),
+            g: to_step(self.g),
+            b: to_step(self.b)
+        }
     }
 
-    let room_for_drawing_x = LEDS_MAX_X as i32 - x_offset;
-    let room_for_drawing_y = LEDS_MAX_Y as i32 - y_offset;
-
-    for (i, row) in graphic.iter().rev().enumerate() { //start rendering at bottom of graphic while all current elements are defined top-down
-        for col in 0.. (graphic_width) { //each x
-            let mut serpentine = false;
-            if (i as i32+y_offset) % 2 == 1 {
-                serpentine = true;
-            }
-
-            let mut matrix_x = x_offset + col as i32;
-            let matrix_y = y_offset + i as i32;
-
-            if serpentine { // serpentine row
-                matrix_x = LEDS_MAX_X as i32 - 1 - x_offset - col as i32;
-            }
-
-            if (col+1) > room_for_drawing_x as usize || (i+1) > room_for_drawing_y as usize {
-                continue
-            }
-            if matrix_x < 0 || matrix_y < 0 {
-                continue
-            }
+    pub fn subtract(&self, other_color: &FineRGB) -> [i32; 3] {
+        [
+            self.r as i32 - other_color.r as i32,
+            self.g as i32 - other_color.g as i32,
+            self.b as i32 - other_color.b as i32
+        ]
+    }
 
-            if let Some(rgb) = &row[col] {
-                let index_in_color_vec = (matrix_x + (matrix_y*LEDS_MAX_X as i32)) as usize;
-                pixelcolors[index_in_color_vec] = RGB{r:rgb.r, b: rgb.b, g:rgb.g}
-            }
+    pub fn return_new_applied_diff(&self, diff: [i32; 3]) -> FineRGB {
+        let apply = |value: u16, diff: i32| (value as i32 + diff).clamp(0, u16::MAX as i32) as u16;
+        FineRGB {
+            r: apply(self.r, diff[0]),
+            g: apply(self.g, diff[1]),
+            b: apply(self.b, diff[2])
         }
     }
 }
@@ -155,71 +128,10 @@ pub fn convert_vecvecbool_to_xy_rgb_vec(src: Vec<Vec<bool>>, color: RGB) -> Vec<
     dest
 }
 
-// paint_element works on a vec of vecs x*y, convert flattened representation to that
-// alpha_num is the RGB value in the bytes that corresponds to a transparent pixel, optionally
-fn convert_flatvec_to_xy_rgb_vec( src: Vec<u8>, image_width: usize, image_height: usize, alpha: Option<RGB> ) -> Vec<Vec<Option<RGB>>> {
-    let num_colors_width = image_width*3;
-    let mut dest_vec = vec![vec![0;num_colors_width]; image_height];
-
-    for i in 0.. image_height {
-        for j in 0.. num_colors_width {
-            let index_in_src_vec = j + i*num_colors_width;
-            dest_vec[i][j] = src[index_in_src_vec];
-        }
-    }
-
-    let mut result = Vec::new();
-
-    for row in dest_vec {
-        let mut row_tuples = Vec::new();
-        for chunk in row.chunks_exact(3) {
-            if let [r, g, b] = chunk { 
-                if let Some(a) = &alpha {
-                    if *r==a.r && *g==a.g && *b==a.g {
-                        row_tuples.push( None ); // transparent pixel
-                    }
-                    else {
-                        row_tuples.push( Some( RGB {r:*r, g:*g, b:*b}));
-                    }
-                }
-            }
-        }
-        result.push(row_tuples);
-    }
-
-    result
-}
-
 /*
 Some definitions of graphical elements below here.
 */
 
-pub fn vecvec_one_up() -> Vec<Vec<Option<RGB>>> {
-    let one_up = one_up();
-    convert_flatvec_to_xy_rgb_vec(one_up, 16, 16, Some(RGB{r:233u8, g:233u8, b:233u8}))
-}
-
-pub fn one_up() -> Vec<u8> {
-    vec![
-        233,233,233,233,233,233,233,233,233,233,233,233,233,233,233,0,0,0,1,0,1,1,0,1,0,0,0,0,0,0,233,233,233,233,233,233,233,233,233,233,233,233,233,233,233,233,233,233,
-        233,233,233,233,233,233,233,233,233,233,233,233,0,0,0,0,0,0,255,255,255,83,198,24,82,198,25,255,255,255,0,0,0,233,233,233,233,233,233,233,233,233,233,233,233,233,233,233,
-        233,233,233,233,233,233,233,233,233,0,0,0,254,255,254,255,254,255,255,255,254,82,199,24,83,198,24,255,255,255,255,255,255,255,255,255,0,1,0,233,233,233,233,233,233,233,233,233,
-        233,233,233,233,233,233,0,0,0,82,198,24,254,255,255,255,255,255,83,199,24,82,199,24,82,198,24,82,198,24,255,255,254,255,255,255,83,198,24,0,0,0,233,233,233,233,233,233,
-        233,233,233,1,0,0,255,255,255,82,199,24,82,198,25,83,198,25,82,198,25,82,198,25,82,198,25,83,199,24,82,198,24,82,198,25,83,198,25,254,255,255,0,1,1,233,233,233,
-        233,233,233,0,0,0,254,255,255,255,255,255,82,198,25,82,198,24,255,255,255,255,255,255,254,255,254,255,255,255,82,198,25,83,198,24,255,255,255,255,255,254,1,0,0,233,233,233,
-        0,0,1,255,255,255,255,255,255,255,255,255,82,198,24,255,255,255,255,255,255,255,255,255,255,255,254,255,254,255,255,255,254,82,198,24,255,254,255,254,254,254,254,254,255,1,0,0,
-        0,0,1,255,254,255,255,255,254,255,254,255,82,199,24,255,254,255,255,254,255,254,255,255,255,255,254,255,255,255,255,255,255,82,198,25,255,254,255,255,255,255,255,254,255,0,0,0,
-        1,0,1,254,255,255,255,255,255,82,198,24,82,198,24,255,255,255,255,255,255,255,255,255,255,255,255,255,255,255,255,255,255,82,198,24,82,198,24,254,254,255,254,255,255,0,0,0,
-        0,0,1,82,198,24,83,199,24,83,198,24,82,198,24,82,198,24,255,254,255,255,254,254,255,255,254,255,254,255,82,198,24,82,198,24,82,198,24,82,199,24,82,198,25,1,0,0,
-        1,0,1,82,198,25,82,198,24,0,0,0,0,0,0,0,0,0,1,0,0,0,1,1,0,0,1,0,0,0,0,1,0,0,0,0,1,0,0,82,198,24,82,199,24,0,0,0,
-        233,233,233,0,0,0,0,0,0,0,0,0,254,255,255,254,255,254,1,0,0,255,254,255,255,255,255,1,0,1,254,255,255,255,255,255,0,0,0,1,0,0,0,0,0,233,233,233,
-        233,233,233,233,233,233,0,0,0,255,255,255,255,255,255,254,255,255,0,0,0,255,254,255,255,255,255,1,0,0,255,254,255,255,255,254,255,255,255,0,0,0,233,233,233,233,233,233,
-        233,233,233,233,233,233,0,1,0,255,255,254,255,255,255,255,255,254,255,255,254,255,255,255,255,255,254,255,254,255,254,254,254,255,254,254,255,254,255,0,0,0,233,233,233,233,233,233,
-        233,233,233,233,233,233,233,233,233,1,0,0,255,254,255,255,254,255,255,255,255,254,254,255,255,255,255,254,255,255,255,255,255,255,255,254,1,1,0,233,233,233,233,233,233,233,233,233,
-        233,233,233,233,233,233,233,233,233,233,233,233,1,0,0,0,0,0,0,0,0,0,0,0,0,0,1,0,1,0,0,0,0,0,1,0,1,1,0,233,233,233,233,233,233,233,233,233
-    ]
-}
-
 pub fn line( width: usize, color: RGB ) -> Vec<Vec<Option<RGB>>> {
     let mut row = Vec::with_capacity(width);
 
@@ -239,181 +151,61 @@ Graphical representations of the musical notes that can be found by NoteDetectio
 also use the NoteName enum from note_detection crate to have consistency for note names
 */
 
-pub struct GraphicalNote {
-    pub matrix: Vec<Vec<bool>>,
-}
-
-impl GraphicalNote {
-    pub fn new(note: &NoteName) -> Self {
-        let matrix = match note {
-            NoteName::A => vec![
-                vec![false, true, false],
-                vec![true, false, true],
-                vec![true, true, true],
-                vec![true, false,true],
-            ],
-            NoteName::ASharp => vec![
-                vec![false, false, false, false, false, true, false],
-                vec![false, false, false, false, true, true, true],
-                vec![false, true, false, false, false, true, false],
-                vec![true, false, true, false, false, false, false],
-                vec![true, true, true, false, false, false, false],
-                vec![true, false, true, false, false, false, false],
-            ],
-            NoteName::B => vec![
-                vec![true, true, false],
-                vec![true, false, true],
-                vec![true, true, false],
-                vec![true, false, true],
-                vec![true, true, false]
-            ],
-            NoteName::C => vec![
-                vec![false, true, true],
-                vec![true, false, false],
-                vec![true, false, false],
-                vec![false, true, true],
-            ],
-            NoteName::CSharp => vec![
-                vec![false, false, false, false, false, true, false],
-                vec![false, true, true, false, true, true, true],
-                vec![true, false, false, false, false, true, false],
-                vec![true, false, false, false, false, false, false],
-                vec![true, false, false, false, false, false, false],
-                vec![false, true, true, false, false, false, false],
-            ],
-            NoteName::D => vec![
-                vec![true, true, false],
-                vec![true, false, true],
-                vec![true, false, true],
-                vec![true, true, false],
-            ],
-            NoteName::DSharp => vec![
-                vec![false, false, false, false, false, true, false],
-                vec![true, true, false, false, true, true, true],
-                vec![true, false, true, false, false, true, false],
-                vec![true, false, true, false, false, false, false],
-                vec![true, true, false, false, false, false, false],
-                vec![false, false, false, false, false, false, false],
-            ],
-            NoteName::E => vec![
-                vec![true, true, true],
-                vec![true, false, false],
-                vec![true, true, false],
-                vec![true, false, false],
-                vec![true, true, true]
-            ],
-            NoteName::F => vec![
-                vec![true, true, true],
-                vec![true, false, false],
-                vec![true, true, true],
-                vec![true, false, false],
-                vec![true, false, false],
-            ],
-            NoteName::FSharp => vec![
-                vec![false, false, false, false, false, true, false],
-                vec![true, true, true, false, true, true, true],
-                vec![true, false, false, false, false, true, false],
-                vec![true, true, true, false, false, false, false],
-                vec![true, false, false, false, false, false, false],
-                vec![true, false, false, false, false, false, false],
-            ],
-            NoteName::G => vec![
-                vec![false, true, true, false],
-                vec![true, false, false, false],
-                vec![true, false, true, true],
-                vec![true, false, false, true],
-                vec![false, true, true, false]
-            ],
-            NoteName::GSharp => vec![
-                vec![false, false, false, false, false, true, false],
-                vec![false, true, true, false, true, true, true],
-                vec![true, false, false, false, false, true, false],
-                vec![true, false, true, true, false, false, false],
-                vec![true, false, false, true, false, false, false],
-                vec![false, true, true, false, false, false, false],
-            ]
-        };
-
-        GraphicalNote {
-            matrix,
-        }
+// Text for a note name as the fonts draw it
+pub fn note_label(note: &NoteName) -> &'static str {
+    match note {
+        NoteName::A => "A",
+        NoteName::ASharp => "A#",
+        NoteName::B => "B",
+        NoteName::C => "C",
+        NoteName::CSharp => "C#",
+        NoteName::D => "D",
+        NoteName::DSharp => "D#",
+        NoteName::E => "E",
+        NoteName::F => "F",
+        NoteName::FSharp => "F#",
+        NoteName::G => "G",
+        NoteName::GSharp => "G#",
     }
 }
 
-pub fn vecvecbool_eq() -> Vec<Vec<bool>> {
-    vec![
-        vec![true, true, false, true, true, true],
-        vec![true, false, false, true, false, true],
-        vec![true, true, false, true, true, true],
-        vec![true, false, false, false, false, true],
-        vec![true, true, false, false, false, true],
-    ]
-}
-
-pub fn vecvecbool_tuner() -> Vec<Vec<bool>> {
-    vec![
-        vec![true, true, true, false, false, false],
-        vec![false, true, false, false, false, false],
-        vec![false, true, false, true, false, true],
-        vec![false, false, false, true, false, true],
-        vec![false, false, false, true, true, true],
-    ]
-}
-
-pub fn display_switch_animation(mode: &EqTunerModeEnum, hw_commander: &mut HwCommander) {
-    let mut mode_init_screen = Vec::with_capacity(LEDS_MAX_X*LEDS_MAX_Y*3);
-    let fill_color_array =  match mode {
-        EqTunerModeEnum::Equalizer => vec![1,30,1],
-        EqTunerModeEnum::Tuner => vec![1,1,5]
-    };
-
-    for _ in 0..(LEDS_MAX_X*LEDS_MAX_Y) {
-        mode_init_screen.append(&mut fill_color_array.clone());
-    }
-
-    let mode_init_animation = mode_init_screen.clone();
-    let one_up_graph = vecvec_one_up();
-    let eq_graph = convert_vecvecbool_to_xy_rgb_vec(vecvecbool_eq(), RGB{r:0, g:70, b:50});
-    let tun_graph = convert_vecvecbool_to_xy_rgb_vec(vecvecbool_tuner(), RGB{r:0, g:70, b:50});
+// Splash after a mode switch: the 1up mushroom walks over the screen with the new mode's label, then a line with dots. Positions follow
+// the layout, they were picked on the 8x32 matrix. It walks at 10 pixels per second like the old blocking version with its 100ms steps.
+// label is two letters of the 3x5 font, from_left sets where the mushroom walks in.
+pub fn switch_animation(label: &str, fill_color: RGB, from_left: bool, tile_map: &TileMap, theme: &Theme) -> KeyframeAnimation {
+    let width = tile_map.width() as i32;
+    let height = tile_map.height() as i32;
+    let step = Duration::from_millis(100);
 
-    let mut animation_bg = mode_init_animation.clone();
-
-    match mode {
-        EqTunerModeEnum::Equalizer => {
-            let mut switch_element_pos = 14;
-            for _ in 0..30 {
-                switch_element_pos -= 1;
-                paint_element(&mut animation_bg, &one_up_graph, switch_element_pos, 2);
-                paint_element(&mut animation_bg, &eq_graph, 1, 23);
-
-                let color_vec = animation_bg.clone(); // replace with an initial screen after switch
-                hw_commander.display_ledmatrix(color_vec);
-                animation_bg = mode_init_animation.clone();
-                FreeRtos::delay_ms(100) // bask in the glory of the switch screen
-            }
-        },
-        EqTunerModeEnum::Tuner => {
-            let mut switch_element_pos = -16;
-            for _ in 0..28 {
-                switch_element_pos += 1;
-                paint_element(&mut animation_bg, &one_up_graph, switch_element_pos, 2);
-                paint_element(&mut animation_bg, &tun_graph, 1, 23);
-                
-                let color_vec = animation_bg.clone(); // replace with an initial screen after switch
-                hw_commander.display_ledmatrix(color_vec);
-                animation_bg = mode_init_screen.clone();
-                FreeRtos::delay_ms(100) // bask in the glory of the switch screen
-            }
-        }
+    let (walk_from, walk_to, walk_steps) = if from_left {
+        (-15, width + 4, width + 20) // walk in from the left until fully out on the right
     }
-    let line_graph = line(LEDS_MAX_X, RGB{r:255, g:216, b:0});
-    let dot_graph = dot(RGB{r:40, g: 0, b: 0});
-    paint_element(&mut mode_init_screen, &line_graph, 0, 16);
-    paint_element(&mut mode_init_screen, &dot_graph, 2, 20);
-    paint_element(&mut mode_init_screen, &dot_graph, 4, 20);
-    paint_element(&mut mode_init_screen, &dot_graph, 6, 20);
-
-    let init_screen_color_vec = mode_init_screen.clone(); // replace with an initial screen after switch
-    hw_commander.display_ledmatrix(init_screen_color_vec);
-    FreeRtos::delay_ms(200) // bask in the glory of the switch screen
-}
\ No newline at end of file
+    else {
+        (width + 5, -16, width + 22) // walk in from the right until fully out on the left
+    };
+    let walk_end = step * walk_steps as u32;
+    let walk_last_step = walk_end - step;
+
+    let mushroom = Layer::sprite(&ONE_UP)
+        .with_position(Track::new((walk_from as f32, 2.0)).with_keyframe(walk_last_step, (walk_to as f32, 2.0), Easing::Linear));
+    let label = Layer::text(label, TextStyle::new())
+        .with_position(Track::new((1.0, (height - 9) as f32)))
+        .with_color(Track::new(FineRGB::from_rgb(&theme.splash.label)))
+        .with_opacity(Track::new(1.0).with_keyframe(walk_end, 0.0, Easing::Hold));
+
+    // end screen, replace with an initial screen after switch
+    let dots: Vec<Option<RGB>> = (0..width).map(|x| if x >= 2 && x % 2 == 0 { Some(theme.splash.end_dots) } else { None }).collect();
+    let line = Layer::graphic(line(tile_map.width(), theme.splash.end_line))
+        .with_position(Track::new((0.0, (height / 2) as f32)))
+        .with_opacity(Track::new(0.0).with_keyframe(walk_end, 1.0, Easing::Hold));
+    let dots = Layer::graphic(vec![dots])
+        .with_position(Track::new((0.0, (height / 2 + 4) as f32)))
+        .with_opacity(Track::new(0.0).with_keyframe(walk_end, 1.0, Easing::Hold));
+
+    KeyframeAnimation::new(walk_end + Duration::from_millis(200)) // bask in the glory of the switch screen
+        .with_background(Track::new(FineRGB::from_rgb(&fill_color)))
+        .with_layer(mushroom)
+        .with_layer(label)
+        .with_layer(line)
+        .with_layer(dots)
+}
@@ -0,0 +1,216 @@
+use super::tile_map::TileMap;
+use super::pixel_format::PixelFormat;
+
+/*
+A LedSink is where finished frames go. A frame is the wire representation the painters output: the bytes of every led as described by the
+PixelFormat (G, R, B on my matrix), in the order of the data line as described by the TileMap.
+
+On the board the sink is the WS2812 driver (see esp32s3_hw), on a host FrameDumper writes the frames to PNG files or an animated GIF.
+*/
+pub trait LedSink {
+    fn write_frame(&mut self, color_vec: &[u8]);
+}
+
+// Undo the wiring of the panels and the pixel format: returns RGB bytes row by row, top row first, the way image formats want them.
+pub fn wire_to_rgb_image(color_vec: &[u8], tile_map: &TileMap, pixel_format: &PixelFormat) -> Vec<u8> {
+    let width = tile_map.width();
+    let height = tile_map.height();
+    let mut image = vec![0u8; width * height * 3];
+
+    // same walk over the chain as Framebuffer::to_wire_bytes, the other way around
+    for (&canvas_index, led_bytes) in tile_map.chain_order().iter().zip(color_vec.chunks_exact(pixel_format.bytes_per_led())) {
+        let rgb = pixel_format.decode(led_bytes);
+
+        let (matrix_x, matrix_y) = (canvas_index % width, canvas_index / width);
+        let image_y = height - 1 - matrix_y; // row 0 of the matrix is at the bottom
+        let index_in_image = (matrix_x + image_y * width) * 3;
+        image[index_in_image] = rgb.r;
+        image[index_in_image + 1] = rgb.g;
+        image[index_in_image + 2] = rgb.b;
+    }
+
+    image
+}
+
+#[cfg(feature = "host")]
+pub use frame_dumper::{FrameDumper, FrameDumpFormat};
+
+#[cfg(feature = "host")]
+mod frame_dumper {
+    use std::{fs::File, io::BufWriter, path::{Path, PathBuf}, time::Duration};
+
+    use super::{wire_to_rgb_image, LedSink, TileMap, PixelFormat};
+
+    pub enum FrameDumpFormat {
+        PngSequence(PathBuf), // directory, frames get written as frame_00000.png, frame_00001.png, ...
+        Gif(PathBuf), // file
+    }
+
+    /*
+    Host side LedSink. Every led becomes a scale x scale block of pixels so the result is viewable without zooming in.
+    frame_delay is only used for the GIF timing, it does not throttle anything itself.
+    display_gamma undoes the gamma correction of the frames: leds are linear but a monitor does its own gamma, the corrected values would
+    look a lot darker on it than on the leds.
+    */
+    pub struct FrameDumper {
+        tile_map: TileMap,
+        pixel_format: PixelFormat,
+        width: usize,
+        height: usize,
+        scale: usize,
+        frame_delay: Duration,
+        display_lookup_table: [u8; 256], // wire byte to image byte
+        format: FrameDumpFormat,
+        gif_encoder: Option<gif::Encoder<BufWriter<File>>>,
+        frame_counter: usize,
+    }
+
+    impl FrameDumper {
+        pub fn new(format: FrameDumpFormat, tile_map: TileMap, pixel_format: PixelFormat) -> Self {
+            FrameDumper {
+                width: tile_map.width(),
+                height: tile_map.height(),
+                tile_map,
+                pixel_format,
+                scale: 10,
+                frame_delay: Duration::from_millis(50),
+                display_lookup_table: std::array::from_fn(|value| value as u8),
+                format,
+                gif_encoder: None,
+                frame_counter: 0,
+            }
+        }
+
+        pub fn with_scale(mut self, scale: usize) -> Self {
+            self.scale = scale.max(1);
+            self
+        }
+
+        pub fn with_frame_delay(mut self, frame_delay: Duration) -> Self {
+            self.frame_delay = frame_delay;
+            self
+        }
+
+        // the gamma the frames were corrected with, 1.0 writes the wire bytes as they are
+        pub fn with_display_gamma(mut self, gamma: f32) -> Self {
+            self.display_lookup_table = std::array::from_fn(|value| ((value as f32 / 255.0).powf(1.0 / gamma) * 255.0).round() as u8);
+            self
+        }
+
+        pub fn frames_written(&self) -> usize {
+            self.frame_counter
+        }
+
+        fn scale_up(&self, image: Vec<u8>) -> Vec<u8> {
+            let scaled_width = self.width * self.scale;
+            let mut scaled = vec![0u8; scaled_width * self.height * self.scale * 3];
+
+            for y in 0..self.height * self.scale {
+                for x in 0..scaled_width {
+                    let index_in_image = (x / self.scale + (y / self.scale) * self.width) * 3;
+                    let index_in_scaled = (x + y * scaled_width) * 3;
+                    scaled[index_in_scaled..index_in_scaled + 3].copy_from_slice(&image[index_in_image..index_in_image + 3]);
+                }
+            }
+            scaled
+        }
+
+        fn write_png(&self, directory: &Path, pixels: &[u8]) {
+            let path = directory.join(format!("frame_{:05}.png", self.frame_counter));
+            let file = File::create(path).expect("Failed to create frame png");
+
+            let mut encoder = png::Encoder::new(BufWriter::new(file), (self.width * self.scale) as u32, (self.height * self.scale) as u32);
+            encoder.set_color(png::ColorType::Rgb);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().expect("Failed to write png header");
+            writer.write_image_data(pixels).expect("Failed to write png data");
+        }
+
+        fn write_gif(&mut self, path: &Path, pixels: &[u8]) {
+            let width = (self.width * self.scale) as u16;
+            let height = (self.height * self.scale) as u16;
+
+            let encoder = self.gif_encoder.get_or_insert_with(|| {
+                let file = File::create(path).expect("Failed to create gif");
+                let mut encoder = gif::Encoder::new(BufWriter::new(file), width, height, &[]).expect("Failed to write gif header");
+                encoder.set_repeat(gif::Repeat::Infinite).ok();
+                encoder
+            });
+
+            let mut frame = gif::Frame::from_rgb_speed(width, height, pixels, 10);
+            frame.delay = (self.frame_delay.as_millis() / 10) as u16; // gif delays are in centiseconds
+            encoder.write_frame(&frame).expect("Failed to write gif frame");
+        }
+    }
+
+    impl LedSink for FrameDumper {
+        fn write_frame(&mut self, color_vec: &[u8]) {
+            let mut image = wire_to_rgb_image(color_vec, &self.tile_map, &self.pixel_format);
+            for byte in image.iter_mut() {
+                *byte = self.display_lookup_table[*byte as usize];
+            }
+            let pixels = self.scale_up(image);
+
+            match &self.format {
+                FrameDumpFormat::PngSequence(directory) => {
+                    let directory = directory.clone();
+                    self.write_png(&directory, &pixels)
+                },
+                FrameDumpFormat::Gif(path) => {
+                    let path = path.clone();
+                    self.write_gif(&path, &pixels)
+                }
+            }
+            self.frame_counter += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::framebuffer::Framebuffer;
+    use super::super::graphics::RGB;
+    use super::super::matrix_layout::{MatrixLayout, Rotation};
+    use super::super::pixel_format::ColorOrder;
+    use super::super::tile_map::Tile;
+
+    // every pixel its own colour, so a pixel in the wrong place shows
+    fn numbered_frame(tile_map: &TileMap, pixel_format: PixelFormat) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(tile_map, pixel_format);
+        for y in 0..tile_map.height() as i32 {
+            for x in 0..tile_map.width() as i32 {
+                framebuffer.set_pixel(x, y, &RGB { r: x as u8 * 7 + 1, g: y as u8 * 7 + 2, b: 200 });
+            }
+        }
+        framebuffer
+    }
+
+    fn round_trip(tile_map: &TileMap, pixel_format: PixelFormat) {
+        let framebuffer = numbered_frame(tile_map, pixel_format);
+        let image = wire_to_rgb_image(&framebuffer.to_wire_bytes(), tile_map, &pixel_format);
+
+        let (width, height) = (tile_map.width(), tile_map.height());
+        assert_eq!(image.len(), width * height * 3);
+        for (index, pixel) in image.chunks_exact(3).enumerate() {
+            let (x, y) = ((index % width) as i32, (height - 1 - index / width) as i32); // image rows go top-down
+            let rgb = framebuffer.pixel(x, y).unwrap().to_rgb();
+            assert_eq!(pixel, [rgb.r, rgb.g, rgb.b], "{:?} pixel {}, {}", pixel_format, x, y);
+        }
+    }
+
+    #[test]
+    fn serpentine_round_trip() {
+        round_trip(&TileMap::single(MatrixLayout::new(8, 32)), PixelFormat::new(ColorOrder::GRB));
+        round_trip(&TileMap::single(MatrixLayout::new(5, 3).with_rotation(Rotation::Clockwise270)), PixelFormat::new(ColorOrder::BGR));
+    }
+
+    #[test]
+    fn tiles_and_white_channel_round_trip() {
+        let tile_map = TileMap::new(&[
+            Tile::new(MatrixLayout::new(4, 2), 0, 0),
+            Tile::new(MatrixLayout::new(4, 2).with_rotation(Rotation::Clockwise180), 0, 2),
+        ]);
+        round_trip(&tile_map, PixelFormat::new(ColorOrder::GRB).with_white_channel());
+    }
+}
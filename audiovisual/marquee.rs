@@ -0,0 +1,136 @@
+use std::time::Duration;
+
+use super::animation::Animation;
+use super::font::{draw_text_styled, TextStyle};
+use super::framebuffer::Framebuffer;
+use super::graphics::RGB;
+use super::matrix_layout::Rotation;
+
+/*
+Text that scrolls across the canvas, for things that don't fit on 8 leds: a frequency in Hz, a mode name, an IP address at boot.
+
+The text moves against its reading direction, so it comes in with the first character first: unrotated text enters on the right and
+moves left, text rotated 90 degrees (reading down) enters at the bottom and moves up. Where it is only depends on the time since the first
+draw and the speed, so a slow audio frame makes the next step bigger instead of the scroll slower. Draw it with the same clock as the
+FrameThrottle and it moves at the same speed on every frame that is shown.
+
+One-shot marquees scroll through once and report they're done, looping ones start over as soon as the end has left the canvas.
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarqueeRepeat {
+    OneShot,
+    Looping,
+}
+
+pub struct Marquee {
+    text: String,
+    style: TextStyle,
+    color: RGB,
+    background: RGB,
+    pixels_per_second: f32,
+    repeat: MarqueeRepeat,
+    cross_position: Option<i32>, // y for horizontal text, x for vertical. None centers it.
+    start_time: Option<Duration>,
+}
+
+impl Marquee {
+    // one-shot, 3x5 font, 20 pixels per second, centered
+    pub fn new(text: &str) -> Self {
+        Marquee {
+            text: text.to_string(),
+            style: TextStyle::new(),
+            color: RGB{r:0, g:142, b:122},
+            background: RGB{r:21, g:21, b:21},
+            pixels_per_second: 20.0,
+            repeat: MarqueeRepeat::OneShot,
+            cross_position: None,
+            start_time: None,
+        }
+    }
+
+    pub fn with_style(mut self, style: TextStyle) -> Self {
+        self.style = style;
+        self
+    }
+
+    pub fn with_color(mut self, color: RGB) -> Self {
+        self.color = color;
+        self
+    }
+
+    // what to fill the canvas with when the marquee is shown on its own
+    pub fn with_background(mut self, background: RGB) -> Self {
+        self.background = background;
+        self
+    }
+
+    pub fn with_speed(mut self, pixels_per_second: f32) -> Self {
+        self.pixels_per_second = pixels_per_second;
+        self
+    }
+
+    pub fn with_repeat(mut self, repeat: MarqueeRepeat) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    // row (horizontal text) or column (vertical text) of the bottom left of the text
+    pub fn with_cross_position(mut self, cross_position: i32) -> Self {
+        self.cross_position = Some(cross_position);
+        self
+    }
+
+    pub fn background(&self) -> &RGB {
+        &self.background
+    }
+
+    // changes the text without restarting the scroll, for values that update like a frequency
+    pub fn set_text(&mut self, text: &str) {
+        self.text.clear();
+        self.text.push_str(text);
+    }
+
+    pub fn restart(&mut self) {
+        self.start_time = None;
+    }
+
+    // Draws the text where it should be at time now. Returns false once a one-shot marquee has scrolled through, it then draws nothing.
+    pub fn draw(&mut self, framebuffer: &mut Framebuffer, now: Duration) -> bool {
+        let start_time = *self.start_time.get_or_insert(now);
+        let travelled = (now.saturating_sub(start_time).as_secs_f32() * self.pixels_per_second) as i32;
+
+        let (text_width, text_height) = self.style.text_size(&self.text);
+        let (text_width, text_height) = (text_width as i32, text_height as i32);
+        let (canvas_width, canvas_height) = (framebuffer.width() as i32, framebuffer.height() as i32);
+
+        let horizontal = matches!(self.style.rotation, Rotation::None | Rotation::Clockwise180);
+        let (text_length, canvas_length) = if horizontal { (text_width, canvas_width) } else { (text_height, canvas_height) };
+
+        // from the text touching the canvas edge to the end of the text leaving at the other side
+        let scroll_length = text_length + canvas_length;
+        let travelled = match self.repeat {
+            MarqueeRepeat::OneShot if travelled >= scroll_length => return false,
+            MarqueeRepeat::OneShot => travelled,
+            MarqueeRepeat::Looping => travelled % scroll_length.max(1),
+        };
+
+        let along = match self.style.rotation {
+            Rotation::None | Rotation::Clockwise270 => canvas_length - travelled, // moving left or down
+            Rotation::Clockwise90 | Rotation::Clockwise180 => travelled - text_length, // moving up or right
+        };
+        let cross = self.cross_position.unwrap_or(if horizontal { (canvas_height - text_height) / 2 } else { (canvas_width - text_width) / 2 });
+
+        let (x, y) = if horizontal { (along, cross) } else { (cross, along) };
+        draw_text_styled(framebuffer, &self.style, &self.text, x, y, &self.color);
+        true
+    }
+}
+
+// Shown on its own: the background with the text scrolling over it
+impl Animation for Marquee {
+    fn draw(&mut self, framebuffer: &mut Framebuffer, elapsed: Duration) -> bool {
+        framebuffer.fill(&self.background);
+        Marquee::draw(self, framebuffer, elapsed)
+    }
+}
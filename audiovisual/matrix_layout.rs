@@ -0,0 +1,306 @@
+use std::str::FromStr;
+
+/*
+Geometry of a WS2812 panel: its size, how the data line runs through it and how it is mounted. The painters draw on a logical canvas with
+x to the right and y up (row 0 at the bottom), MatrixLayout maps that to the index of the led on the data line.
+
+ - panel_width / panel_height: leds per row and number of rows in the order the panel is wired (rows are the runs of the data line)
+ - wiring: serpentine panels reverse every other row, progressive panels start every row at the same side
+ - start_corner: where the data line enters, seen with the panel in its wiring orientation
+ - rotation: how the panel is mounted, in quarter turns clockwise. A 90 or 270 degree rotation swaps logical width and height.
+
+My 8x32 AliExpress matrix stands vertically: 8 wide, 32 high, serpentine, data in at the bottom left.
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Wiring {
+    Serpentine,
+    Progressive,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Corner {
+    BottomLeft,
+    BottomRight,
+    TopLeft,
+    TopRight,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MatrixLayout {
+    panel_width: usize,
+    panel_height: usize,
+    wiring: Wiring,
+    start_corner: Corner,
+    rotation: Rotation,
+}
+
+impl MatrixLayout {
+    // serpentine, starting bottom left, not rotated
+    pub const fn new(panel_width: usize, panel_height: usize) -> Self {
+        MatrixLayout {
+            panel_width,
+            panel_height,
+            wiring: Wiring::Serpentine,
+            start_corner: Corner::BottomLeft,
+            rotation: Rotation::None,
+        }
+    }
+
+    pub const fn with_wiring(mut self, wiring: Wiring) -> Self {
+        self.wiring = wiring;
+        self
+    }
+
+    pub const fn with_start_corner(mut self, start_corner: Corner) -> Self {
+        self.start_corner = start_corner;
+        self
+    }
+
+    pub const fn with_rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    // width of the logical canvas
+    pub fn width(&self) -> usize {
+        match self.rotation {
+            Rotation::None | Rotation::Clockwise180 => self.panel_width,
+            Rotation::Clockwise90 | Rotation::Clockwise270 => self.panel_height,
+        }
+    }
+
+    // height of the logical canvas
+    pub fn height(&self) -> usize {
+        match self.rotation {
+            Rotation::None | Rotation::Clockwise180 => self.panel_height,
+            Rotation::Clockwise90 | Rotation::Clockwise270 => self.panel_width,
+        }
+    }
+
+    pub fn num_leds(&self) -> usize {
+        self.panel_width * self.panel_height
+    }
+
+    // index on the data line of the led at canvas position x, y. None when outside of the canvas.
+    pub fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x as usize >= self.width() || y as usize >= self.height() {
+            return None
+        }
+        let (x, y) = (x as usize, y as usize);
+        let (pw, ph) = (self.panel_width, self.panel_height);
+
+        // undo the mounting rotation, gives the position on the panel in its wiring orientation
+        let (panel_x, panel_y) = match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Clockwise90 => (pw - 1 - y, x),
+            Rotation::Clockwise180 => (pw - 1 - x, ph - 1 - y),
+            Rotation::Clockwise270 => (y, ph - 1 - x),
+        };
+
+        // count rows and columns from the corner where the data line enters
+        let wire_row = match self.start_corner {
+            Corner::BottomLeft | Corner::BottomRight => panel_y,
+            Corner::TopLeft | Corner::TopRight => ph - 1 - panel_y,
+        };
+        let wire_col = match self.start_corner {
+            Corner::BottomLeft | Corner::TopLeft => panel_x,
+            Corner::BottomRight | Corner::TopRight => pw - 1 - panel_x,
+        };
+
+        let wire_col = if self.wiring == Wiring::Serpentine && wire_row % 2 == 1 {
+            pw - 1 - wire_col // serpentine row runs back
+        }
+        else {
+            wire_col
+        };
+
+        Some(wire_row * pw + wire_col)
+    }
+}
+
+/*
+Layouts can be given as text, for instance on the simulator command line: WIDTHxHEIGHT followed by optional comma separated parts in any order.
+  8x32
+  16x16,progressive,top-left
+  32x8,serpentine,bottom-right,90
+*/
+impl FromStr for MatrixLayout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(',').map(str::trim);
+        let size = parts.next().unwrap_or_default();
+        let (width, height) = size.split_once('x').ok_or_else(|| format!("'{}' is not WIDTHxHEIGHT", size))?;
+        let width: usize = width.parse().map_err(|_| format!("invalid width '{}'", width))?;
+        let height: usize = height.parse().map_err(|_| format!("invalid height '{}'", height))?;
+        if width == 0 || height == 0 {
+            return Err("matrix can't have a zero size".to_string())
+        }
+
+        let mut layout = MatrixLayout::new(width, height);
+        for part in parts {
+            layout = match part {
+                "serpentine" => layout.with_wiring(Wiring::Serpentine),
+                "progressive" => layout.with_wiring(Wiring::Progressive),
+                "bottom-left" => layout.with_start_corner(Corner::BottomLeft),
+                "bottom-right" => layout.with_start_corner(Corner::BottomRight),
+                "top-left" => layout.with_start_corner(Corner::TopLeft),
+                "top-right" => layout.with_start_corner(Corner::TopRight),
+                "0" => layout.with_rotation(Rotation::None),
+                "90" => layout.with_rotation(Rotation::Clockwise90),
+                "180" => layout.with_rotation(Rotation::Clockwise180),
+                "270" => layout.with_rotation(Rotation::Clockwise270),
+                _ => return Err(format!("unknown layout option '{}'", part))
+            };
+        }
+        Ok(layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CORNERS: [Corner; 4] = [Corner::BottomLeft, Corner::BottomRight, Corner::TopLeft, Corner::TopRight];
+    const WIRINGS: [Wiring; 2] = [Wiring::Serpentine, Wiring::Progressive];
+    const ROTATIONS: [Rotation; 4] = [Rotation::None, Rotation::Clockwise90, Rotation::Clockwise180, Rotation::Clockwise270];
+
+    // the index of every led as it looks on the canvas, top row first
+    fn grid(layout: MatrixLayout) -> Vec<Vec<usize>> {
+        (0..layout.height() as i32).rev()
+            .map(|y| (0..layout.width() as i32).map(|x| layout.index(x, y).unwrap()).collect())
+            .collect()
+    }
+
+    // the corner the panel's start corner ends up in after a clockwise quarter turn
+    fn turned(corner: Corner) -> Corner {
+        match corner {
+            Corner::BottomLeft => Corner::TopLeft,
+            Corner::TopLeft => Corner::TopRight,
+            Corner::TopRight => Corner::BottomRight,
+            Corner::BottomRight => Corner::BottomLeft,
+        }
+    }
+
+    fn corner_position(corner: Corner, layout: MatrixLayout) -> (i32, i32) {
+        let (right, top) = (layout.width() as i32 - 1, layout.height() as i32 - 1);
+        match corner {
+            Corner::BottomLeft => (0, 0),
+            Corner::BottomRight => (right, 0),
+            Corner::TopLeft => (0, top),
+            Corner::TopRight => (right, top),
+        }
+    }
+
+    #[test]
+    fn corners_and_wirings() {
+        // 3 wide, 2 rows, not rotated
+        let cases = [
+            (Corner::BottomLeft, Wiring::Serpentine, [[5, 4, 3], [0, 1, 2]]),
+            (Corner::BottomLeft, Wiring::Progressive, [[3, 4, 5], [0, 1, 2]]),
+            (Corner::BottomRight, Wiring::Serpentine, [[3, 4, 5], [2, 1, 0]]),
+            (Corner::BottomRight, Wiring::Progressive, [[5, 4, 3], [2, 1, 0]]),
+            (Corner::TopLeft, Wiring::Serpentine, [[0, 1, 2], [5, 4, 3]]),
+            (Corner::TopLeft, Wiring::Progressive, [[0, 1, 2], [3, 4, 5]]),
+            (Corner::TopRight, Wiring::Serpentine, [[2, 1, 0], [3, 4, 5]]),
+            (Corner::TopRight, Wiring::Progressive, [[2, 1, 0], [5, 4, 3]]),
+        ];
+        for (corner, wiring, expected) in cases {
+            let layout = MatrixLayout::new(3, 2).with_start_corner(corner).with_wiring(wiring);
+            assert_eq!(grid(layout), expected, "{:?} {:?}", corner, wiring);
+        }
+    }
+
+    #[test]
+    fn rotations() {
+        let layout = MatrixLayout::new(3, 2);
+        assert_eq!(grid(layout.with_rotation(Rotation::None)), vec![vec![5, 4, 3], vec![0, 1, 2]]);
+        assert_eq!(grid(layout.with_rotation(Rotation::Clockwise90)), vec![vec![0, 5], vec![1, 4], vec![2, 3]]);
+        assert_eq!(grid(layout.with_rotation(Rotation::Clockwise180)), vec![vec![2, 1, 0], vec![3, 4, 5]]);
+        assert_eq!(grid(layout.with_rotation(Rotation::Clockwise270)), vec![vec![3, 2], vec![4, 1], vec![5, 0]]);
+    }
+
+    // every combination: each led once, the data line starts in the turned corner and goes from neighbour to neighbour
+    #[test]
+    fn every_corner_wiring_and_rotation() {
+        let (panel_width, panel_height) = (4, 3);
+        for corner in CORNERS {
+            for wiring in WIRINGS {
+                for (turns, rotation) in ROTATIONS.into_iter().enumerate() {
+                    let layout = MatrixLayout::new(panel_width, panel_height).with_start_corner(corner).with_wiring(wiring).with_rotation(rotation);
+                    let case = format!("{:?} {:?} {:?}", corner, wiring, rotation);
+
+                    let mut positions = vec![None; layout.num_leds()];
+                    for y in 0..layout.height() as i32 {
+                        for x in 0..layout.width() as i32 {
+                            let index = layout.index(x, y).unwrap();
+                            assert!(positions[index].replace((x, y)).is_none(), "{}: led {} twice", case, index);
+                        }
+                    }
+                    let positions: Vec<(i32, i32)> = positions.into_iter().map(|position| position.unwrap()).collect();
+
+                    let start_corner = (0..turns).fold(corner, |corner, _| turned(corner));
+                    assert_eq!(positions[0], corner_position(start_corner, layout), "{}", case);
+
+                    for (index, step) in positions.windows(2).enumerate() {
+                        let distance = (step[0].0 - step[1].0).abs() + (step[0].1 - step[1].1).abs();
+                        let next_row = (index + 1) % panel_width == 0;
+                        if wiring == Wiring::Serpentine || !next_row {
+                            assert_eq!(distance, 1, "{}: led {} to {}", case, index, index + 1);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn outside_the_canvas() {
+        let layout = MatrixLayout::new(8, 32).with_rotation(Rotation::Clockwise90);
+        assert_eq!((layout.width(), layout.height()), (32, 8));
+        assert_eq!(layout.index(-1, 0), None);
+        assert_eq!(layout.index(0, -1), None);
+        assert_eq!(layout.index(32, 0), None);
+        assert_eq!(layout.index(0, 8), None);
+        assert!(layout.index(31, 7).is_some());
+    }
+
+    #[test]
+    fn parse_layouts() {
+        assert_eq!("8x32".parse(), Ok(MatrixLayout::new(8, 32)));
+        assert_eq!(
+            "16x16, progressive, top-left".parse(),
+            Ok(MatrixLayout::new(16, 16).with_wiring(Wiring::Progressive).with_start_corner(Corner::TopLeft))
+        );
+        assert_eq!(
+            "32x8,90,bottom-right,serpentine".parse(),
+            Ok(MatrixLayout::new(32, 8).with_start_corner(Corner::BottomRight).with_rotation(Rotation::Clockwise90))
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (text, error) in [
+            ("", "'' is not WIDTHxHEIGHT"),
+            ("8", "'8' is not WIDTHxHEIGHT"),
+            ("ax32", "invalid width 'a'"),
+            ("8x", "invalid height ''"),
+            ("8x-1", "invalid height '-1'"),
+            ("0x32", "matrix can't have a zero size"),
+            ("8x0", "matrix can't have a zero size"),
+            ("8x32,diagonal", "unknown layout option 'diagonal'"),
+            ("8x32,45", "unknown layout option '45'"),
+        ] {
+            assert_eq!(text.parse::<MatrixLayout>(), Err(error.to_string()), "{:?}", text);
+        }
+    }
+}
@@ -1,8 +1,17 @@
 
-use pitch_detector::note::NoteDetectionResult;
 use fundsp::hacker32::*;
 
-use crate::{EqTunerModeEnum, LEDS_MAX_Y};
+use std::time::Duration;
+
+use tile_map::TileMap;
+use pixel_format::PixelFormat;
+use framebuffer::Framebuffer;
+use dither::TemporalDither;
+use output_stage::GammaCorrection;
+use animation::{Animation, AnimationPlayer};
+use transition::{Compositor, Transition};
+use mode::Mode;
+use theme::Theme;
 
 // mode Equalizer processing
 mod audio_fft_binner;
@@ -12,20 +21,103 @@ mod visual_bins_to_animation;
 mod audio_tuner;
 mod visual_tuner_painter;
 
+// mode Spectrogram processing, uses the equalizer's FFT binner
+mod visual_spectrogram_painter;
+
+// mode Oscilloscope processing
+mod visual_scope_painter;
+
+// mode Meter processing
+mod visual_meter_painter;
+
+// the gain on top of the frame while the knob turns
+mod visual_gain_overlay;
+
+// a mode is an analyzer, a painter and a splash, the registry holds them in the order of the button
+pub mod mode;
+
+// equalizer, tuner, spectrogram, scope and meter as modes
+pub mod builtin_modes;
+
+// debounce and gestures of the mode button, what they do. Hardware free, the board's interrupt only queues edges
+pub mod button;
+
+// gain knob readings to the input gain in dB, filtered and with hysteresis. Hardware free, the board reads the ADC
+pub mod gain_knob;
+
+// oscilloscope trigger and sweeps of the filtered input
+pub mod audio_scope;
+
+// absolute levels: RMS or peak with VU / PPM ballistics, true peak, short-term loudness
+pub mod audio_meter;
+
 // visual elements and rendering
 pub mod graphics;
 
-// The audioprocessor fills either the buffer for equalizer or the buffer for tuner depending on mode
-pub struct AudioProcessor {  
-    sample_rate: u32,
-    frequalizer: audio_fft_binner::AudioFrequalizer,
-    tuner: audio_tuner::GiTuner,
+// hardware independent settings: matrix size, sample rate
+pub mod config;
+
+// geometry of the led panel: size, wiring, rotation
+pub mod matrix_layout;
+
+// several panels on one data line as one virtual canvas
+pub mod tile_map;
+
+// what gets drawn on: one frame of the canvas, to wire bytes for the leds
+pub mod framebuffer;
+
+// showing colours in between the 8 bit steps over time
+pub mod dither;
+
+// 3x5 and 4x6 bitmap fonts, draw_text
+pub mod font;
 
+// named palettes and gradients for the painters and splashes, several built-in themes
+pub mod theme;
+
+// viridis and inferno, for magnitudes
+pub mod colormap;
+
+// scrolling text
+pub mod marquee;
+
+// time driven splashes with keyframed layers, the main loop keeps reading audio while they play
+pub mod animation;
+
+// crossfade, wipe and slide from what was shown to what comes next
+pub mod transition;
+
+// images from assets/, converted by build.rs
+pub mod sprites;
+
+// gamma and brightness before the dither, power budget on the finished frames
+pub mod output_stage;
+
+// colour byte order of the leds, RGBW
+pub mod pixel_format;
+
+// where the samples come from: I2S on the board, WAV files on a host
+pub mod audio_source;
+
+// deterministic test signals (sines, sweeps, noise, plucked strings) to feed the pipeline without an instrument
+pub mod signals;
+
+// accuracy numbers for the tuner over the full guitar and bass range
+pub mod tuner_bench;
+
+// where the frames go: the WS2812 ledmatrix on the board, image files on a host
+pub mod led_sink;
+pub mod frame_throttle;
+
+// The audioprocessor gets the samples ready for the modes: gain and filters, then the current mode analyzes them
+pub struct AudioProcessor {  
     lowpass_filter: An<FixedSvf<f32, LowpassMode<f32>>>,
     highpass_filter: An<FixedSvf<f32, HighpassMode<f32>>>,
+    gain_db: f32,
+    gain: f32, // linear, from gain_db
 }
 impl AudioProcessor {
-    pub fn new(sample_rate: u32) -> Self {
+    pub fn new() -> Self {
         // fundsp filters as a pre-processor. Removes a lot of audio glitching when there isn't a lot coming in.
         // lowpass and highpass need to be persisted because fundsp filters work by mainining internal state
 
@@ -39,42 +131,37 @@ impl AudioProcessor {
         AudioProcessor {
             lowpass_filter,
             highpass_filter,
-            sample_rate,
-            frequalizer: audio_fft_binner::AudioFrequalizer::new(LEDS_MAX_Y, sample_rate),
-            tuner: audio_tuner::GiTuner::new()
+            gain_db: config::GAIN_DEFAULT_DB,
+            gain: db_to_gain(config::GAIN_DEFAULT_DB),
         }
     }
 
-    pub fn process(&mut self, audio_values: Vec<f32>, mode: &EqTunerModeEnum) {
-        let lowhighpass_audio_vals = self.apply_lowhighpass(audio_values);
-        match mode {
-            EqTunerModeEnum::Equalizer => {
-                self.frequalizer.frequalize(lowhighpass_audio_vals)
-            },
-            EqTunerModeEnum::Tuner => {
-                self.tuner.tune(lowhighpass_audio_vals, self.sample_rate)
-            }
-        }
+    pub fn with_gain_db(mut self, gain_db: f32) -> Self {
+        self.set_gain_db(gain_db);
+        self
     }
-    
-    pub fn output(&mut self, mode: &EqTunerModeEnum) -> AudioProcessorOutputEnum {
-        match mode {
-            EqTunerModeEnum::Equalizer => {
-                AudioProcessorOutputEnum::EqBins(&self.frequalizer.eq_bins)
-            },
-            EqTunerModeEnum::Tuner => {
-                // note_info is optional because the pitch detector is strict
-                AudioProcessorOutputEnum::NoteInfo(&self.tuner.note_info)
-            }
-        }
+
+    // Boost signal because of low amplitude on ADC I2S module, the gain knob sets this
+    pub fn set_gain_db(&mut self, gain_db: f32) {
+        self.gain_db = gain_db;
+        self.gain = db_to_gain(gain_db);
     }
 
-    fn apply_lowhighpass(&mut self, mut samples: Vec<f32>) -> Vec<f32> {
+    pub fn gain_db(&self) -> f32 {
+        self.gain_db
+    }
+
+    pub fn process(&mut self, audio_values: Vec<f32>, mode: &mut dyn Mode) {
+        let lowhighpass_audio_vals = self.apply_lowhighpass(&audio_values);
+        mode.set_input_gain(self.gain);
+        mode.analyze(&lowhighpass_audio_vals, &audio_values);
+    }
+
+    // gain and filters, the samples the modes get. Only whole blocks of 64 come out, the tuner bench feeds it whole analysis windows.
+    pub fn apply_lowhighpass(&mut self, samples: &[f32]) -> Vec<f32> {
         let max_dsp_buffer = 64; // max size of the processing used by fundsp
         let max_dsp_buffer_idx = 63; // for use in index calculations
 
-        let gain = 3.0f32; // Boost signal because of low amplitude on ADC I2S module. 
-
         let mut dsp_buff = BufferVec::new(1);
         let mut dsp_lowpassed_values = BufferVec::new(1);
         let mut dsp_highpassed_values = BufferVec::new(1);
@@ -82,8 +169,8 @@ impl AudioProcessor {
         let mut output_vec = vec![0f32; samples.len()];
         let mut dspbuffer_counter = 0;
 
-        for (i, sample) in samples.iter_mut().enumerate() {
-            let gained_sample = *sample * gain;
+        for (i, sample) in samples.iter().enumerate() {
+            let gained_sample = *sample * self.gain;
             dsp_buff.buffer_mut().set_f32(0, dspbuffer_counter, gained_sample);
 
             if dspbuffer_counter == max_dsp_buffer_idx {
@@ -103,37 +190,122 @@ impl AudioProcessor {
     }
 }
 
-pub enum AudioProcessorOutputEnum<'a> {
-    EqBins(&'a Vec<f32>),
-    NoteInfo(&'a Option<NoteDetectionResult>)
+fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+impl Default for AudioProcessor {
+    fn default() -> Self {
+        AudioProcessor::new()
+    }
+}
+
+/*
+Everything that ends up on the leds comes out of here: the frame of the current mode, or an animation (switch splash, marquee) over it
+while one plays. When what is shown changes, from one mode to the other or from a mode to an animation and back, the Compositor blends
+from the last frame that was shown to the new one so nothing jumps.
+
+The modes paint on Framebuffers they keep. The composed frame gets the gamma correction and brightness (see output_stage.rs) and then
+goes out through one TemporalDither for the whole canvas. The dither is the last step that changes values, after it they are the 8 bit
+wire bytes and the fractions it spreads over frames would be lost in any further correction.
+*/
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FrameSource {
+    Mode(&'static str), // by name
+    Animation,
 }
 
 pub struct VisualProcessor {
-    eq_painter: visual_bins_to_animation::Painter,
-    tuner_painter: visual_tuner_painter::Painter
+    animation: Option<AnimationPlayer>,
+    animation_frame: Framebuffer,
+    compositor: Compositor,
+    shown: Option<FrameSource>, // what the last frame came from
+    gamma_correction: GammaCorrection,
+    corrected: Framebuffer, // the composed frame after the gamma correction
+    dither: TemporalDither,
+    frozen: bool, // the mode isn't painted, its last frame stays up
+    gain_overlay: visual_gain_overlay::Overlay,
 }
 impl VisualProcessor {
-    pub fn new() -> Self {
+    pub fn new(tile_map: TileMap, pixel_format: PixelFormat) -> Self {
         VisualProcessor {
-            eq_painter: visual_bins_to_animation::Painter::new(),
-            tuner_painter: visual_tuner_painter::Painter{}
+            animation: None,
+            animation_frame: Framebuffer::new(&tile_map, pixel_format),
+            compositor: Compositor::new(config::MODE_TRANSITION, &tile_map, pixel_format),
+            shown: None,
+            gamma_correction: GammaCorrection::new(config::OUTPUT_GAMMA).with_brightness(config::OUTPUT_BRIGHTNESS),
+            corrected: Framebuffer::new(&tile_map, pixel_format),
+            dither: TemporalDither::new(tile_map.canvas().num_pixels()),
+            frozen: false,
+            gain_overlay: visual_gain_overlay::Overlay::new(&tile_map, pixel_format, config::DEFAULT_THEME),
         }
     }
 
-    pub fn process_and_output(&mut self, input: AudioProcessorOutputEnum) -> Option<Vec<u8>> {
-        match input {
-            AudioProcessorOutputEnum::EqBins(bins) => {
-                Some(self.eq_painter.paint(bins))
-            }
-            AudioProcessorOutputEnum::NoteInfo(note_info_option) => {
-                // note_info is optional because the pitch detector is strict
-                if let Some(note_info) = note_info_option {
-                    Some(self.tuner_painter.paint(note_info))
-                }
-                else {
-                    None
-                }
+    pub fn with_transition(mut self, transition: Transition) -> Self {
+        self.compositor.set_transition(transition);
+        self
+    }
+
+    // Gamma and brightness of the leds, OUTPUT_GAMMA and OUTPUT_BRIGHTNESS from config by default. A gamma of 1.0 is no correction.
+    pub fn with_gamma_correction(mut self, gamma_correction: GammaCorrection) -> Self {
+        self.gamma_correction = gamma_correction;
+        self
+    }
+
+    // can change while running
+    pub fn set_brightness(&mut self, brightness: f32) {
+        self.gamma_correction.set_brightness(brightness);
+    }
+
+    // Shown instead of the modes until it is done, replaces the animation that is playing if any
+    pub fn play_animation(&mut self, animation: impl Animation + 'static) {
+        self.animation = Some(AnimationPlayer::new(animation));
+    }
+
+    // Freezes the frame of the mode, the mode keeps analyzing behind it. Animations still play over a frozen mode.
+    pub fn set_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+    }
+
+    pub fn frozen(&self) -> bool {
+        self.frozen
+    }
+
+    // Puts the gain over the frame for a moment, every turn of the knob. position is where the knob is, 0.0 to 1.0.
+    pub fn show_gain(&mut self, gain_db: f32, position: f32, theme: &'static Theme, now: Duration) {
+        self.gain_overlay.show(gain_db, position, theme, now);
+    }
+
+    // now is on the same clock as the FrameThrottle, animations and transitions move on it. None when there is nothing new to show.
+    pub fn process_and_output(&mut self, mode: &mut dyn Mode, now: Duration) -> Option<Vec<u8>> {
+        let painted = !self.frozen && mode.paint(now);
+
+        if let Some(animation) = &mut self.animation {
+            if !animation.draw(&mut self.animation_frame, now) {
+                self.animation = None;
             }
         }
+        let source = if self.animation.is_some() { FrameSource::Animation } else { FrameSource::Mode(mode.name()) };
+
+        if self.shown.is_some_and(|shown| shown != source) {
+            self.compositor.start(now);
+        }
+        let changed = self.shown != Some(source);
+        self.shown = Some(source);
+        let overlay_changed = self.gain_overlay.needs_frame(now);
+        if !painted && !changed && source != FrameSource::Animation && !self.compositor.running() && !overlay_changed {
+            return None
+        }
+
+        let incoming = match source {
+            FrameSource::Mode(_) => mode.frame(),
+            FrameSource::Animation => &self.animation_frame,
+        };
+        // led matrix needs a vec of bytes in its own colour order, in the order of the leds on the chain
+        let composed = self.compositor.compose(incoming, now);
+        let frame = if self.gain_overlay.visible() { self.gain_overlay.paint(composed) } else { composed };
+        self.corrected.pixels_mut().copy_from_slice(frame.pixels());
+        self.gamma_correction.apply(self.corrected.pixels_mut());
+        Some(self.corrected.to_wire_bytes_dithered(&mut self.dither))
     }
 }
\ No newline at end of file
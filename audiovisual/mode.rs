@@ -0,0 +1,147 @@
+use std::time::Duration;
+use super::tile_map::TileMap;
+use super::framebuffer::Framebuffer;
+use super::animation::KeyframeAnimation;
+use super::theme::Theme;
+use super::config;
+
+/*
+A Mode is one of the things the display can be: the equalizer, the tuner, ... It bundles the analyzer that takes the filtered samples, the
+painter that turns what the analyzer found into a frame, and the splash that is shown when the button switches to it.
+
+The ModeRegistry holds the modes in the order the button cycles through them and the theme they paint in. The AudioProcessor feeds the
+current mode and the VisualProcessor shows its frame, neither knows which modes there are. Adding a mode is implementing Mode in a module
+of its own and putting it in the registry with with_mode, see builtin_modes.rs for the ones that come with the frequatuner and
+builtin_modes::registry for a registry with all of them.
+
+Only the current mode analyzes, the others keep the state they had when the button switched away from them.
+*/
+
+pub trait Mode {
+    // short lowercase name for settings and the command line, unique in a registry
+    fn name(&self) -> &'static str;
+
+    // samples after the gain and the filters, and the raw ADC samples they came from (same length, 1.0 is ADC full scale)
+    fn analyze(&mut self, samples: &[f32], raw_samples: &[f32]);
+
+    // the linear gain the samples of the next analyze got, so the ADC full scale is at this sample value. The gain knob changes it any time.
+    fn set_input_gain(&mut self, _gain: f32) {}
+
+    // paints what the analyzer found since the last paint, false when there was nothing new and frame() stayed the same
+    fn paint(&mut self, now: Duration) -> bool;
+
+    // the last painted frame
+    fn frame(&self) -> &Framebuffer;
+
+    // takes effect from the next paint
+    fn set_theme(&mut self, theme: &'static Theme);
+
+    // shown while the button switches to this mode, this mode already analyzes and paints behind it
+    fn splash(&self, tile_map: &TileMap, theme: &Theme) -> KeyframeAnimation;
+
+    // the button switched to this mode, for resetting latches and such
+    fn enter(&mut self) {}
+}
+
+pub struct ModeRegistry {
+    modes: Vec<Box<dyn Mode>>, // in the order the button goes through them
+    current: usize,
+    theme: &'static Theme,
+}
+
+impl ModeRegistry {
+    // empty, needs at least one mode before it is used
+    pub fn new() -> Self {
+        ModeRegistry {
+            modes: vec![],
+            current: 0,
+            theme: config::DEFAULT_THEME,
+        }
+    }
+
+    // added at the end, or in the place of the mode with the same name
+    pub fn with_mode(mut self, mut mode: impl Mode + 'static) -> Self {
+        mode.set_theme(self.theme);
+        match self.modes.iter().position(|existing| existing.name() == mode.name()) {
+            Some(index) => self.modes[index] = Box::new(mode),
+            None => self.modes.push(Box::new(mode)),
+        }
+        self
+    }
+
+    // only the modes with these names in this order, names that aren't in the registry are skipped
+    pub fn with_order(mut self, order: &[&str]) -> Self {
+        let mut unordered: Vec<Option<Box<dyn Mode>>> = self.modes.drain(..).map(Some).collect();
+        for name in order {
+            if let Some(mode) = unordered.iter_mut().find(|mode| mode.as_ref().is_some_and(|mode| mode.name() == *name)) {
+                self.modes.push(mode.take().unwrap());
+            }
+        }
+        self.current = 0;
+        self
+    }
+
+    pub fn with_theme(mut self, theme: &'static Theme) -> Self {
+        self.set_theme(theme);
+        self
+    }
+
+    // all modes switch, the ones that aren't shown paint in the new colours when they are next
+    pub fn set_theme(&mut self, theme: &'static Theme) {
+        self.theme = theme;
+        for mode in &mut self.modes {
+            mode.set_theme(theme);
+        }
+    }
+
+    // for splashes that should match the modes
+    pub fn theme(&self) -> &'static Theme {
+        self.theme
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.modes.iter().map(|mode| mode.name())
+    }
+
+    pub fn current(&self) -> &dyn Mode {
+        self.modes.get(self.current).expect("No modes in the registry").as_ref()
+    }
+
+    pub fn current_mut(&mut self) -> &mut dyn Mode {
+        self.modes.get_mut(self.current).expect("No modes in the registry").as_mut()
+    }
+
+    // the next mode in the order, after the last comes the first
+    pub fn next(&mut self) {
+        self.current = (self.current + 1) % self.modes.len().max(1);
+        self.current_mut().enter();
+    }
+
+    // the one before, before the first comes the last
+    pub fn previous(&mut self) {
+        let len = self.modes.len().max(1);
+        self.current = (self.current + len - 1) % len;
+        self.current_mut().enter();
+    }
+
+    // false when there is no mode with that name
+    pub fn switch_to(&mut self, name: &str) -> bool {
+        let Some(index) = self.modes.iter().position(|mode| mode.name() == name) else {
+            return false
+        };
+        self.current = index;
+        self.current_mut().enter();
+        true
+    }
+
+    // the splash of the current mode in the current theme
+    pub fn splash(&self, tile_map: &TileMap) -> KeyframeAnimation {
+        self.current().splash(tile_map, self.theme)
+    }
+}
+
+impl Default for ModeRegistry {
+    fn default() -> Self {
+        ModeRegistry::new()
+    }
+}
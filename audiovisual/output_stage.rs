@@ -0,0 +1,211 @@
+use super::pixel_format::PixelFormat;
+use super::graphics::FineRGB;
+
+/*
+The last steps before a frame goes out to the leds.
+
+GammaCorrection works on the FineRGB of the composed frame, before the VisualProcessor's TemporalDither:
+ - gamma: leds are linear, eyes are not. A gamma of 2.2 - 2.8 makes steps between intensities look even, the painters and themes pick their
+   colours on that even scale. Dim colours come out as fractions of a step, which is why this has to happen before the dither: on the 8 bit
+   wire bytes everything up to about 5 would round to off.
+ - brightness: global scalar between 0 and 1 on top of what the painters chose
+Gamma and brightness are baked into one lookup table over the 256 steps, the fraction between two steps is interpolated.
+
+OutputStage works on the wire bytes so it doesn't care about colour order or chaining:
+ - power budget: estimates the current a frame draws and scales the whole frame down when it would go over the budget. A WS2812 draws
+   about 20 mA per colour channel at full intensity plus about 1 mA just for being on, a full white frame on 256 leds is over 15 A while a
+   USB port gives 0.5 - 3 A. Browning out the board is no fun. Frames within the budget pass unchanged, so the dither is the last thing that
+   changes a frame unless the leds would draw too much.
+
+The estimate is linear in the byte values, real leds are a bit under that at high intensities, so it errs on the safe side.
+*/
+
+pub struct GammaCorrection {
+    gamma: f32,
+    brightness: f32,
+    lookup_table: [u32; 257], // corrected FineRGB value per step, the last one again so the fraction above 255 has something to go to
+}
+
+impl GammaCorrection {
+    // full brightness
+    pub fn new(gamma: f32) -> Self {
+        let mut gamma_correction = GammaCorrection {
+            gamma,
+            brightness: 1.0,
+            lookup_table: [0; 257],
+        };
+        gamma_correction.build_lookup_table();
+        gamma_correction
+    }
+
+    pub fn with_brightness(mut self, brightness: f32) -> Self {
+        self.set_brightness(brightness);
+        self
+    }
+
+    // can change while running, the lookup table is rebuilt
+    pub fn set_brightness(&mut self, brightness: f32) {
+        self.brightness = brightness.clamp(0.0, 1.0);
+        self.build_lookup_table();
+    }
+
+    pub fn brightness(&self) -> f32 {
+        self.brightness
+    }
+
+    pub fn gamma(&self) -> f32 {
+        self.gamma
+    }
+
+    fn build_lookup_table(&mut self) {
+        for (step, entry) in self.lookup_table.iter_mut().enumerate() {
+            let corrected = (step.min(255) as f32 / 255.0).powf(self.gamma) * self.brightness;
+            *entry = (corrected * 255.0 * FineRGB::ONE_STEP as f32).round() as u32;
+        }
+    }
+
+    // in place, what is left between two steps stays for the dither
+    pub fn apply(&self, pixels: &mut [FineRGB]) {
+        let one_step = FineRGB::ONE_STEP as u32;
+        let correct = |value: u16| {
+            let step = (value as u32 / one_step).min(255) as usize;
+            let fraction = value as u32 % one_step;
+            let (low, high) = (self.lookup_table[step], self.lookup_table[step + 1]);
+            ((low * (one_step - fraction) + high * fraction) / one_step) as u16
+        };
+        for pixel in pixels.iter_mut() {
+            *pixel = FineRGB {
+                r: correct(pixel.r),
+                g: correct(pixel.g),
+                b: correct(pixel.b)
+            };
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PowerBudget {
+    pub milliamps: f32,
+    pub milliamps_per_channel: f32, // one colour channel at 255
+    pub idle_milliamps_per_led: f32, // drawn even when the led is off
+}
+
+impl PowerBudget {
+    // typical WS2812B numbers
+    pub const fn new(milliamps: f32) -> Self {
+        PowerBudget {
+            milliamps,
+            milliamps_per_channel: 20.0,
+            idle_milliamps_per_led: 1.0,
+        }
+    }
+
+    pub const fn with_milliamps_per_channel(mut self, milliamps_per_channel: f32) -> Self {
+        self.milliamps_per_channel = milliamps_per_channel;
+        self
+    }
+
+    pub const fn with_idle_milliamps_per_led(mut self, idle_milliamps_per_led: f32) -> Self {
+        self.idle_milliamps_per_led = idle_milliamps_per_led;
+        self
+    }
+}
+
+pub struct OutputStage {
+    bytes_per_led: usize,
+    power_budget: Option<PowerBudget>,
+
+    // stats of the last frame, for logging and the simulator
+    last_estimate_milliamps: f32,
+    last_frame_limited: bool,
+}
+
+impl OutputStage {
+    // no budget: frames pass through unchanged
+    pub fn new(pixel_format: &PixelFormat) -> Self {
+        OutputStage {
+            bytes_per_led: pixel_format.bytes_per_led(),
+            power_budget: None,
+            last_estimate_milliamps: 0.0,
+            last_frame_limited: false,
+        }
+    }
+
+    pub fn with_power_budget(mut self, power_budget: PowerBudget) -> Self {
+        self.power_budget = Some(power_budget);
+        self
+    }
+
+    // estimated current of the last processed frame, after limiting
+    pub fn last_estimate_milliamps(&self) -> f32 {
+        self.last_estimate_milliamps
+    }
+
+    // whether the last processed frame was scaled down to fit the budget
+    pub fn last_frame_limited(&self) -> bool {
+        self.last_frame_limited
+    }
+
+    // Keeps the frame within the power budget, in place
+    pub fn process(&mut self, frame: &mut [u8]) {
+        let byte_sum: u32 = frame.iter().map(|&byte| byte as u32).sum();
+
+        let Some(power_budget) = self.power_budget else {
+            self.last_estimate_milliamps = 0.0;
+            self.last_frame_limited = false;
+            return
+        };
+
+        // the idle current is there regardless, only what the colours draw can be scaled
+        let idle_milliamps = (frame.len() / self.bytes_per_led) as f32 * power_budget.idle_milliamps_per_led;
+        let colour_milliamps = byte_sum as f32 / 255.0 * power_budget.milliamps_per_channel;
+        let available_milliamps = (power_budget.milliamps - idle_milliamps).max(0.0);
+
+        self.last_frame_limited = colour_milliamps > available_milliamps;
+        if self.last_frame_limited {
+            let scale = available_milliamps / colour_milliamps;
+            for byte in frame.iter_mut() {
+                *byte = (*byte as f32 * scale) as u8; // round down so the scaled frame stays under the budget
+            }
+            self.last_estimate_milliamps = idle_milliamps + available_milliamps;
+        }
+        else {
+            self.last_estimate_milliamps = idle_milliamps + colour_milliamps;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::pixel_format::ColorOrder;
+
+    // 4 - 20 are less than one step after the correction, they would all round to off on the wire bytes
+    #[test]
+    fn dim_values_stay_lit() {
+        let gamma_correction = GammaCorrection::new(2.2);
+        let mut pixels: Vec<FineRGB> = (1..=5).map(|step| FineRGB::from_f32(step as f32 * 4.0, 0.0, 0.0)).collect();
+        gamma_correction.apply(&mut pixels);
+        assert!(pixels.iter().all(|pixel| pixel.r > 0 && pixel.r < FineRGB::ONE_STEP));
+        assert!(pixels.windows(2).all(|pair| pair[0].r < pair[1].r));
+    }
+
+    #[test]
+    fn ends_and_brightness() {
+        let gamma_correction = GammaCorrection::new(2.2).with_brightness(0.5);
+        let mut pixels = [FineRGB::from_f32(0.0, 255.0, 255.5)];
+        gamma_correction.apply(&mut pixels);
+        assert_eq!(pixels[0].r, 0);
+        assert_eq!(pixels[0].g, 255 * FineRGB::ONE_STEP / 2);
+        assert_eq!(pixels[0].b, pixels[0].g);
+    }
+
+    #[test]
+    fn within_budget_passes_unchanged() {
+        let mut output_stage = OutputStage::new(&PixelFormat::new(ColorOrder::GRB)).with_power_budget(PowerBudget::new(1500.0));
+        let mut frame = vec![1, 2, 3, 200, 0, 7];
+        output_stage.process(&mut frame);
+        assert_eq!(frame, vec![1, 2, 3, 200, 0, 7]);
+        assert!(!output_stage.last_frame_limited());
+    }
+}
@@ -0,0 +1,172 @@
+use std::str::FromStr;
+
+use super::graphics::RGB;
+
+/*
+How a led wants its colour on the data line. WS2812 clones differ in the order of the three colour bytes (my AliExpress matrix is GRB), RGBW
+strips like the SK6812 take a fourth white byte after the colour bytes.
+
+For RGBW the white that all three channels have in common is taken out of the colours and sent as white, which is brighter and a cleaner
+white than mixing it from red, green and blue.
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorOrder {
+    RGB,
+    RBG,
+    GRB,
+    GBR,
+    BRG,
+    BGR,
+}
+
+impl ColorOrder {
+    // position of r, g and b on the wire
+    fn positions(&self) -> [usize; 3] {
+        match self {
+            ColorOrder::RGB => [0, 1, 2],
+            ColorOrder::RBG => [0, 2, 1],
+            ColorOrder::GRB => [1, 0, 2],
+            ColorOrder::GBR => [2, 0, 1],
+            ColorOrder::BRG => [1, 2, 0],
+            ColorOrder::BGR => [2, 1, 0],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PixelFormat {
+    order: ColorOrder,
+    white_channel: bool,
+}
+
+impl PixelFormat {
+    pub const fn new(order: ColorOrder) -> Self {
+        PixelFormat {
+            order,
+            white_channel: false,
+        }
+    }
+
+    // RGBW led, the white byte comes after the colour bytes
+    pub const fn with_white_channel(mut self) -> Self {
+        self.white_channel = true;
+        self
+    }
+
+    pub fn bytes_per_led(&self) -> usize {
+        if self.white_channel { 4 } else { 3 }
+    }
+
+    // writes one led into dest, which is bytes_per_led long
+    pub fn encode(&self, rgb: &RGB, dest: &mut [u8]) {
+        let (mut r, mut g, mut b) = (rgb.r, rgb.g, rgb.b);
+        if self.white_channel {
+            let white = r.min(g).min(b);
+            r -= white;
+            g -= white;
+            b -= white;
+            dest[3] = white;
+        }
+
+        let [r_pos, g_pos, b_pos] = self.order.positions();
+        dest[r_pos] = r;
+        dest[g_pos] = g;
+        dest[b_pos] = b;
+    }
+
+    // the led as it would look, white mixed back into the colours. Used to show frames on a host.
+    pub fn decode(&self, src: &[u8]) -> RGB {
+        let [r_pos, g_pos, b_pos] = self.order.positions();
+        let white = if self.white_channel { src[3] } else { 0 };
+
+        RGB {
+            r: src[r_pos].saturating_add(white),
+            g: src[g_pos].saturating_add(white),
+            b: src[b_pos].saturating_add(white),
+        }
+    }
+}
+
+// Text form for the simulator command line: the colour order, with a W at the end for RGBW. GRB, RGB, GRBW, ...
+impl FromStr for PixelFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let upper = s.to_ascii_uppercase();
+        let (order, white_channel) = match upper.strip_suffix('W') {
+            Some(order) => (order, true),
+            None => (upper.as_str(), false),
+        };
+
+        let order = match order {
+            "RGB" => ColorOrder::RGB,
+            "RBG" => ColorOrder::RBG,
+            "GRB" => ColorOrder::GRB,
+            "GBR" => ColorOrder::GBR,
+            "BRG" => ColorOrder::BRG,
+            "BGR" => ColorOrder::BGR,
+            _ => return Err(format!("unknown colour order '{}'", s))
+        };
+
+        let pixel_format = PixelFormat::new(order);
+        Ok(if white_channel { pixel_format.with_white_channel() } else { pixel_format })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORDERS: [ColorOrder; 6] = [ColorOrder::RGB, ColorOrder::RBG, ColorOrder::GRB, ColorOrder::GBR, ColorOrder::BRG, ColorOrder::BGR];
+
+    fn encode(pixel_format: PixelFormat, rgb: RGB) -> Vec<u8> {
+        let mut bytes = vec![0; pixel_format.bytes_per_led()];
+        pixel_format.encode(&rgb, &mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn color_orders_put_every_byte_in_place() {
+        let rgb = RGB { r: 1, g: 2, b: 3 };
+        for (order, expected) in ORDERS.into_iter().zip([[1, 2, 3], [1, 3, 2], [2, 1, 3], [2, 3, 1], [3, 1, 2], [3, 2, 1]]) {
+            assert_eq!(encode(PixelFormat::new(order), rgb), expected, "{:?}", order);
+        }
+    }
+
+    #[test]
+    fn white_channel_takes_the_common_part() {
+        let rgbw = PixelFormat::new(ColorOrder::RGB).with_white_channel();
+        assert_eq!(encode(rgbw, RGB { r: 200, g: 120, b: 50 }), [150, 70, 0, 50]);
+        assert_eq!(encode(rgbw, RGB { r: 255, g: 255, b: 255 }), [0, 0, 0, 255]);
+        assert_eq!(encode(rgbw, RGB { r: 0, g: 120, b: 50 }), [0, 120, 50, 0]);
+
+        // the white byte stays last whatever the order of the colours
+        let grbw = PixelFormat::new(ColorOrder::GRB).with_white_channel();
+        assert_eq!(encode(grbw, RGB { r: 200, g: 120, b: 50 }), [70, 150, 0, 50]);
+    }
+
+    #[test]
+    fn decode_gives_back_the_colour() {
+        let colours = [RGB { r: 1, g: 2, b: 3 }, RGB { r: 200, g: 120, b: 50 }, RGB { r: 255, g: 255, b: 255 }, RGB { r: 0, g: 0, b: 0 }];
+        for order in ORDERS {
+            for pixel_format in [PixelFormat::new(order), PixelFormat::new(order).with_white_channel()] {
+                for rgb in colours {
+                    assert_eq!(pixel_format.decode(&encode(pixel_format, rgb)), rgb, "{:?}", pixel_format);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn parse_pixel_formats() {
+        assert_eq!("GRB".parse(), Ok(PixelFormat::new(ColorOrder::GRB)));
+        assert_eq!("bgr".parse(), Ok(PixelFormat::new(ColorOrder::BGR)));
+        assert_eq!("grbw".parse(), Ok(PixelFormat::new(ColorOrder::GRB).with_white_channel()));
+        assert_eq!("RGBW".parse::<PixelFormat>().map(|format| format.bytes_per_led()), Ok(4));
+
+        assert_eq!("RGGB".parse::<PixelFormat>(), Err("unknown colour order 'RGGB'".to_string()));
+        assert_eq!("W".parse::<PixelFormat>(), Err("unknown colour order 'W'".to_string()));
+        assert_eq!("".parse::<PixelFormat>(), Err("unknown colour order ''".to_string()));
+    }
+}
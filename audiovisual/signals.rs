@@ -0,0 +1,224 @@
+use std::f32::consts::PI;
+
+/*
+Deterministic test signals for feeding AudioProcessor, AudioFrequalizer and GiTuner without a guitar plugged in: sines, log sweeps, white and pink
+noise and Karplus-Strong plucked strings.
+
+Everything that has randomness in it takes a seed, same seed gives the same buffer on every machine, so results can be compared between runs
+and in CI. Buffers are mono f32 like the AudioSource output, amplitude 1.0 is full scale.
+*/
+
+// Frequency of freq shifted by cents_offset (100 cents is a semitone)
+pub fn frequency_with_cents(freq: f32, cents_offset: f32) -> f32 {
+    freq * 2f32.powf(cents_offset / 1200.0)
+}
+
+pub fn sine(freq: f32, cents_offset: f32, amplitude: f32, num_samples: usize, sample_rate: u32) -> Vec<f32> {
+    let freq = frequency_with_cents(freq, cents_offset) as f64;
+
+    // phase in f64, f32 loses precision over a few seconds of samples
+    (0..num_samples)
+        .map(|i| {
+            let phase = 2.0 * std::f64::consts::PI * freq * i as f64 / sample_rate as f64;
+            amplitude * phase.sin() as f32
+        })
+        .collect()
+}
+
+// Exponential sweep from start_freq to end_freq, every octave takes the same amount of time. Both frequencies have to be above 0, a sweep
+// from a frequency to itself is a sine.
+pub fn log_sweep(start_freq: f32, end_freq: f32, amplitude: f32, num_samples: usize, sample_rate: u32) -> Vec<f32> {
+    assert!(start_freq > 0.0 && end_freq > 0.0, "log_sweep needs frequencies above 0, got {} to {}", start_freq, end_freq);
+    let duration = num_samples as f64 / sample_rate as f64;
+    let rate = (end_freq as f64 / start_freq as f64).ln();
+    if rate.abs() < 1e-9 {
+        return sine(start_freq, 0.0, amplitude, num_samples, sample_rate) // the phase below divides by rate
+    }
+
+    (0..num_samples)
+        .map(|i| {
+            let t = i as f64 / sample_rate as f64;
+            let phase = 2.0 * std::f64::consts::PI * start_freq as f64 * duration / rate * ((t / duration * rate).exp() - 1.0);
+            amplitude * phase.sin() as f32
+        })
+        .collect()
+}
+
+pub fn white_noise(seed: u64, amplitude: f32, num_samples: usize) -> Vec<f32> {
+    let mut rng = NoiseRng::new(seed);
+    (0..num_samples).map(|_| amplitude * rng.next_f32()).collect()
+}
+
+// Pink (1/f) noise from white noise through Paul Kellet's filter, accurate to about 0.05 dB above 9 Hz at 44.1 kHz
+pub fn pink_noise(seed: u64, amplitude: f32, num_samples: usize) -> Vec<f32> {
+    let mut rng = NoiseRng::new(seed);
+    let mut b = [0f32; 7];
+
+    (0..num_samples)
+        .map(|_| {
+            let white = rng.next_f32();
+            b[0] = 0.99886 * b[0] + white * 0.0555179;
+            b[1] = 0.99332 * b[1] + white * 0.0750759;
+            b[2] = 0.96900 * b[2] + white * 0.153852;
+            b[3] = 0.86650 * b[3] + white * 0.3104856;
+            b[4] = 0.55000 * b[4] + white * 0.5329522;
+            b[5] = -0.7616 * b[5] - white * 0.0168980;
+            let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+            b[6] = white * 0.115926;
+
+            amplitude * pink * 0.11 // roughly back to full scale
+        })
+        .collect()
+}
+
+/*
+Karplus-Strong plucked string. A delay line filled with noise is fed back through a two point average, which damps the high harmonics first
+like a real string. decay is the feedback gain: 1.0 rings (almost) forever, 0.99 dies out within a second for mid range notes.
+
+The average adds half a sample of delay and the delay line can only be a whole number of samples, so a first order allpass makes up the
+fractional rest. Without it high notes are tens of cents off, which makes the signal useless for checking the tuner.
+
+freq is kept between 1 Hz and half the sample rate: 0 would need an endless delay line, and above about sample_rate / 1.5 the fractional
+delay goes negative and the allpass leaves its stable range.
+*/
+pub fn karplus_strong(freq: f32, decay: f32, seed: u64, amplitude: f32, num_samples: usize, sample_rate: u32) -> Vec<f32> {
+    let freq = freq.clamp(1.0, sample_rate as f32 / 2.0);
+    let period = sample_rate as f32 / freq;
+    let mut delay_len = (period - 0.5).floor().max(1.0) as usize;
+    let mut fractional_delay = period - 0.5 - delay_len as f32;
+    if fractional_delay < 0.1 && delay_len > 1 { // allpass gets unstable-ish near 0, shift a sample into it
+        delay_len -= 1;
+        fractional_delay += 1.0;
+    }
+    let allpass_coefficient = (1.0 - fractional_delay) / (1.0 + fractional_delay);
+
+    // the pluck: zero mean noise burst
+    let mut delay_line = white_noise(seed, 1.0, delay_len);
+    let mean = delay_line.iter().sum::<f32>() / delay_len as f32;
+    delay_line.iter_mut().for_each(|x| *x -= mean);
+
+    let mut output = Vec::with_capacity(num_samples);
+    let mut index = 0;
+    let mut previous = 0.0;
+    let mut allpass_prev_in = 0.0;
+    let mut allpass_prev_out = 0.0;
+
+    for _ in 0..num_samples {
+        let current = delay_line[index];
+        output.push(amplitude * current);
+
+        let averaged = decay * 0.5 * (current + previous);
+        previous = current;
+
+        let allpassed = allpass_coefficient * averaged + allpass_prev_in - allpass_coefficient * allpass_prev_out;
+        allpass_prev_in = averaged;
+        allpass_prev_out = allpassed;
+
+        delay_line[index] = allpassed;
+        index = (index + 1) % delay_len;
+    }
+    output
+}
+
+// Sample by sample sum of signals, the result is as long as the longest one
+pub fn mix(signals: &[&[f32]]) -> Vec<f32> {
+    let len = signals.iter().map(|signal| signal.len()).max().unwrap_or(0);
+    let mut output = vec![0f32; len];
+
+    for signal in signals {
+        for (out, sample) in output.iter_mut().zip(signal.iter()) {
+            *out += sample;
+        }
+    }
+    output
+}
+
+// Hann fade in and out over fade_samples at both ends, avoids clicks that show up as broadband energy in the equalizer
+pub fn apply_fade(signal: &mut [f32], fade_samples: usize) {
+    let fade_samples = fade_samples.min(signal.len() / 2);
+    let len = signal.len();
+
+    for i in 0..fade_samples {
+        let gain = 0.5 - 0.5 * (PI * i as f32 / fade_samples as f32).cos();
+        signal[i] *= gain;
+        signal[len - 1 - i] *= gain;
+    }
+}
+
+/*
+SplitMix64, small and good enough for test noise. Not using the rand crate so the exact sequence can't change with a dependency update.
+*/
+pub struct NoiseRng {
+    state: u64,
+}
+
+impl NoiseRng {
+    pub fn new(seed: u64) -> Self {
+        NoiseRng {
+            state: seed,
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    // uniform between -1 and 1
+    pub fn next_f32(&mut self) -> f32 {
+        let unit = (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32; // 24 bits fit exactly in an f32 mantissa
+        unit * 2.0 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_buffer() {
+        assert_eq!(white_noise(42, 0.5, 4096), white_noise(42, 0.5, 4096));
+        assert_eq!(pink_noise(42, 0.5, 4096), pink_noise(42, 0.5, 4096));
+        assert_eq!(karplus_strong(82.41, 0.996, 42, 0.5, 4096, 48000), karplus_strong(82.41, 0.996, 42, 0.5, 4096, 48000));
+    }
+
+    #[test]
+    fn other_seed_other_buffer() {
+        assert_ne!(white_noise(1, 0.5, 64), white_noise(2, 0.5, 64));
+        assert_ne!(karplus_strong(110.0, 0.996, 1, 0.5, 1024, 48000), karplus_strong(110.0, 0.996, 2, 0.5, 1024, 48000));
+    }
+
+    // the published SplitMix64 sequence for seed 0, if this changes every stored result changes with it
+    #[test]
+    fn noise_rng_sequence() {
+        let mut rng = NoiseRng::new(0);
+        assert_eq!(rng.next_u64(), 0xE220_A839_7B1D_CDAF);
+        assert_eq!(rng.next_u64(), 0x6E78_9E6A_A1B9_65F4);
+        assert_eq!(rng.next_u64(), 0x06C4_5D18_8009_454F);
+    }
+
+    #[test]
+    fn log_sweep_to_same_frequency_is_a_sine() {
+        let sweep = log_sweep(440.0, 440.0, 0.5, 2048, 48000);
+        assert!(sweep.iter().all(|x| x.is_finite()));
+        assert_eq!(sweep, sine(440.0, 0.0, 0.5, 2048, 48000));
+    }
+
+    #[test]
+    #[should_panic]
+    fn log_sweep_from_zero_panics() {
+        log_sweep(0.0, 440.0, 0.5, 16, 48000);
+    }
+
+    #[test]
+    fn karplus_strong_out_of_range_stays_finite() {
+        for freq in [0.0, 47000.0, 48000.0] {
+            let pluck = karplus_strong(freq, 0.996, 7, 0.5, 4800, 48000);
+            assert_eq!(pluck.len(), 4800);
+            assert!(pluck.iter().all(|x| x.is_finite() && x.abs() <= 1.0), "freq {}", freq); // the zero mean noise burst is at most about 2x amplitude
+        }
+    }
+}
@@ -0,0 +1,21 @@
+/*
+Sprites from the images in assets/, generated at build time by build.rs. Add an image there and it shows up here as a static with the file
+name in upper case: assets/one_up.png is ONE_UP.
+
+Pixels are RGBA rows from the top, the alpha is real: Framebuffer::blit_sprite blends half transparent pixels with what is under them.
+*/
+
+pub struct StaticSprite {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: &'static [[u8; 4]],
+}
+
+impl StaticSprite {
+    // RGBA at x, y from the top left
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        self.pixels[y * self.width + x]
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/sprites.rs"));
@@ -0,0 +1,328 @@
+use super::graphics::{FineRGB, RGB};
+use super::colormap;
+
+/*
+All the colours the painters and the switch splash use, in one place. A Theme has a palette per part of the display, plain colours are
+RGB and the things that move through colours (the pulsing equalizer background, the bar colour cycle) are Gradients.
+
+CLASSIC is the look the painters always had. COLOR_BLIND_SAFE is built from the Okabe-Ito palette and never tells two things apart by red
+against green only: in tune is blue, out of tune orange. The ModeRegistry holds the active theme and hands it to the modes, so switching
+is just ModeRegistry::set_theme between two frames.
+
+The colours are on the even scale of OUTPUT_GAMMA (see GammaCorrection), not what the leds get: 20 - 40 is a dim but visible background,
+100 is already bright in a dark room. Change the gamma and the themes have to be picked again.
+*/
+
+// Colours along 0.0 - 1.0, linear in between the stops. Stops are in RGB steps and may have fractions.
+pub struct Gradient {
+    stops: &'static [(f32, [f32; 3])], // position, r g b. Sorted by position.
+}
+
+impl Gradient {
+    pub const fn new(stops: &'static [(f32, [f32; 3])]) -> Self {
+        Gradient { stops }
+    }
+
+    // r g b at position, before the first stop it's the first colour and after the last the last colour
+    pub fn at(&self, position: f32) -> [f32; 3] {
+        let next = self.stops.partition_point(|(stop, _)| *stop <= position);
+        if next == 0 {
+            return self.stops[0].1
+        }
+        let (from_position, from) = self.stops[next - 1];
+        let Some(&(to_position, to)) = self.stops.get(next) else {
+            return from
+        };
+
+        let amount = (position - from_position) / (to_position - from_position);
+        std::array::from_fn(|i| from[i] + (to[i] - from[i]) * amount)
+    }
+
+    pub fn fine_at(&self, position: f32) -> FineRGB {
+        let [r, g, b] = self.at(position);
+        FineRGB::from_f32(r, g, b)
+    }
+}
+
+pub struct EqualizerPalette {
+    pub background: Gradient, // the background pulses back and forth over this
+    pub bars: Gradient, // the hue the bars cycle through, scaled to the brightness of the bar so only the ratio between r g b counts
+    pub peak: RGB, // the peak hold dots
+}
+
+pub struct TunerPalette {
+    pub background: RGB,
+    pub baseline: RGB, // the frequency of the note
+    pub detected_line: RGB, // the frequency that is played
+    pub detected_note: RGB,
+    pub in_tune_note: RGB,
+    pub adjacent_notes: RGB,
+}
+
+pub struct SpectrogramPalette {
+    pub magnitudes: Gradient, // silent at 0.0 to loudest at 1.0, full range, the painter dims it (see colormap.rs)
+}
+
+pub struct ScopePalette {
+    pub background: RGB,
+    pub grid: RGB, // the zero line
+    pub dc_offset: RGB,
+    pub trace: RGB,
+    pub free_running: RGB, // the trace when nothing triggered, dimmer than trace
+    pub clipped: RGB, // the trace where the ADC clipped
+}
+
+pub struct MeterPalette {
+    pub background: RGB,
+    pub low: RGB, // segments below METER_YELLOW_DB
+    pub mid: RGB, // up to METER_RED_DB
+    pub high: RGB,
+    pub clip: RGB, // the clip latch
+}
+
+pub struct OverlayPalette {
+    pub background: RGB, // behind the gain, the mode is covered where it is
+    pub text: RGB,
+    pub bar: RGB, // where the knob is between GAIN_MIN_DB and GAIN_MAX_DB
+}
+
+pub struct SplashPalette {
+    pub equalizer_background: RGB,
+    pub tuner_background: RGB,
+    pub spectrogram_background: RGB,
+    pub scope_background: RGB,
+    pub meter_background: RGB,
+    pub label: RGB,
+    pub end_line: RGB,
+    pub end_dots: RGB,
+}
+
+pub struct Theme {
+    pub name: &'static str,
+    pub equalizer: EqualizerPalette,
+    pub tuner: TunerPalette,
+    pub spectrogram: SpectrogramPalette,
+    pub scope: ScopePalette,
+    pub meter: MeterPalette,
+    pub overlay: OverlayPalette,
+    pub splash: SplashPalette,
+}
+
+impl Theme {
+    // case insensitive, for picking one from a setting or the command line
+    pub fn by_name(name: &str) -> Option<&'static Theme> {
+        THEMES.iter().copied().find(|theme| theme.name.eq_ignore_ascii_case(name))
+    }
+
+    // the one after this in THEMES, wraps around
+    pub fn next(&self) -> &'static Theme {
+        let index = THEMES.iter().position(|theme| theme.name == self.name).unwrap_or(0);
+        THEMES[(index + 1) % THEMES.len()]
+    }
+}
+
+pub const CLASSIC: Theme = Theme {
+    name: "classic",
+    equalizer: EqualizerPalette {
+        background: Gradient::new(&[(0.0, [21.0, 36.0, 21.0]), (1.0, [43.0, 43.0, 21.0])]), // dim green to dim yellow
+        bars: Gradient::new(&[(0.0, [0.1, 0.0, 0.0]), (1.0, [0.0, 0.0, 1.0])]), // red through purple to blue, mostly blue
+        peak: RGB{r:110, g:96, b:0},
+    },
+    tuner: TunerPalette {
+        background: RGB{r:21, g:21, b:43},
+        baseline: RGB{r:255, g:236, b:0},
+        detected_line: RGB{r:123, g:255, b:255},
+        detected_note: RGB{r:228, g:0, b:0},
+        in_tune_note: RGB{r:0, g:255, b:0},
+        adjacent_notes: RGB{r:167, g:0, b:167},
+    },
+    spectrogram: SpectrogramPalette {
+        magnitudes: colormap::INFERNO,
+    },
+    scope: ScopePalette {
+        background: RGB{r:0, g:0, b:21},
+        grid: RGB{r:28, g:28, b:28},
+        dc_offset: RGB{r:80, g:59, b:0},
+        trace: RGB{r:0, g:122, b:0},
+        free_running: RGB{r:0, g:64, b:0},
+        clipped: RGB{r:132, g:0, b:0},
+    },
+    meter: MeterPalette {
+        background: RGB{r:0, g:0, b:0},
+        low: RGB{r:0, g:110, b:0},
+        mid: RGB{r:103, g:96, b:0},
+        high: RGB{r:122, g:0, b:0},
+        clip: RGB{r:151, g:0, b:0},
+    },
+    overlay: OverlayPalette {
+        background: RGB{r:0, g:0, b:0},
+        text: RGB{r:0, g:142, b:122},
+        bar: RGB{r:110, g:96, b:0},
+    },
+    splash: SplashPalette {
+        equalizer_background: RGB{r:96, g:21, b:21},
+        tuner_background: RGB{r:21, g:21, b:43},
+        spectrogram_background: RGB{r:80, g:21, b:80},
+        scope_background: RGB{r:21, g:70, b:21},
+        meter_background: RGB{r:0, g:70, b:43},
+        label: RGB{r:0, g:142, b:122},
+        end_line: RGB{r:255, g:236, b:0},
+        end_dots: RGB{r:110, g:0, b:0},
+    },
+};
+
+// Okabe-Ito: blue, orange, sky blue, bluish green, yellow, vermillion and reddish purple stay apart for all common colour vision types
+pub const COLOR_BLIND_SAFE: Theme = Theme {
+    name: "colorblind",
+    equalizer: EqualizerPalette {
+        background: Gradient::new(&[(0.0, [21.0, 21.0, 28.0]), (1.0, [21.0, 28.0, 43.0])]), // dim blue
+        bars: Gradient::new(&[(0.0, [230.0, 159.0, 0.0]), (1.0, [86.0, 180.0, 233.0])]), // orange to sky blue
+        peak: RGB{r:132, g:129, b:72},
+    },
+    tuner: TunerPalette {
+        background: RGB{r:21, g:21, b:28},
+        baseline: RGB{r:248, g:242, b:138}, // yellow
+        detected_line: RGB{r:230, g:182, b:210}, // reddish purple
+        detected_note: RGB{r:235, g:162, b:0}, // vermillion
+        in_tune_note: RGB{r:0, g:177, b:217}, // blue
+        adjacent_notes: RGB{r:132, g:132, b:132},
+    },
+    spectrogram: SpectrogramPalette {
+        magnitudes: colormap::VIRIDIS,
+    },
+    scope: ScopePalette {
+        background: RGB{r:0, g:0, b:21},
+        grid: RGB{r:28, g:28, b:28},
+        dc_offset: RGB{r:87, g:85, b:50},
+        trace: RGB{r:74, g:105, b:118},
+        free_running: RGB{r:39, g:56, b:64},
+        clipped: RGB{r:114, g:78, b:0},
+    },
+    meter: MeterPalette { // blue, yellow, vermillion
+        background: RGB{r:0, g:0, b:0},
+        low: RGB{r:0, g:85, b:105},
+        mid: RGB{r:119, g:117, b:66},
+        high: RGB{r:114, g:78, b:0},
+        clip: RGB{r:155, g:106, b:0},
+    },
+    overlay: OverlayPalette {
+        background: RGB{r:0, g:0, b:0},
+        text: RGB{r:243, g:206, b:0},
+        bar: RGB{r:0, g:85, b:105},
+    },
+    splash: SplashPalette {
+        equalizer_background: RGB{r:0, g:80, b:103},
+        tuner_background: RGB{r:21, g:21, b:43},
+        spectrogram_background: RGB{r:59, g:80, b:70},
+        scope_background: RGB{r:0, g:64, b:56},
+        meter_background: RGB{r:0, g:59, b:76},
+        label: RGB{r:243, g:206, b:0},
+        end_line: RGB{r:248, g:242, b:138},
+        end_dots: RGB{r:0, g:132, b:159},
+    },
+};
+
+// warm: embers glowing on a dark red background
+pub const EMBER: Theme = Theme {
+    name: "ember",
+    equalizer: EqualizerPalette {
+        background: Gradient::new(&[(0.0, [28.0, 0.0, 0.0]), (1.0, [43.0, 21.0, 0.0])]),
+        bars: Gradient::new(&[(0.0, [1.0, 0.05, 0.0]), (0.6, [1.0, 0.35, 0.0]), (1.0, [1.0, 0.6, 0.1])]),
+        peak: RGB{r:132, g:116, b:70},
+    },
+    tuner: TunerPalette {
+        background: RGB{r:34, g:21, b:0},
+        baseline: RGB{r:255, g:181, b:0},
+        detected_line: RGB{r:255, g:243, b:200},
+        detected_note: RGB{r:228, g:80, b:0},
+        in_tune_note: RGB{r:255, g:228, b:110},
+        adjacent_notes: RGB{r:159, g:96, b:0},
+    },
+    spectrogram: SpectrogramPalette {
+        magnitudes: colormap::INFERNO,
+    },
+    scope: ScopePalette {
+        background: RGB{r:21, g:0, b:0},
+        grid: RGB{r:34, g:21, b:0},
+        dc_offset: RGB{r:80, g:80, b:59},
+        trace: RGB{r:122, g:80, b:0},
+        free_running: RGB{r:64, g:43, b:0},
+        clipped: RGB{r:132, g:122, b:96},
+    },
+    meter: MeterPalette {
+        background: RGB{r:0, g:0, b:0},
+        low: RGB{r:96, g:59, b:0},
+        mid: RGB{r:122, g:89, b:0},
+        high: RGB{r:132, g:122, b:80},
+        clip: RGB{r:151, g:142, b:110},
+    },
+    overlay: OverlayPalette {
+        background: RGB{r:0, g:0, b:0},
+        text: RGB{r:255, g:194, b:0},
+        bar: RGB{r:122, g:89, b:0},
+    },
+    splash: SplashPalette {
+        equalizer_background: RGB{r:96, g:46, b:0},
+        tuner_background: RGB{r:34, g:21, b:0},
+        spectrogram_background: RGB{r:89, g:59, b:0},
+        scope_background: RGB{r:80, g:43, b:0},
+        meter_background: RGB{r:80, g:53, b:0},
+        label: RGB{r:255, g:194, b:0},
+        end_line: RGB{r:255, g:228, b:110},
+        end_dots: RGB{r:132, g:59, b:0},
+    },
+};
+
+// cold: cyan and white over deep blue
+pub const ICE: Theme = Theme {
+    name: "ice",
+    equalizer: EqualizerPalette {
+        background: Gradient::new(&[(0.0, [0.0, 21.0, 34.0]), (1.0, [21.0, 34.0, 43.0])]),
+        bars: Gradient::new(&[(0.0, [0.0, 0.6, 1.0]), (1.0, [0.7, 0.9, 1.0])]),
+        peak: RGB{r:110, g:122, b:132},
+    },
+    tuner: TunerPalette {
+        background: RGB{r:0, g:21, b:39},
+        baseline: RGB{r:228, g:243, b:255},
+        detected_line: RGB{r:0, g:218, b:255},
+        detected_note: RGB{r:181, g:0, b:228},
+        in_tune_note: RGB{r:0, g:255, b:228},
+        adjacent_notes: RGB{r:80, g:110, b:159},
+    },
+    spectrogram: SpectrogramPalette {
+        magnitudes: colormap::VIRIDIS,
+    },
+    scope: ScopePalette {
+        background: RGB{r:0, g:0, b:21},
+        grid: RGB{r:21, g:28, b:34},
+        dc_offset: RGB{r:96, g:0, b:110},
+        trace: RGB{r:0, g:110, b:122},
+        free_running: RGB{r:0, g:59, b:64},
+        clipped: RGB{r:132, g:132, b:132},
+    },
+    meter: MeterPalette {
+        background: RGB{r:0, g:0, b:0},
+        low: RGB{r:0, g:80, b:110},
+        mid: RGB{r:80, g:110, b:122},
+        high: RGB{r:122, g:122, b:132},
+        clip: RGB{r:151, g:151, b:159},
+    },
+    overlay: OverlayPalette {
+        background: RGB{r:0, g:0, b:0},
+        text: RGB{r:0, g:218, b:255},
+        bar: RGB{r:0, g:80, b:110},
+    },
+    splash: SplashPalette {
+        equalizer_background: RGB{r:0, g:53, b:96},
+        tuner_background: RGB{r:0, g:21, b:39},
+        spectrogram_background: RGB{r:0, g:70, b:80},
+        scope_background: RGB{r:0, g:59, b:70},
+        meter_background: RGB{r:0, g:53, b:80},
+        label: RGB{r:0, g:218, b:255},
+        end_line: RGB{r:228, g:243, b:255},
+        end_dots: RGB{r:0, g:96, b:132},
+    },
+};
+
+// the built-in themes in the order Theme::next cycles through them
+pub const THEMES: &[&Theme] = &[&CLASSIC, &COLOR_BLIND_SAFE, &EMBER, &ICE];
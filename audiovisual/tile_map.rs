@@ -0,0 +1,241 @@
+use std::{str::FromStr, sync::Arc};
+
+use super::matrix_layout::MatrixLayout;
+
+/*
+Several panels chained on one data line, shown as one big virtual canvas. Four 8x32 panels next to each other make a 32x32 wall for instance.
+
+Every Tile is a panel with its own MatrixLayout (size, wiring, rotation) and the position of its bottom left corner on the virtual canvas. The
+order of the tiles is the order of the panels on the data line: the first tile gets the first leds, the data out of its last led goes into
+the next tile.
+
+The painters draw on a Framebuffer the size of the VirtualCanvas, one RGB per position, row by row from the bottom left. Only when a frame
+goes out it gets reordered into the order of the leds on the chain (chain_order). Positions without a panel are drawn but never shown,
+overlapping panels show the same part of the canvas.
+
+The mapping is worked out once in TileMap::new and shared, so a TileMap is cheap to clone and every Framebuffer carries one.
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub layout: MatrixLayout,
+    pub x: usize,
+    pub y: usize,
+}
+
+impl Tile {
+    pub const fn new(layout: MatrixLayout, x: usize, y: usize) -> Self {
+        Tile {
+            layout,
+            x,
+            y,
+        }
+    }
+}
+
+// Size of the virtual canvas, what the painters need to know to draw on it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VirtualCanvas {
+    width: usize,
+    height: usize,
+}
+
+impl VirtualCanvas {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn num_pixels(&self) -> usize {
+        self.width * self.height
+    }
+
+    // index in the canvas of position x, y. None when outside of the canvas.
+    pub fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return None
+        }
+        Some(y as usize * self.width + x as usize)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TileMap {
+    tiles: Arc<[Tile]>,
+    canvas: VirtualCanvas,
+    chain_to_canvas: Arc<[usize]>, // for every led on the data line, the canvas index it shows
+}
+
+impl TileMap {
+    pub fn new(tiles: &[Tile]) -> Self {
+        let width = tiles.iter().map(|tile| tile.x + tile.layout.width()).max().unwrap_or(0);
+        let height = tiles.iter().map(|tile| tile.y + tile.layout.height()).max().unwrap_or(0);
+        let canvas = VirtualCanvas { width, height };
+
+        let num_leds = tiles.iter().map(|tile| tile.layout.num_leds()).sum();
+        let mut chain_to_canvas = vec![0; num_leds];
+        let mut first_led = 0;
+        for tile in tiles {
+            for y in 0..tile.layout.height() {
+                for x in 0..tile.layout.width() {
+                    let led_index = tile.layout.index(x as i32, y as i32).expect("position inside panel");
+                    chain_to_canvas[first_led + led_index] = canvas.index((tile.x + x) as i32, (tile.y + y) as i32).expect("panel inside canvas");
+                }
+            }
+            first_led += tile.layout.num_leds();
+        }
+
+        TileMap {
+            tiles: tiles.into(),
+            canvas,
+            chain_to_canvas: chain_to_canvas.into(),
+        }
+    }
+
+    // just the one panel, the canvas is the panel
+    pub fn single(layout: MatrixLayout) -> Self {
+        TileMap::new(&[Tile::new(layout, 0, 0)])
+    }
+
+    pub fn tiles(&self) -> &[Tile] {
+        &self.tiles
+    }
+
+    pub fn canvas(&self) -> VirtualCanvas {
+        self.canvas
+    }
+
+    pub fn width(&self) -> usize {
+        self.canvas.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.canvas.height
+    }
+
+    // leds on the whole chain
+    pub fn num_leds(&self) -> usize {
+        self.chain_to_canvas.len()
+    }
+
+    // for every led on the data line in order, the canvas index it shows
+    pub fn chain_order(&self) -> &[usize] {
+        &self.chain_to_canvas
+    }
+}
+
+/*
+Tile maps as text for the simulator command line: panels in chain order separated by +, every panel a MatrixLayout (see its FromStr) with
+an optional @X:Y position on the canvas. Without a position the panel sits at 0:0, so a single layout is a tile map too.
+  8x32
+  8x32@0:0+8x32@8:0+8x32@16:0+8x32@24:0
+  32x8,progressive@0:0+32x8,progressive,top-right,180@0:8
+*/
+impl FromStr for TileMap {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut tiles = vec![];
+        for panel in s.split('+').map(str::trim) {
+            let (layout, position) = panel.split_once('@').unwrap_or((panel, "0:0"));
+            let layout: MatrixLayout = layout.parse()?;
+            let (x, y) = position.split_once(':').ok_or_else(|| format!("'{}' is not X:Y", position))?;
+            let x: usize = x.parse().map_err(|_| format!("invalid panel x '{}'", x))?;
+            let y: usize = y.parse().map_err(|_| format!("invalid panel y '{}'", y))?;
+            tiles.push(Tile::new(layout, x, y));
+        }
+        Ok(TileMap::new(&tiles))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::matrix_layout::Rotation;
+
+    // three panels with their own rotation, with a gap at the left and the right of the middle
+    //   C C C .      C is 3x2 turned 180 at 0:3
+    //   C C C .
+    //   . . B B      B is 3x2 turned 90 at 2:0
+    //   A A B B      A is 2x2 at 0:0
+    //   A A B B
+    fn mixed_tiles() -> [Tile; 3] {
+        [
+            Tile::new(MatrixLayout::new(2, 2), 0, 0),
+            Tile::new(MatrixLayout::new(3, 2).with_rotation(Rotation::Clockwise90), 2, 0),
+            Tile::new(MatrixLayout::new(3, 2).with_rotation(Rotation::Clockwise180), 0, 3),
+        ]
+    }
+
+    #[test]
+    fn chain_runs_through_the_tiles_in_order() {
+        let tile_map = TileMap::new(&mixed_tiles());
+        assert_eq!((tile_map.width(), tile_map.height()), (4, 5));
+        assert_eq!(tile_map.num_leds(), 16);
+
+        // canvas index is y * 4 + x
+        let panel_a = [0, 1, 5, 4];
+        let panel_b = [10, 6, 2, 3, 7, 11];
+        let panel_c = [18, 17, 16, 12, 13, 14];
+        assert_eq!(tile_map.chain_order(), [&panel_a[..], &panel_b, &panel_c].concat());
+    }
+
+    #[test]
+    fn positions_without_a_panel_are_not_on_the_chain() {
+        let tile_map = TileMap::new(&mixed_tiles());
+        let canvas = tile_map.canvas();
+        for hidden in [(0, 2), (1, 2), (3, 3), (3, 4)] {
+            let index = canvas.index(hidden.0, hidden.1).unwrap();
+            assert!(!tile_map.chain_order().contains(&index), "{:?}", hidden);
+        }
+    }
+
+    #[test]
+    fn overlapping_panels_show_the_same_leds() {
+        let layout = MatrixLayout::new(2, 2);
+        let tile_map = TileMap::new(&[Tile::new(layout, 0, 0), Tile::new(layout.with_rotation(Rotation::Clockwise180), 0, 0)]);
+        assert_eq!((tile_map.width(), tile_map.height()), (2, 2));
+        assert_eq!(tile_map.chain_order(), [0, 1, 3, 2, 3, 2, 0, 1]);
+    }
+
+    #[test]
+    fn canvas_grows_to_the_panel_furthest_out() {
+        let tile_map = TileMap::new(&[Tile::new(MatrixLayout::new(2, 2), 5, 3)]);
+        assert_eq!((tile_map.width(), tile_map.height()), (7, 5));
+        assert_eq!(tile_map.chain_order(), [3 * 7 + 5, 3 * 7 + 6, 4 * 7 + 6, 4 * 7 + 5]);
+
+        let canvas = tile_map.canvas();
+        assert_eq!(canvas.index(7, 0), None);
+        assert_eq!(canvas.index(0, 5), None);
+        assert_eq!(canvas.index(-1, 0), None);
+
+        let empty = TileMap::new(&[]);
+        assert_eq!((empty.width(), empty.height(), empty.num_leds()), (0, 0, 0));
+    }
+
+    #[test]
+    fn parse_tile_maps() {
+        assert_eq!("2x2@0:0+3x2,90@2:0+3x2,180@0:3".parse(), Ok(TileMap::new(&mixed_tiles())));
+        assert_eq!("8x32".parse(), Ok(TileMap::single(MatrixLayout::new(8, 32))));
+
+        let wall: TileMap = "8x32@0:0+8x32@8:0 + 8x32@16:0+8x32@24:0".parse().unwrap();
+        assert_eq!((wall.width(), wall.height(), wall.num_leds()), (32, 32, 1024));
+        assert_eq!(wall.chain_order()[256], 8); // the second panel starts at its bottom left
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (text, error) in [
+            ("8x32@8", "'8' is not X:Y"),
+            ("8x32@a:0", "invalid panel x 'a'"),
+            ("8x32@0:-1", "invalid panel y '-1'"),
+            ("8x32+", "'' is not WIDTHxHEIGHT"),
+            ("8x32@0:0+8x32,diagonal@8:0", "unknown layout option 'diagonal'"),
+        ] {
+            assert_eq!(text.parse::<TileMap>(), Err(error.to_string()), "{:?}", text);
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use super::animation::{Easing, Interpolate};
+use super::framebuffer::Framebuffer;
+use super::graphics::FineRGB;
+use super::pixel_format::PixelFormat;
+use super::tile_map::TileMap;
+
+/*
+Blends from one frame to another when what is shown changes: from the equalizer to the switch splash, from the splash to the tuner. Without
+it the screen jumps from one to the other.
+
+The Compositor keeps the last frame it put out. start() freezes that frame as the outgoing one, after that every compose() mixes it with the
+incoming frame until the transition has run for its duration. The outgoing side stays frozen: the painter that goes out doesn't get audio
+anymore, so there is nothing new to show from it. The incoming side keeps moving, it's the live frame of whatever comes in.
+
+Timestamps come from the caller, like FrameThrottle and the animations.
+*/
+
+// The way a wipe edge or a slide moves over the canvas, with y up like everywhere else
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransitionKind {
+    Cut, // no transition
+    Crossfade, // every pixel fades from outgoing to incoming
+    Wipe(Direction), // an edge moves over the canvas, incoming is behind it
+    Slide(Direction), // outgoing is pushed off the canvas by incoming
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transition {
+    pub kind: TransitionKind,
+    pub duration: Duration,
+    pub easing: Easing,
+}
+
+impl Transition {
+    // eased in and out
+    pub const fn new(kind: TransitionKind, duration: Duration) -> Self {
+        Transition {
+            kind,
+            duration,
+            easing: Easing::EaseInOut,
+        }
+    }
+
+    pub const fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+}
+
+// "crossfade", "wipe-left", "slide-up", "cut", ... with an optional duration in ms: "crossfade:400". Default duration is 400ms.
+impl FromStr for Transition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, duration) = match s.split_once(':') {
+            Some((kind, milliseconds)) => (kind, milliseconds.parse().map_err(|_| format!("invalid transition duration: {}", milliseconds))?),
+            None => (s, 400),
+        };
+
+        let direction = |name: &str| match name {
+            "left" => Ok(Direction::Left),
+            "right" => Ok(Direction::Right),
+            "up" => Ok(Direction::Up),
+            "down" => Ok(Direction::Down),
+            _ => Err(format!("unknown transition direction: {}", name))
+        };
+        let kind = match kind.to_ascii_lowercase().as_str() {
+            "cut" => TransitionKind::Cut,
+            "crossfade" | "fade" => TransitionKind::Crossfade,
+            kind => match kind.split_once('-') {
+                Some(("wipe", name)) => TransitionKind::Wipe(direction(name)?),
+                Some(("slide", name)) => TransitionKind::Slide(direction(name)?),
+                _ => return Err(format!("unknown transition: {} (cut, crossfade, wipe-DIRECTION or slide-DIRECTION)", kind))
+            }
+        };
+        Ok(Transition::new(kind, Duration::from_millis(duration)))
+    }
+}
+
+pub struct Compositor {
+    transition: Transition,
+    outgoing: Framebuffer,
+    output: Framebuffer,
+    start_time: Option<Duration>, // None when no transition runs
+}
+
+impl Compositor {
+    pub fn new(transition: Transition, tile_map: &TileMap, pixel_format: PixelFormat) -> Self {
+        Compositor {
+            transition,
+            outgoing: Framebuffer::new(tile_map, pixel_format),
+            output: Framebuffer::new(tile_map, pixel_format),
+            start_time: None,
+        }
+    }
+
+    pub fn set_transition(&mut self, transition: Transition) {
+        self.transition = transition;
+    }
+
+    // Starts blending from the last frame put out. Starting again halfway blends from the half finished frame, so nothing jumps.
+    pub fn start(&mut self, now: Duration) {
+        std::mem::swap(&mut self.outgoing, &mut self.output);
+        self.start_time = Some(now);
+    }
+
+    pub fn running(&self) -> bool {
+        self.start_time.is_some()
+    }
+
+    // The frame to show at time now, incoming as it is once the transition is done
+    pub fn compose(&mut self, incoming: &Framebuffer, now: Duration) -> &Framebuffer {
+        let progress = match self.start_time {
+            Some(start_time) if !self.transition.duration.is_zero() && self.transition.kind != TransitionKind::Cut => {
+                now.saturating_sub(start_time).as_secs_f32() / self.transition.duration.as_secs_f32()
+            },
+            _ => 1.0,
+        };
+        if progress >= 1.0 {
+            self.start_time = None;
+            self.output.pixels_mut().copy_from_slice(incoming.pixels());
+            return &self.output
+        }
+        let progress = self.transition.easing.apply(progress);
+
+        let (width, height) = (self.output.width() as i32, self.output.height() as i32);
+        for y in 0..height {
+            for x in 0..width {
+                let color = self.blend_pixel(incoming, x, y, width, height, progress);
+                self.output.set_pixel_fine(x, y, color);
+            }
+        }
+        &self.output
+    }
+
+    fn blend_pixel(&self, incoming: &Framebuffer, x: i32, y: i32, width: i32, height: i32, progress: f32) -> FineRGB {
+        let outgoing = &self.outgoing;
+        let at = |framebuffer: &Framebuffer, x: i32, y: i32| framebuffer.pixel(x, y).unwrap_or_default();
+
+        match self.transition.kind {
+            TransitionKind::Cut => at(incoming, x, y),
+            TransitionKind::Crossfade => at(outgoing, x, y).interpolate(at(incoming, x, y), progress),
+            TransitionKind::Wipe(direction) => {
+                let behind_edge = match direction {
+                    Direction::Left => x as f32 >= width as f32 * (1.0 - progress),
+                    Direction::Right => (x as f32) < width as f32 * progress,
+                    Direction::Up => (y as f32) < height as f32 * progress,
+                    Direction::Down => y as f32 >= height as f32 * (1.0 - progress),
+                };
+                if behind_edge { at(incoming, x, y) } else { at(outgoing, x, y) }
+            },
+            TransitionKind::Slide(direction) => {
+                // where the pixel was before both frames moved, along the direction of the slide
+                let (along, length) = if matches!(direction, Direction::Left | Direction::Right) { (x, width) } else { (y, height) };
+                let offset = (length as f32 * progress).round() as i32;
+                let moved_from = match direction {
+                    Direction::Left | Direction::Down => along + offset,
+                    Direction::Right | Direction::Up => along - offset,
+                };
+                // the part that has left the canvas on one side shows incoming, which follows right behind outgoing
+                let (framebuffer, along) = match moved_from {
+                    moved_from if moved_from < 0 => (incoming, moved_from + length),
+                    moved_from if moved_from >= length => (incoming, moved_from - length),
+                    moved_from => (outgoing, moved_from),
+                };
+                if matches!(direction, Direction::Left | Direction::Right) { at(framebuffer, along, y) } else { at(framebuffer, x, along) }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::matrix_layout::MatrixLayout;
+    use super::super::pixel_format::ColorOrder;
+
+    const DURATION: Duration = Duration::from_millis(400);
+    const START: Duration = Duration::from_secs(10);
+
+    const DIRECTIONS: [Direction; 4] = [Direction::Left, Direction::Right, Direction::Up, Direction::Down];
+
+    fn tile_map() -> TileMap {
+        TileMap::single(MatrixLayout::new(4, 4))
+    }
+
+    // every pixel knows where it came from: r is x, g is y, b tells outgoing (0) from incoming (1)
+    fn numbered_frame(incoming: bool) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(&tile_map(), PixelFormat::new(ColorOrder::GRB));
+        for y in 0..4 {
+            for x in 0..4 {
+                framebuffer.set_pixel_fine(x, y, FineRGB { r: x as u16, g: y as u16, b: incoming as u16 });
+            }
+        }
+        framebuffer
+    }
+
+    // the outgoing frame put out, then the transition started and composed progress of the way through
+    fn composed(kind: TransitionKind, progress: f32) -> Framebuffer {
+        let mut compositor = Compositor::new(Transition::new(kind, DURATION).with_easing(Easing::Linear), &tile_map(), PixelFormat::new(ColorOrder::GRB));
+        compositor.compose(&numbered_frame(false), START - DURATION);
+        compositor.start(START);
+
+        let output = compositor.compose(&numbered_frame(true), START + DURATION.mul_f32(progress));
+        let mut copy = Framebuffer::new(&tile_map(), PixelFormat::new(ColorOrder::GRB));
+        copy.pixels_mut().copy_from_slice(output.pixels());
+        copy
+    }
+
+    // the line along the direction, left to right or bottom to top: a b c d for where outgoing pixels came from, A B C D for incoming
+    fn line(framebuffer: &Framebuffer, direction: Direction) -> String {
+        (0..4)
+            .map(|along| {
+                let (x, y) = if matches!(direction, Direction::Left | Direction::Right) { (along, 1) } else { (1, along) };
+                let pixel = framebuffer.pixel(x, y).unwrap();
+                let from = if matches!(direction, Direction::Left | Direction::Right) { pixel.r } else { pixel.g };
+                let letter = (b'a' + from as u8) as char;
+                if pixel.b == 1 { letter.to_ascii_uppercase() } else { letter }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn wipes() {
+        for (direction, halfway) in DIRECTIONS.into_iter().zip(["abCD", "ABcd", "ABcd", "abCD"]) {
+            let kind = TransitionKind::Wipe(direction);
+            assert_eq!(line(&composed(kind, 0.0), direction), "abcd", "{:?} at the start", kind);
+            assert_eq!(line(&composed(kind, 0.5), direction), halfway, "{:?} halfway", kind);
+            assert_eq!(line(&composed(kind, 1.0), direction), "ABCD", "{:?} at the end", kind);
+        }
+    }
+
+    #[test]
+    fn slides() {
+        for (direction, quarter, halfway) in [
+            (Direction::Left, "bcdA", "cdAB"),
+            (Direction::Right, "Dabc", "CDab"),
+            (Direction::Up, "Dabc", "CDab"),
+            (Direction::Down, "bcdA", "cdAB"),
+        ] {
+            let kind = TransitionKind::Slide(direction);
+            assert_eq!(line(&composed(kind, 0.0), direction), "abcd", "{:?} at the start", kind);
+            assert_eq!(line(&composed(kind, 0.25), direction), quarter, "{:?} a quarter of the way", kind);
+            assert_eq!(line(&composed(kind, 0.5), direction), halfway, "{:?} halfway", kind);
+            assert_eq!(line(&composed(kind, 1.0), direction), "ABCD", "{:?} at the end", kind);
+        }
+    }
+
+    #[test]
+    fn crossfade_and_cut() {
+        let pixel = |framebuffer: Framebuffer| framebuffer.pixel(2, 3).unwrap();
+        assert_eq!(pixel(composed(TransitionKind::Crossfade, 0.0)), FineRGB { r: 2, g: 3, b: 0 });
+        assert_eq!(pixel(composed(TransitionKind::Crossfade, 0.5)), FineRGB { r: 2, g: 3, b: 1 }); // 0.5 rounds up
+        assert_eq!(pixel(composed(TransitionKind::Crossfade, 1.0)), FineRGB { r: 2, g: 3, b: 1 });
+
+        let mut framebuffer = Framebuffer::new(&tile_map(), PixelFormat::new(ColorOrder::GRB));
+        let dark = FineRGB { r: 0, g: 0, b: 0 };
+        let light = FineRGB { r: 1000, g: 2000, b: 100 };
+        framebuffer.fill_fine(light);
+        let mut compositor = Compositor::new(Transition::new(TransitionKind::Crossfade, DURATION), &tile_map(), PixelFormat::new(ColorOrder::GRB));
+        compositor.start(START);
+        // eased in and out: a quarter of the time is an eighth of the way
+        assert_eq!(compositor.compose(&framebuffer, START + DURATION / 4).pixel(0, 0), Some(dark.interpolate(light, 0.125)));
+        assert_eq!(compositor.compose(&framebuffer, START + DURATION / 2).pixel(0, 0), Some(dark.interpolate(light, 0.5)));
+
+        // a cut is done right away
+        assert_eq!(line(&composed(TransitionKind::Cut, 0.0), Direction::Left), "ABCD");
+    }
+
+    #[test]
+    fn done_after_the_duration() {
+        let mut compositor = Compositor::new(Transition::new(TransitionKind::Crossfade, DURATION), &tile_map(), PixelFormat::new(ColorOrder::GRB));
+        assert!(!compositor.running());
+        compositor.start(START);
+        assert!(compositor.running());
+        compositor.compose(&numbered_frame(true), START + DURATION / 2);
+        assert!(compositor.running());
+        compositor.compose(&numbered_frame(true), START + DURATION);
+        assert!(!compositor.running());
+    }
+
+    #[test]
+    fn parse_transitions() {
+        assert_eq!("crossfade".parse(), Ok(Transition::new(TransitionKind::Crossfade, Duration::from_millis(400))));
+        assert_eq!("fade:250".parse(), Ok(Transition::new(TransitionKind::Crossfade, Duration::from_millis(250))));
+        assert_eq!("cut".parse(), Ok(Transition::new(TransitionKind::Cut, Duration::from_millis(400))));
+        assert_eq!("Wipe-Left:100".parse(), Ok(Transition::new(TransitionKind::Wipe(Direction::Left), Duration::from_millis(100))));
+        for (name, direction) in ["left", "right", "up", "down"].into_iter().zip(DIRECTIONS) {
+            assert_eq!(format!("slide-{}", name).parse(), Ok(Transition::new(TransitionKind::Slide(direction), Duration::from_millis(400))));
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        for (text, error) in [
+            ("spin", "unknown transition: spin (cut, crossfade, wipe-DIRECTION or slide-DIRECTION)"),
+            ("wipe", "unknown transition: wipe (cut, crossfade, wipe-DIRECTION or slide-DIRECTION)"),
+            ("wipe-diagonal", "unknown transition direction: diagonal"),
+            ("crossfade:fast", "invalid transition duration: fast"),
+            ("slide-up:-5", "invalid transition duration: -5"),
+        ] {
+            assert_eq!(text.parse::<Transition>(), Err(error.to_string()), "{:?}", text);
+        }
+    }
+}
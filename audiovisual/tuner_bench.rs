@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use super::audio_tuner::GiTuner;
+use super::signals;
+use super::AudioProcessor;
+
+/*
+Accuracy benchmark for GiTuner. Notes get pushed through GiTuner::tune one analysis window at a time and every window's note_info is checked
+against the note that was played:
+ - detection rate: share of windows with a result for the right note. Results for another note (mostly octave errors) are counted separately.
+ - cents error: distance between the detected frequency and NoteDetectionResult::note_freq, over the windows with a right result.
+ - latency: time from the start of the note until the first right result. GiTuner averages two detections so this is at least two windows.
+
+Synthetic notes cover B0 (5 string bass) to E6 (24th fret high E), recordings can be added through bench_note. Samples are at the ADC, the
+way an AudioSource gives them: 1.0 is ADC full scale and a guitar at line level is around 0.1. Every note goes through its own
+AudioProcessor at GAIN_DEFAULT_DB (the knob in the middle) before GiTuner gets it, the same gain and filters as on the board. The synthetic
+notes run at AUDIO_SAMPLE_RATE like the board, recordings have to be at that rate too. A recording only tells what the board would do when
+it was made at the level the line in of the board gets.
+*/
+
+pub const LOWEST_NOTE_MIDI: i32 = 23; // B0
+pub const HIGHEST_NOTE_MIDI: i32 = 88; // E6
+
+const NOTE_LABELS: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+pub fn midi_to_frequency(midi: i32) -> f64 {
+    440.0 * 2f64.powf((midi - 69) as f64 / 12.0)
+}
+
+pub fn midi_to_label(midi: i32) -> String {
+    format!("{}{}", NOTE_LABELS[midi.rem_euclid(12) as usize], midi.div_euclid(12) - 1)
+}
+
+// parses labels like E2, A#1 or Bb0 (also the file names of recordings) into a midi note number
+pub fn label_to_midi(label: &str) -> Option<i32> {
+    let mut chars = label.chars();
+    let base = match chars.next()?.to_ascii_uppercase() {
+        'C' => 0, 'D' => 2, 'E' => 4, 'F' => 5, 'G' => 7, 'A' => 9, 'B' => 11,
+        _ => return None
+    };
+    let rest = chars.as_str();
+    let (accidental, octave) = if let Some(octave) = rest.strip_prefix('#') {
+        (1, octave)
+    }
+    else if let Some(octave) = rest.strip_prefix('b') {
+        (-1, octave)
+    }
+    else {
+        (0, rest)
+    };
+    let octave: i32 = octave.parse().ok()?;
+    Some((octave + 1) * 12 + base + accidental)
+}
+
+pub enum BenchSignal {
+    Sine,
+    Pluck { decay: f32 }, // Karplus-Strong, see signals::karplus_strong
+}
+
+pub struct NoteBenchResult {
+    pub label: String,
+    pub expected_freq: f64,
+    pub windows: usize,
+    pub detections: usize,
+    pub wrong_note_detections: usize,
+    pub mean_cents_error: Option<f64>,
+    pub max_cents_error: Option<f64>,
+    pub first_result_latency: Option<Duration>,
+}
+
+impl NoteBenchResult {
+    pub fn detection_rate(&self) -> f64 {
+        if self.windows == 0 {
+            return 0.0
+        }
+        self.detections as f64 / self.windows as f64
+    }
+}
+
+// Run one note through a fresh AudioProcessor and GiTuner. samples are at the ADC and should start at the onset of the note.
+pub fn bench_note(label: &str, expected_freq: f64, samples: &[f32], sample_rate: u32) -> NoteBenchResult {
+    let mut audio_processor = AudioProcessor::new();
+    let mut tuner = GiTuner::new();
+    let window = tuner.samples_per_analysis();
+
+    let mut windows = 0;
+    let mut detections = 0;
+    let mut wrong_note_detections = 0;
+    let mut cents_errors = vec![];
+    let mut first_result_latency = None;
+
+    for (i, chunk) in samples.chunks_exact(window).enumerate() {
+        tuner.tune(audio_processor.apply_lowhighpass(chunk), sample_rate);
+        windows += 1;
+
+        if let Some(note_info) = &tuner.note_info {
+            let cents_from_expected = 1200.0 * (note_info.note_freq / expected_freq).log2();
+            if cents_from_expected.abs() > 1.0 {
+                wrong_note_detections += 1;
+                continue
+            }
+
+            detections += 1;
+            cents_errors.push((1200.0 * (note_info.actual_freq / note_info.note_freq).log2()).abs());
+            if first_result_latency.is_none() {
+                let samples_until_result = (i + 1) * window;
+                first_result_latency = Some(Duration::from_secs_f64(samples_until_result as f64 / sample_rate as f64));
+            }
+        }
+    }
+
+    let mean_cents_error = if cents_errors.is_empty() { None } else { Some(cents_errors.iter().sum::<f64>() / cents_errors.len() as f64) };
+    let max_cents_error = cents_errors.iter().cloned().reduce(f64::max);
+
+    NoteBenchResult {
+        label: label.to_string(),
+        expected_freq,
+        windows,
+        detections,
+        wrong_note_detections,
+        mean_cents_error,
+        max_cents_error,
+        first_result_latency,
+    }
+}
+
+// Every note from B0 to E6, amplitude at the ADC like the samples of bench_note
+pub fn bench_synthetic_range(signal: &BenchSignal, amplitude: f32, duration: Duration, sample_rate: u32) -> Vec<NoteBenchResult> {
+    let num_samples = (duration.as_secs_f64() * sample_rate as f64) as usize;
+
+    (LOWEST_NOTE_MIDI..=HIGHEST_NOTE_MIDI)
+        .map(|midi| {
+            let freq = midi_to_frequency(midi);
+            let samples = match signal {
+                BenchSignal::Sine => signals::sine(freq as f32, 0.0, amplitude, num_samples, sample_rate),
+                BenchSignal::Pluck { decay } => signals::karplus_strong(freq as f32, *decay, midi as u64, amplitude, num_samples, sample_rate),
+            };
+            bench_note(&midi_to_label(midi), freq, &samples, sample_rate)
+        })
+        .collect()
+}
+
+pub fn format_report(results: &[NoteBenchResult]) -> String {
+    let mut report = String::from("note  expected Hz  windows  detected  wrong note  mean cents  max cents  latency ms\n");
+
+    let format_optional = |value: Option<f64>| value.map(|v| format!("{:.2}", v)).unwrap_or_else(|| "-".to_string());
+    for result in results {
+        report += &format!(
+            "{:<5} {:>11.2} {:>8} {:>8.1}% {:>11} {:>11} {:>10} {:>11}\n",
+            result.label,
+            result.expected_freq,
+            result.windows,
+            result.detection_rate() * 100.0,
+            result.wrong_note_detections,
+            format_optional(result.mean_cents_error),
+            format_optional(result.max_cents_error),
+            result.first_result_latency.map(|latency| latency.as_millis().to_string()).unwrap_or_else(|| "-".to_string()),
+        );
+    }
+
+    let windows: usize = results.iter().map(|result| result.windows).sum();
+    let detections: usize = results.iter().map(|result| result.detections).sum();
+    let max_cents_error = results.iter().filter_map(|result| result.max_cents_error).reduce(f64::max);
+    let missed_notes = results.iter().filter(|result| result.detections == 0).count();
+    report += &format!(
+        "total: {:.1}% of {} windows detected, max cents error {}, {} of {} notes never detected\n",
+        if windows > 0 { detections as f64 / windows as f64 * 100.0 } else { 0.0 },
+        windows,
+        format_optional(max_cents_error),
+        missed_notes,
+        results.len(),
+    );
+    report
+}
@@ -1,49 +1,95 @@
 use std::f32::consts::PI;
-use crate::{LEDS_MAX_X, LEDS_MAX_Y};
+use std::time::Duration;
+use super::tile_map::TileMap;
+use super::framebuffer::Framebuffer;
+use super::pixel_format::PixelFormat;
 use super::graphics::*;
+use super::theme::Theme;
+use super::config::{EQ_PEAK_HOLD, EQ_PEAK_GRAVITY};
 
 /*
 Based on a set of frequency bins with f32 values, an animation is made showing the magnitude of the frequency ranges on the led matrix.
 
-This does NOT take into account when number of frequency bins is not the same as max_y on ledmatrix, it is specifically made for my
-aliexpress ledmatrix that takes G R B (for some reason) and serpentines every other row.
+Every frequency bin is one row of the canvas, bins beyond the height of the canvas are not shown. Bars are painted on a Framebuffer that
+the painter keeps, the VisualProcessor composes it with whatever else is on screen and puts it in the order and byte order of the leds.
+
+The background pulses between very dim colours and the bar ghosts fade out to it, both are painted as FineRGB and go out through the
+VisualProcessor's TemporalDither so they move smoothly instead of in visible steps. The colours come from the EqualizerPalette of the theme.
+
+On top of every bar a peak dot shows the highest recent magnitude. It stays put for EQ_PEAK_HOLD and then falls with EQ_PEAK_GRAVITY, so it
+drops slowly at first and then faster, until a bar pushes it up again. This runs on the clock of the VisualProcessor, not on the number of
+frames, so the dots fall at the same speed however fast the audio processing is.
 */
 
 /*
 Painter keeps some general state and runs the animation process.
 */
 pub struct Painter {
+    tile_map: TileMap,
+    pixel_format: PixelFormat,
+    theme: &'static Theme,
+
     // some paint state that is needed between iterations
     iteration: u16,
     background_cycle_state: f32,
     bar_cycle_state: f32,
-    current_bg_color: RGB,
-    bar_ghosts: Vec<Option<RGB>>, // for fadeout of previous bars
+    current_bg_color: FineRGB,
+    bar_ghosts: Vec<Option<FineRGB>>, // for fadeout of previous bars, by Framebuffer index
+    peaks: Vec<Peak>, // per bin
+    last_paint: Option<Duration>,
+    frame: Framebuffer, // the last painted frame
+}
+
+#[derive(Clone, Copy, Default)]
+struct Peak {
+    level: f32, // in leds from the right edge, like the bars
+    held_since: Duration,
+    fall_speed: f32, // leds per second
 }
 impl Painter {
-    pub fn new() -> Self {
-        let mut bar_ghosts = Vec::with_capacity(LEDS_MAX_X*LEDS_MAX_Y);
-        for _ in 0..LEDS_MAX_X*LEDS_MAX_Y {
+    pub fn new(tile_map: TileMap, pixel_format: PixelFormat, theme: &'static Theme) -> Self {
+        let tile_map_pixels = tile_map.canvas().num_pixels();
+        let mut bar_ghosts = Vec::with_capacity(tile_map_pixels);
+        for _ in 0..tile_map_pixels {
             bar_ghosts.push(None);
         }
 
+        let frame = BlankCanvas::new(Framebuffer::new(&tile_map, pixel_format), theme).framebuffer;
+
         Painter {
+            tile_map,
+            pixel_format,
+            theme,
             // some paint state that is needed between iterations
             iteration: 0,
             background_cycle_state: 0.0,
             bar_cycle_state: 0.0,
-            current_bg_color: RGB{r:0, g:0, b:0},
-            bar_ghosts
+            current_bg_color: FineRGB::default(),
+            bar_ghosts,
+            peaks: vec![],
+            last_paint: None,
+            frame
         }
     }
 
-    pub fn paint(&mut self, eq_bins: &Vec<f32>) -> Vec<u8> { // go from a blank canvas to a painted canvas
-        let blank_canvas = BlankCanvas::new();        
+    pub fn frame(&self) -> &Framebuffer {
+        &self.frame
+    }
+
+    // the next frame is painted in the new colours, the bar ghosts fade to the new background
+    pub fn set_theme(&mut self, theme: &'static Theme) {
+        self.theme = theme;
+    }
+
+    pub fn paint(&mut self, eq_bins: &Vec<f32>, now: Duration) -> &Framebuffer { // go from a blank canvas to a painted canvas
+        let blank_canvas = BlankCanvas::new(Framebuffer::new(&self.tile_map, self.pixel_format), self.theme);
         let background_drawn = blank_canvas.draw_background(self);
         let faded_bars_drawn = background_drawn.draw_fade_bars(self);
         let new_bars_drawn = faded_bars_drawn.draw_new_bars(self, eq_bins);
+        let peaks_drawn = new_bars_drawn.draw_peaks(self, eq_bins, now);
 
-        new_bars_drawn.output()
+        self.frame = peaks_drawn.output();
+        &self.frame
     }
 }
 
@@ -53,48 +99,49 @@ Typestate pattern:
  - Draw background
  - Draw fade bars (ghost of previously displayed bar)
  - Draw new bars
+ - Draw peak dots
  After each state transition the previous state is destroyed
 
  Did not want to use a trait object for the shared / state stuff because I wanted to minimize run-time impact on embedded.
 */
 
 struct BlankCanvas {
-    color_vec: Vec<RGB>,
+    framebuffer: Framebuffer,
     
     // background color variables
     iterations_between_bg_refreshes: u16,
-    background_min_value: u8,
-    background_max_value: u8,
 }
 struct BackgroundDrawn {
-    color_vec: Vec<RGB>,
+    framebuffer: Framebuffer,
 
     // fade settings
     fade_factor: f32
 }
 struct FadedBarsDrawn {
-    color_vec: Vec<RGB>,
+    framebuffer: Framebuffer,
 
     // newbar color settings
     newbar_min_intensity: u8,
     newbar_max_intensity: u8,
 }
 struct NewBarsDrawn {
-    color_vec: Vec<RGB>
+    framebuffer: Framebuffer,
+
+    // peak settings
+    peak_hold: Duration,
+    peak_gravity: f32, // leds per second per second
+}
+struct PeaksDrawn {
+    framebuffer: Framebuffer
 }
 
 impl BlankCanvas {
-    fn new() -> BlankCanvas {
-        let mut empty_canvas = vec![];
-        for _ in 0..(LEDS_MAX_X*LEDS_MAX_Y) {
-            empty_canvas.push(RGB{r:1,g:1,b:1});
-        }
+    fn new(mut framebuffer: Framebuffer, theme: &Theme) -> BlankCanvas {
+        framebuffer.fill_fine(theme.equalizer.background.fine_at(0.0));
 
         BlankCanvas {
-            color_vec: empty_canvas,
-            iterations_between_bg_refreshes: 5,
-            background_min_value: 1,
-            background_max_value: 5
+            framebuffer,
+            iterations_between_bg_refreshes: 5
         }
     }
 
@@ -108,13 +155,11 @@ impl BlankCanvas {
             painter.iteration = 0;
         }
       
-        painter.current_bg_color = self.get_bg_based_on_cycle_state(&painter.background_cycle_state);
+        painter.current_bg_color = self.get_bg_based_on_cycle_state(painter.theme, &painter.background_cycle_state);
         
-        for i in 0 .. LEDS_MAX_X as usize * LEDS_MAX_Y as usize {
-            self.color_vec[i] = RGB{r:painter.current_bg_color.r, g:painter.current_bg_color.g, b:painter.current_bg_color.b};
-        }
+        self.framebuffer.fill_fine(painter.current_bg_color);
         
-        BackgroundDrawn::new(self.color_vec)
+        BackgroundDrawn::new(self.framebuffer)
     }
 
     fn get_new_cycle_state(&mut self, mut cycle_state: f32) -> f32 {
@@ -125,95 +170,84 @@ impl BlankCanvas {
         cycle_state
     }
 
-    fn get_bg_based_on_cycle_state(&mut self, cycle_state: &f32) -> RGB {
-        let range = (self.background_max_value - self.background_min_value) as f32;
-        let green = (self.background_min_value as f32 + range * (0.8 + 0.2 * (cycle_state * PI).sin())).round() as u8;
-        let red = (self.background_min_value as f32 + range * (0.5 - 0.5 * ((cycle_state + 0.5) * PI).cos())).round() as u8;
-        let blue = self.background_min_value;
+    fn get_bg_based_on_cycle_state(&mut self, theme: &Theme, cycle_state: &f32) -> FineRGB {
+        // back and forth over the background gradient, one full sine per cycle
+        let position = 0.5 + 0.5 * (cycle_state * PI).sin();
     
-        RGB{r: red, g: green, b: blue}
+        theme.equalizer.background.fine_at(position) // the fractions are kept, the dither shows them
     }
 }
 
 impl BackgroundDrawn {
-    fn new(color_vec: Vec<RGB>) -> Self {
+    fn new(framebuffer: Framebuffer) -> Self {
         BackgroundDrawn {
-            color_vec,
+            framebuffer,
             fade_factor: 0.2
         }
     }
     fn draw_fade_bars(mut self, painter: &mut Painter) -> FadedBarsDrawn {
         // For every barghosts entry that is fading, fade it some more. 
         //  - If it's near the background color, stop fading
-        //  - Otherwise Update the color_vec and barghosts with the more faded color entry
-        for i in 0..self.color_vec.len() {
-            if let Some(current_ghost_bar_color) = &mut painter.bar_ghosts[i] {
-                let current_color = RGB {r: current_ghost_bar_color.r, g: current_ghost_bar_color.g, b:current_ghost_bar_color.b};
-                let mut desired_color = RGB {r: painter.current_bg_color.r, g: painter.current_bg_color.g, b:painter.current_bg_color.b};
-
-                let diff_colors = desired_color.subtract(current_color); // the full change that is needed to reach the desired color
-                let threshold = 4;
+        //  - Otherwise Update the framebuffer and barghosts with the more faded color entry
+        for i in 0..self.framebuffer.pixels().len() {
+            if let Some(current_ghost_bar_color) = painter.bar_ghosts[i] {
+                let desired_color = painter.current_bg_color;
+
+                let diff_colors = desired_color.subtract(&current_ghost_bar_color); // the full change that is needed to reach the desired color
+                let threshold = FineRGB::ONE_STEP as i32 / 4; // a quarter step, the dither makes the last steps as smooth as the rest
                 
                 if diff_colors[0].abs() <= threshold && diff_colors[1].abs() <= threshold && diff_colors[2].abs() <= threshold {
                     painter.bar_ghosts[i] = None; // don't need to fade this anymore, value within threshold
                 }
                 else {
                     let diff_with_fade_factor = std::array::from_fn(|i| {
-                        (diff_colors[i] as f32 * self.fade_factor).round() as i16 // the current step towards desired we're taking this canvas paint run
+                        (diff_colors[i] as f32 * self.fade_factor).round() as i32 // the current step towards desired we're taking this canvas paint run
                     });
                     let stepped_color = current_ghost_bar_color.return_new_applied_diff(diff_with_fade_factor);
     
-                    self.color_vec[i] = RGB{r:stepped_color.r, g:stepped_color.g, b:stepped_color.b};
-                    painter.bar_ghosts[i] = Some(RGB{r:stepped_color.r, g:stepped_color.g, b:stepped_color.b});
+                    self.framebuffer.pixels_mut()[i] = stepped_color;
+                    painter.bar_ghosts[i] = Some(stepped_color);
                 }
             }
         }
 
-        FadedBarsDrawn::new(self.color_vec)
+        FadedBarsDrawn::new(self.framebuffer)
     }
 }
 
 impl FadedBarsDrawn {
-    fn new(color_vec: Vec<RGB>) -> Self {
+    fn new(framebuffer: Framebuffer) -> Self {
         FadedBarsDrawn {
-            newbar_min_intensity: 4,
-            newbar_max_intensity: 60,
-            color_vec
+            newbar_min_intensity: 39, // on the OUTPUT_GAMMA scale, quiet bins stay a visible glow
+            newbar_max_intensity: 132,
+            framebuffer
         }
     }
     fn draw_new_bars(mut self, painter: &mut Painter, eq_bins: &Vec<f32>) -> NewBarsDrawn {
+        let width = self.framebuffer.width();
+
         // equalizer magnitudes displayed as rows on a portrait ledmatrix. Every bin corresponds 1:1 to a led matrix Y.
         for row in 0.. eq_bins.len() {
             //magnitude of frequency bin expressed in number of leds
-            let amount_leds_mag = (LEDS_MAX_X as f32 * eq_bins[row]).round().clamp(0.0, LEDS_MAX_X as f32) as usize;
+            let amount_leds_mag = (width as f32 * eq_bins[row]).round().clamp(0.0, width as f32) as usize;
 
             let newbar_color = self.get_newbar_color(painter, &eq_bins[row]);
-            let line_graphic = super::graphics::line(LEDS_MAX_X, RGB{r:newbar_color.r, g:newbar_color.g, b:newbar_color.b});
-            let line_shift = LEDS_MAX_X - amount_leds_mag;
-            paint_element_rgb(&mut self.color_vec, &line_graphic, line_shift as i32, row as i32);
+            let line_graphic = super::graphics::line(width, RGB{r:newbar_color.r, g:newbar_color.g, b:newbar_color.b});
+            let line_shift = width - amount_leds_mag;
+            self.framebuffer.blit(&line_graphic, line_shift as i32, row as i32);
 
             // Start fading the bars that are new in the next cycle.
-            if amount_leds_mag == 0 {
-                continue
-            } 
-            for i in 0..amount_leds_mag {
-                let index_to_paint;
-
-                if row % 2 == 1 { // serpentine
-                    let row_start_index = row*LEDS_MAX_X + LEDS_MAX_X - 1 - line_shift;
-                    index_to_paint = row_start_index - i; // serpentine goes from right to left which is the good direction
-                    painter.bar_ghosts[index_to_paint] = Some(RGB{r:newbar_color.r, g:newbar_color.g, b:newbar_color.b});
-                }
-                else {
-                    let row_start_index = row*LEDS_MAX_X + line_shift;
-                    index_to_paint = row_start_index + i;
-                    painter.bar_ghosts[index_to_paint] = Some(RGB{r:newbar_color.r, g:newbar_color.g, b:newbar_color.b});
+            for x in line_shift..width {
+                if let Some(index_to_paint) = self.framebuffer.index(x as i32, row as i32) {
+                    painter.bar_ghosts[index_to_paint] = Some(FineRGB::from_rgb(&newbar_color));
                 }
             }
         }
 
         NewBarsDrawn {
-            color_vec: self.color_vec
+            framebuffer: self.framebuffer,
+            peak_hold: EQ_PEAK_HOLD,
+            peak_gravity: EQ_PEAK_GRAVITY
         }
     }
 
@@ -226,27 +260,57 @@ impl FadedBarsDrawn {
             painter.bar_cycle_state -= 2.0 * PI;
         }
 
-        let blue_factor = (painter.bar_cycle_state.sin() + 1.0) / 2.0;
-        let red_factor = (1.0 - blue_factor) * 0.1;
+        let hue = painter.theme.equalizer.bars.at((painter.bar_cycle_state.sin() + 1.0) / 2.0);
         
         // Ensure the total intensity stays the same
-        let total_factor = blue_factor + red_factor;
+        let total_factor = (hue[0] + hue[1] + hue[2]).max(f32::EPSILON);
         let intensity_scale = bar_fill_intensity as f32 / total_factor;
-        
-        let blue = (blue_factor * intensity_scale).round() as u8;
-        let red = (red_factor * intensity_scale).round() as u8;
+        let scale = |factor: f32| (factor * intensity_scale).round().clamp(0.0, 255.0) as u8;
 
         RGB {
-            r: red,
-            g: 0,
-            b: blue
+            r: scale(hue[0]),
+            g: scale(hue[1]),
+            b: scale(hue[2])
         }
     }
 }
 
 impl NewBarsDrawn {
-    fn output(self) -> Vec<u8> {
-        // led matrix needs a vec of GRB values
-        self.color_vec.into_iter().flat_map(|rgb| [rgb.g, rgb.r, rgb.b]).collect()
+    fn draw_peaks(mut self, painter: &mut Painter, eq_bins: &[f32], now: Duration) -> PeaksDrawn {
+        let width = self.framebuffer.width();
+        let elapsed = painter.last_paint.map_or(0.0, |last_paint| now.saturating_sub(last_paint).as_secs_f32());
+        painter.last_paint = Some(now);
+        painter.peaks.resize(eq_bins.len(), Peak::default());
+
+        let peak_color = painter.theme.equalizer.peak;
+        for (row, (peak, bin)) in painter.peaks.iter_mut().zip(eq_bins).enumerate() {
+            // same rounding as the bars, so a dot on top of a bar is on its last led
+            let bar_level = (width as f32 * bin).round().clamp(0.0, width as f32);
+
+            if bar_level >= peak.level {
+                peak.level = bar_level;
+                peak.held_since = now;
+                peak.fall_speed = 0.0;
+            }
+            else if now.saturating_sub(peak.held_since) >= self.peak_hold {
+                peak.fall_speed += self.peak_gravity * elapsed;
+                peak.level = (peak.level - peak.fall_speed * elapsed).max(bar_level);
+            }
+
+            let peak_leds = peak.level.round() as i32;
+            if peak_leds > 0 {
+                self.framebuffer.set_pixel(width as i32 - peak_leds, row as i32, &peak_color);
+            }
+        }
+
+        PeaksDrawn {
+            framebuffer: self.framebuffer
+        }
+    }
+}
+
+impl PeaksDrawn {
+    fn output(self) -> Framebuffer {
+        self.framebuffer
     }
 }
\ No newline at end of file
@@ -0,0 +1,123 @@
+use std::time::Duration;
+use super::tile_map::TileMap;
+use super::framebuffer::Framebuffer;
+use super::pixel_format::PixelFormat;
+use super::theme::Theme;
+use super::font::{draw_text, TextStyle};
+use super::config::GAIN_OVERLAY_TIME;
+
+/*
+The gain while the knob turns: a box over the top of whatever is shown, with the gain in whole dB and a bar under it for where the knob is.
+It stays up for GAIN_OVERLAY_TIME after the last turn. It goes over the composed frame, so it stays put through transitions and splashes.
+
+Two digits fit the 8 wide matrix, a third is clipped. The default GAIN_CURVE stays within two.
+*/
+
+pub struct Overlay {
+    theme: &'static Theme,
+    gain_db: f32,
+    position: f32, // of the knob, 0.0 to 1.0
+    shown_until: Option<Duration>,
+    frame: Framebuffer, // the last frame with the overlay on it
+}
+impl Overlay {
+    pub fn new(tile_map: &TileMap, pixel_format: PixelFormat, theme: &'static Theme) -> Self {
+        Overlay {
+            theme,
+            gain_db: 0.0,
+            position: 0.0,
+            shown_until: None,
+            frame: Framebuffer::new(tile_map, pixel_format),
+        }
+    }
+
+    // the knob turned, shown from the next frame on
+    pub fn show(&mut self, gain_db: f32, position: f32, theme: &'static Theme, now: Duration) {
+        self.theme = theme;
+        self.gain_db = gain_db;
+        self.position = position;
+        self.shown_until = Some(now + GAIN_OVERLAY_TIME);
+    }
+
+    // true while it is up and once more after, for the frame without it
+    pub fn needs_frame(&mut self, now: Duration) -> bool {
+        match self.shown_until {
+            Some(shown_until) if now >= shown_until => {
+                self.shown_until = None;
+                true
+            }
+            Some(_) => true,
+            None => false
+        }
+    }
+
+    pub fn visible(&self) -> bool {
+        self.shown_until.is_some()
+    }
+
+    // the frame with the overlay on it
+    pub fn paint(&mut self, under: &Framebuffer) -> &Framebuffer {
+        let copied = Copied::new(&mut self.frame, under);
+        let box_drawn = copied.draw_box(self.theme);
+        box_drawn.draw_gain(self.theme, self.gain_db, self.position);
+        &self.frame
+    }
+}
+
+/*
+Typestates:
+ - Frame copied from what is shown
+ - Draw the box
+ - Draw the gain and the knob bar
+*/
+struct Copied<'a> {
+    framebuffer: &'a mut Framebuffer,
+
+    box_height: i32, // 1 row margin, the text, 1 row gap, the bar, 1 row margin
+}
+struct BoxDrawn<'a> {
+    framebuffer: &'a mut Framebuffer,
+}
+
+impl <'a>Copied<'a> {
+    fn new(framebuffer: &'a mut Framebuffer, under: &Framebuffer) -> Copied<'a> {
+        framebuffer.pixels_mut().copy_from_slice(under.pixels());
+
+        Copied {
+            framebuffer,
+            box_height: TextStyle::new().font.glyph_height as i32 + 4,
+        }
+    }
+
+    fn draw_box(self, theme: &Theme) -> BoxDrawn<'a> {
+        let (width, height) = (self.framebuffer.width() as i32, self.framebuffer.height() as i32);
+        for y in (height - self.box_height).max(0)..height {
+            for x in 0..width {
+                self.framebuffer.set_pixel(x, y, &theme.overlay.background);
+            }
+        }
+
+        BoxDrawn {
+            framebuffer: self.framebuffer
+        }
+    }
+}
+
+impl BoxDrawn<'_> {
+    fn draw_gain(self, theme: &Theme, gain_db: f32, position: f32) {
+        let style = TextStyle::new();
+        let (width, height) = (self.framebuffer.width() as i32, self.framebuffer.height() as i32);
+
+        let text = format!("{:.0}", gain_db);
+        let (text_width, text_height) = style.text_size(&text);
+        let text_x = ((width - text_width as i32) / 2).max(0);
+        draw_text(self.framebuffer, &text, text_x, height - 1 - text_height as i32, &theme.overlay.text);
+
+        // bar under the text, at least one led so all the way down still shows the bar is there
+        let bar_y = height - 3 - text_height as i32;
+        let lit = ((position * width as f32).round() as i32).clamp(1, width);
+        for x in 0..lit {
+            self.framebuffer.set_pixel(x, bar_y, &theme.overlay.bar);
+        }
+    }
+}
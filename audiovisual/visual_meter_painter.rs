@@ -0,0 +1,149 @@
+use super::tile_map::TileMap;
+use super::framebuffer::Framebuffer;
+use super::pixel_format::PixelFormat;
+use super::graphics::{FineRGB, RGB};
+use super::theme::Theme;
+use super::audio_meter::MeterLevels;
+use super::config::{METER_RANGE_DB, METER_YELLOW_DB, METER_RED_DB};
+
+/*
+Level meter as segmented bars, from the bottom up: every led is a segment of METER_RANGE_DB / rows dB, the top of a bar is 0 dBFS. Segments
+are in the low, mid or high colour of their zone (below METER_YELLOW_DB, below METER_RED_DB, above), the segments that are off show their
+zone colour very dimly like the unlit segments of a real meter.
+
+Three bars side by side: the level (VU or PPM), the short-term loudness and the held true peak. The top row is the clip latch, lit over the
+whole width once the true peak reached 0 dBFS.
+*/
+
+pub struct Painter {
+    tile_map: TileMap,
+    pixel_format: PixelFormat,
+    theme: &'static Theme,
+    frame: Framebuffer, // the last painted frame
+}
+impl Painter {
+    pub fn new(tile_map: TileMap, pixel_format: PixelFormat, theme: &'static Theme) -> Self {
+        let frame = BlankCanvas::new(Framebuffer::new(&tile_map, pixel_format), theme).framebuffer;
+
+        Painter {
+            tile_map,
+            pixel_format,
+            theme,
+            frame
+        }
+    }
+
+    pub fn frame(&self) -> &Framebuffer {
+        &self.frame
+    }
+
+    // the next frame is painted in the new colours
+    pub fn set_theme(&mut self, theme: &'static Theme) {
+        self.theme = theme;
+    }
+
+    pub fn paint(&mut self, levels: &MeterLevels) -> &Framebuffer {
+        let blank_canvas = BlankCanvas::new(Framebuffer::new(&self.tile_map, self.pixel_format), self.theme);
+        let bars_drawn = blank_canvas.draw_bars(self.theme, levels);
+        let clip_drawn = bars_drawn.draw_clip_latch(self.theme, levels);
+
+        self.frame = clip_drawn.output();
+        &self.frame
+    }
+}
+
+/*
+Typestates:
+ - Blank canvas
+ - Draw the bars
+ - Draw the clip latch
+*/
+struct BlankCanvas {
+    framebuffer: Framebuffer,
+
+    // segments that are off are the zone colour divided by this, on the OUTPUT_GAMMA scale: 3.5 is about a 16th of the light
+    unlit_dimming: f32,
+}
+struct BarsDrawn {
+    framebuffer: Framebuffer,
+}
+struct ClipDrawn {
+    framebuffer: Framebuffer,
+}
+
+impl BlankCanvas {
+    fn new(mut framebuffer: Framebuffer, theme: &Theme) -> BlankCanvas {
+        framebuffer.fill(&theme.meter.background);
+
+        BlankCanvas {
+            framebuffer,
+            unlit_dimming: 3.5
+        }
+    }
+
+    fn zone_color(theme: &Theme, segment_db: f32) -> RGB {
+        if segment_db >= METER_RED_DB {
+            theme.meter.high
+        }
+        else if segment_db >= METER_YELLOW_DB {
+            theme.meter.mid
+        }
+        else {
+            theme.meter.low
+        }
+    }
+
+    fn draw_bars(mut self, theme: &Theme, levels: &MeterLevels) -> BarsDrawn {
+        let width = self.framebuffer.width();
+        let segments = self.framebuffer.height().saturating_sub(1); // the top row is the clip latch
+        let segment_db = METER_RANGE_DB / segments.max(1) as f32;
+
+        // three bars with a column in between, the bars get what's left over
+        let bar_width = ((width + 1) / 3).saturating_sub(1).max(1);
+        let bars = [levels.level_db, levels.short_term_lufs, levels.true_peak_db];
+
+        for (bar, db) in bars.into_iter().enumerate() {
+            let lit_segments = ((db + METER_RANGE_DB) / segment_db).round().clamp(0.0, segments as f32) as usize;
+            let bar_x = bar * (bar_width + 1);
+
+            for segment in 0..segments {
+                let zone_color = Self::zone_color(theme, -METER_RANGE_DB + (segment as f32 + 0.5) * segment_db);
+                let color = if segment < lit_segments {
+                    FineRGB::from_rgb(&zone_color)
+                }
+                else {
+                    let dim = |value: u8| value as f32 / self.unlit_dimming;
+                    FineRGB::from_f32(dim(zone_color.r), dim(zone_color.g), dim(zone_color.b)) // fractions go through the dither
+                };
+                for x in bar_x..bar_x + bar_width {
+                    self.framebuffer.set_pixel_fine(x as i32, segment as i32, color);
+                }
+            }
+        }
+
+        BarsDrawn {
+            framebuffer: self.framebuffer
+        }
+    }
+}
+
+impl BarsDrawn {
+    fn draw_clip_latch(mut self, theme: &Theme, levels: &MeterLevels) -> ClipDrawn {
+        if levels.clipped {
+            let top = self.framebuffer.height() as i32 - 1;
+            for x in 0..self.framebuffer.width() {
+                self.framebuffer.set_pixel(x as i32, top, &theme.meter.clip);
+            }
+        }
+
+        ClipDrawn {
+            framebuffer: self.framebuffer
+        }
+    }
+}
+
+impl ClipDrawn {
+    fn output(self) -> Framebuffer {
+        self.framebuffer
+    }
+}
@@ -0,0 +1,163 @@
+use super::tile_map::TileMap;
+use super::framebuffer::Framebuffer;
+use super::pixel_format::PixelFormat;
+use super::theme::Theme;
+use super::audio_scope::Sweep;
+
+/*
+Oscilloscope: the waveform of one sweep, time along the height of the canvas and the amplitude along the width, made for the 8x32 matrix
+standing up. A landscape canvas gets the trace the same way, with fewer rows of time. The sweep starts at the bottom row and ends at the
+top, 0.0 is the middle of the width and the ADC full scale reaches the edges (Sweep::full_scale, after the gain).
+
+A row covers a bunch of samples, it shows all of them as a line from the lowest to the highest value. A row with a sample that hit the end
+of the ADC range is drawn in the clipped colour, so clipping shows up even when the gain pushes the trace off the canvas anyway.
+
+The highpass filter takes DC out of the trace, so the DC on the input is a separate line: it's the mean of the raw ADC samples, drawn where
+it would be on the same scale. Without DC it's hidden behind the zero line.
+
+A free running sweep (auto trigger, nothing triggered) is drawn in a dimmer colour than a triggered one.
+*/
+
+pub struct Painter {
+    tile_map: TileMap,
+    pixel_format: PixelFormat,
+    theme: &'static Theme,
+    frame: Framebuffer, // the last painted frame
+}
+impl Painter {
+    pub fn new(tile_map: TileMap, pixel_format: PixelFormat, theme: &'static Theme) -> Self {
+        let frame = BlankCanvas::new(Framebuffer::new(&tile_map, pixel_format), theme).draw_grid(theme).framebuffer;
+
+        Painter {
+            tile_map,
+            pixel_format,
+            theme,
+            frame
+        }
+    }
+
+    pub fn frame(&self) -> &Framebuffer {
+        &self.frame
+    }
+
+    // the next sweep is painted in the new colours
+    pub fn set_theme(&mut self, theme: &'static Theme) {
+        self.theme = theme;
+    }
+
+    pub fn paint(&mut self, sweep: &Sweep) -> &Framebuffer {
+        let blank_canvas = BlankCanvas::new(Framebuffer::new(&self.tile_map, self.pixel_format), self.theme);
+        let grid_drawn = blank_canvas.draw_grid(self.theme);
+        let dc_offset_drawn = grid_drawn.draw_dc_offset(self.theme, sweep);
+        let trace_drawn = dc_offset_drawn.draw_trace(self.theme, sweep);
+
+        self.frame = trace_drawn.output();
+        &self.frame
+    }
+}
+
+/*
+Typestates:
+ - Blank canvas
+ - Draw the zero line
+ - Draw the DC offset line
+ - Draw the trace
+*/
+struct BlankCanvas {
+    framebuffer: Framebuffer,
+}
+struct GridDrawn {
+    framebuffer: Framebuffer,
+}
+struct DcOffsetDrawn {
+    framebuffer: Framebuffer,
+}
+struct TraceDrawn {
+    framebuffer: Framebuffer,
+}
+
+// x of a value on the scale where -full_scale is the left edge and full_scale the right edge
+fn value_to_x(value: f32, full_scale: f32, width: usize) -> f32 {
+    let half = (width as f32 - 1.0) / 2.0;
+    half + (value / full_scale).clamp(-1.0, 1.0) * half
+}
+
+impl BlankCanvas {
+    fn new(mut framebuffer: Framebuffer, theme: &Theme) -> BlankCanvas {
+        framebuffer.fill(&theme.scope.background);
+
+        BlankCanvas {
+            framebuffer
+        }
+    }
+
+    // on an even width the middle is in between two columns, both get the line
+    fn draw_grid(mut self, theme: &Theme) -> GridDrawn {
+        let middle = value_to_x(0.0, 1.0, self.framebuffer.width());
+        for x in [middle.floor() as i32, middle.ceil() as i32] {
+            for y in 0..self.framebuffer.height() {
+                self.framebuffer.set_pixel(x, y as i32, &theme.scope.grid);
+            }
+        }
+
+        GridDrawn {
+            framebuffer: self.framebuffer
+        }
+    }
+}
+
+impl GridDrawn {
+    fn draw_dc_offset(mut self, theme: &Theme, sweep: &Sweep) -> DcOffsetDrawn {
+        // raw samples are before the gain, 1.0 is the ADC full scale and that is the edge of the canvas
+        let x = value_to_x(sweep.dc_offset, 1.0, self.framebuffer.width()).round() as i32;
+        let middle = value_to_x(0.0, 1.0, self.framebuffer.width());
+
+        if (x as f32 - middle).abs() > 0.5 {
+            for y in 0..self.framebuffer.height() {
+                self.framebuffer.set_pixel(x, y as i32, &theme.scope.dc_offset);
+            }
+        }
+
+        DcOffsetDrawn {
+            framebuffer: self.framebuffer
+        }
+    }
+}
+
+impl DcOffsetDrawn {
+    fn draw_trace(mut self, theme: &Theme, sweep: &Sweep) -> TraceDrawn {
+        let width = self.framebuffer.width();
+        let height = self.framebuffer.height();
+        let trace_color = if sweep.triggered { theme.scope.trace } else { theme.scope.free_running };
+
+        for row in 0..height {
+            // the samples of this row, rows get one more sample than others when it doesn't divide
+            let start = row * sweep.samples.len() / height;
+            let end = ((row + 1) * sweep.samples.len() / height).max(start + 1).min(sweep.samples.len());
+            let Some(row_samples) = sweep.samples.get(start..end) else {
+                continue
+            };
+
+            let lowest = row_samples.iter().copied().fold(f32::INFINITY, f32::min);
+            let highest = row_samples.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            let from_x = value_to_x(lowest, sweep.full_scale, width).round() as i32;
+            let to_x = value_to_x(highest, sweep.full_scale, width).round() as i32;
+
+            let clipped = sweep.clipped[start..end].iter().any(|clipped| *clipped);
+            let color = if clipped { theme.scope.clipped } else { trace_color };
+            for x in from_x..=to_x {
+                self.framebuffer.set_pixel(x, row as i32, &color);
+            }
+        }
+
+        TraceDrawn {
+            framebuffer: self.framebuffer
+        }
+    }
+}
+
+impl TraceDrawn {
+    fn output(self) -> Framebuffer {
+        self.framebuffer
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+use super::tile_map::TileMap;
+use super::framebuffer::Framebuffer;
+use super::pixel_format::PixelFormat;
+use super::graphics::FineRGB;
+use super::theme::{Gradient, Theme};
+use super::config::{SPECTROGRAM_HISTORY, SPECTROGRAM_RANGE_DB};
+
+/*
+Scrolling spectrogram (waterfall): the same frequency bins as the equalizer, but over time. Every bin is a row like in the equalizer, every
+column is a slice of time with the newest on the right, and the magnitude is the colour through the colour map of the theme.
+
+The magnitudes are levels in dB, not normalized to the loudest bin like the equalizer: the colour map goes from SPECTROGRAM_RANGE_DB below
+full scale to full scale. That way silence and noise stay at the bottom of the colour map and a note gets darker while it dies out.
+
+The canvas shows SPECTROGRAM_HISTORY, so one column is that divided by the width: half a second on the 8 wide matrix. Several FFT frames
+end up in one column, the column keeps the loudest magnitude per bin so short notes don't disappear. The column on the right edge is the
+one being filled, it moves one to the left when its time is up. When no frames came in for longer than a column (another mode was on),
+every column that went by is pushed, empty, so the time on the canvas stays right.
+
+History is a ring buffer of columns, the oldest falls off when a new one comes in.
+*/
+
+pub struct Painter {
+    tile_map: TileMap,
+    pixel_format: PixelFormat,
+    theme: &'static Theme,
+
+    // paint state that is needed between iterations
+    history: VecDeque<Vec<f32>>, // finished columns, newest at the back
+    current_column: Vec<f32>,
+    current_column_start: Option<Duration>,
+    column_duration: Duration,
+    frame: Framebuffer, // the last painted frame
+}
+impl Painter {
+    pub fn new(tile_map: TileMap, pixel_format: PixelFormat, theme: &'static Theme) -> Self {
+        let width = tile_map.width();
+        let frame = BlankCanvas::new(Framebuffer::new(&tile_map, pixel_format), theme).framebuffer;
+
+        Painter {
+            tile_map,
+            pixel_format,
+            theme,
+            history: VecDeque::with_capacity(width),
+            current_column: vec![],
+            current_column_start: None,
+            column_duration: SPECTROGRAM_HISTORY / width.max(1) as u32,
+            frame
+        }
+    }
+
+    pub fn frame(&self) -> &Framebuffer {
+        &self.frame
+    }
+
+    // the colours change with the next frame, the history is kept
+    pub fn set_theme(&mut self, theme: &'static Theme) {
+        self.theme = theme;
+    }
+
+    // levels are one FFT frame of bins, 1.0 is full scale, now is the clock of the VisualProcessor
+    pub fn paint(&mut self, levels: &[f32], now: Duration) -> &Framebuffer {
+        self.add_to_history(levels, now);
+
+        let blank_canvas = BlankCanvas::new(Framebuffer::new(&self.tile_map, self.pixel_format), self.theme);
+        let history_drawn = blank_canvas.draw_history(self);
+
+        self.frame = history_drawn.output();
+        &self.frame
+    }
+
+    fn add_to_history(&mut self, levels: &[f32], now: Duration) {
+        let column_start = *self.current_column_start.get_or_insert(now);
+        let columns_elapsed = (now.saturating_sub(column_start).as_nanos() / self.column_duration.as_nanos().max(1)) as u32;
+        if columns_elapsed > 0 {
+            let finished_column = std::mem::take(&mut self.current_column);
+            self.push_column(finished_column);
+            // nothing came in during the ones after it, more than the width would only push out what is already empty
+            for _ in 1..columns_elapsed.min(self.tile_map.width() as u32) {
+                self.push_column(vec![]);
+            }
+            self.current_column_start = Some(column_start + self.column_duration * columns_elapsed);
+        }
+
+        self.current_column.resize(levels.len(), 0.0);
+        for (loudest, level) in self.current_column.iter_mut().zip(levels) {
+            *loudest = loudest.max(Self::level_to_magnitude(*level));
+        }
+    }
+
+    // the right edge is for the current column, the history gets the rest of the width
+    fn push_column(&mut self, column: Vec<f32>) {
+        self.history.push_back(column);
+        while self.history.len() > self.tile_map.width().saturating_sub(1) {
+            self.history.pop_front();
+        }
+    }
+
+    // 0.0 at SPECTROGRAM_RANGE_DB below full scale and everything under it, 1.0 at full scale
+    fn level_to_magnitude(level: f32) -> f32 {
+        let db = 20.0 * level.max(f32::MIN_POSITIVE).log10();
+        ((db + SPECTROGRAM_RANGE_DB) / SPECTROGRAM_RANGE_DB).clamp(0.0, 1.0)
+    }
+}
+
+/*
+Typestates:
+ - Blank canvas
+ - Draw the columns of the history and the current column
+*/
+struct BlankCanvas {
+    framebuffer: Framebuffer,
+
+    // brightest a magnitude of 1.0 gets, 255 is the top of the colour map at full brightness
+    max_intensity: f32,
+}
+struct HistoryDrawn {
+    framebuffer: Framebuffer,
+}
+
+impl BlankCanvas {
+    // the colour of silence, for the columns that have no history yet
+    fn new(mut framebuffer: Framebuffer, theme: &Theme) -> BlankCanvas {
+        let max_intensity = 132.0; // about a quarter of the light the leds can give
+        framebuffer.fill_fine(Self::magnitude_color(&theme.spectrogram.magnitudes, 0.0, max_intensity));
+
+        BlankCanvas {
+            framebuffer,
+            max_intensity
+        }
+    }
+
+    fn magnitude_color(magnitudes: &Gradient, magnitude: f32, max_intensity: f32) -> FineRGB {
+        let scale = max_intensity / 255.0;
+        let [r, g, b] = magnitudes.at(magnitude.clamp(0.0, 1.0));
+        FineRGB::from_f32(r * scale, g * scale, b * scale) // dim colours keep their fractions for the dither
+    }
+
+    fn draw_history(mut self, painter: &Painter) -> HistoryDrawn {
+        let width = self.framebuffer.width() as i32;
+        let magnitudes = &painter.theme.spectrogram.magnitudes;
+
+        // the current column at the right edge, older ones to the left of it
+        let columns = std::iter::once(&painter.current_column).chain(painter.history.iter().rev());
+        for (age, column) in columns.enumerate() {
+            let x = width - 1 - age as i32;
+            for y in 0..self.framebuffer.height() {
+                let magnitude = column.get(y).copied().unwrap_or(0.0);
+                self.framebuffer.set_pixel_fine(x, y as i32, Self::magnitude_color(magnitudes, magnitude, self.max_intensity));
+            }
+        }
+
+        HistoryDrawn {
+            framebuffer: self.framebuffer
+        }
+    }
+}
+
+impl HistoryDrawn {
+    fn output(self) -> Framebuffer {
+        self.framebuffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::matrix_layout::MatrixLayout;
+    use super::super::pixel_format::ColorOrder;
+    use super::super::config::DEFAULT_THEME;
+
+    // 8 wide, half a second per column
+    fn painter() -> Painter {
+        Painter::new(TileMap::single(MatrixLayout::new(8, 32)), PixelFormat::new(ColorOrder::GRB), DEFAULT_THEME)
+    }
+
+    // every column gets its own level, so where it ends up can be told apart
+    fn column_level(column: u32) -> f32 {
+        (column + 1) as f32 / 100.0
+    }
+
+    #[test]
+    fn history_wraps_at_width_minus_one() {
+        let mut painter = painter();
+        assert_eq!(painter.column_duration, Duration::from_millis(500));
+
+        for column in 0..20 {
+            painter.paint(&[column_level(column)], painter.column_duration * column);
+        }
+
+        // 19 finished columns, the 7 newest are kept next to the current one at the right edge
+        assert_eq!(painter.history.len(), 7);
+        let kept: Vec<f32> = painter.history.iter().map(|column| column[0]).collect();
+        let expected: Vec<f32> = (12..19).map(|column| Painter::level_to_magnitude(column_level(column))).collect();
+        assert_eq!(kept, expected);
+        assert_eq!(painter.current_column, vec![Painter::level_to_magnitude(column_level(19))]);
+    }
+
+    #[test]
+    fn frames_in_one_column_keep_the_loudest() {
+        let mut painter = painter();
+        painter.paint(&[0.01, 0.5], Duration::ZERO);
+        painter.paint(&[0.1, 0.001], Duration::from_millis(200));
+        painter.paint(&[0.001, 0.001], Duration::from_millis(499));
+
+        assert!(painter.history.is_empty());
+        assert_eq!(painter.current_column, vec![Painter::level_to_magnitude(0.1), Painter::level_to_magnitude(0.5)]);
+    }
+
+    #[test]
+    fn one_column_per_column_duration() {
+        let mut painter = painter();
+        painter.paint(&[0.5], Duration::ZERO);
+
+        // no frames for 3.2 columns: the filled one and two empty ones are pushed, the current column started where the third began
+        painter.paint(&[0.1], Duration::from_millis(1600));
+        assert_eq!(painter.history.len(), 3);
+        assert_eq!(painter.history[0], vec![Painter::level_to_magnitude(0.5)]);
+        assert!(painter.history[1].is_empty() && painter.history[2].is_empty());
+        assert_eq!(painter.current_column_start, Some(Duration::from_millis(1500)));
+
+        // the next column is up at 2000, not 500 after the late frame
+        painter.paint(&[0.1], Duration::from_millis(1999));
+        assert_eq!(painter.history.len(), 3);
+        painter.paint(&[0.1], Duration::from_millis(2000));
+        assert_eq!(painter.history.len(), 4);
+    }
+
+    #[test]
+    fn long_gap_leaves_an_empty_canvas() {
+        let mut painter = painter();
+        painter.paint(&[0.5], Duration::ZERO);
+        painter.paint(&[0.1], Duration::from_secs(3600));
+
+        assert_eq!(painter.history.len(), 7);
+        assert!(painter.history.iter().all(|column| column.is_empty()));
+    }
+
+    #[test]
+    fn levels_in_db_over_the_range() {
+        assert_eq!(Painter::level_to_magnitude(0.0), 0.0);
+        assert_eq!(Painter::level_to_magnitude(1.0), 1.0);
+        assert_eq!(Painter::level_to_magnitude(2.0), 1.0);
+        let half_range = 10f32.powf(-SPECTROGRAM_RANGE_DB / 2.0 / 20.0);
+        assert!((Painter::level_to_magnitude(half_range) - 0.5).abs() < 1e-4);
+        let below_range = 10f32.powf(-(SPECTROGRAM_RANGE_DB + 1.0) / 20.0);
+        assert_eq!(Painter::level_to_magnitude(below_range), 0.0);
+    }
+}
@@ -1,6 +1,10 @@
 use pitch_detector::{core::NoteName, note::NoteDetectionResult};
 use super::graphics::*;
-use crate::{LEDS_MAX_X, LEDS_MAX_Y};
+use super::tile_map::TileMap;
+use super::framebuffer::Framebuffer;
+use super::font::draw_text;
+use super::pixel_format::PixelFormat;
+use super::theme::Theme;
 
 /*
 Based on a NoteDetectionResult from pitch_detector crate. 
@@ -34,25 +38,50 @@ pub struct NoteDetectionResult
 */
 
 /*
-Painter keeps some general state and runs the steps to draw layers.
+Painter keeps some general state and runs the steps to draw layers. Colours come from the TunerPalette of the theme.
 */
-pub struct Painter {}
+pub struct Painter {
+    tile_map: TileMap,
+    pixel_format: PixelFormat,
+    theme: &'static Theme,
+    frame: Framebuffer, // the last painted frame, the background until a note is found
+}
 impl Painter {
-    pub fn paint(&mut self, note_det_result: &NoteDetectionResult) -> Vec<u8> {
+    pub fn new(tile_map: TileMap, pixel_format: PixelFormat, theme: &'static Theme) -> Self {
+        let frame = BlankCanvas::new(Framebuffer::new(&tile_map, pixel_format), theme).framebuffer;
+
+        Painter {
+            tile_map,
+            pixel_format,
+            theme,
+            frame
+        }
+    }
+
+    pub fn frame(&self) -> &Framebuffer {
+        &self.frame
+    }
+
+    // the last frame is repainted in the new background right away, the lines and notes follow with the next note
+    pub fn set_theme(&mut self, theme: &'static Theme) {
+        self.theme = theme;
+        self.frame = BlankCanvas::new(Framebuffer::new(&self.tile_map, self.pixel_format), theme).framebuffer;
+    }
+
+    pub fn paint(&mut self, note_det_result: &NoteDetectionResult) -> &Framebuffer {
         let detected_note = &note_det_result.note_name;
         let prev_note = &note_det_result.previous_note_name;
         let next_note = &note_det_result.next_note_name;
         let cents_offset = note_det_result.cents_offset;
         let in_tune = note_det_result.in_tune;
 
-        println!("{} {} {} {} {}", &note_det_result.note_name, &note_det_result.previous_note_name, &note_det_result.next_note_name, &note_det_result.cents_offset, &note_det_result.in_tune);
-
-        let blank_canvas = BlankCanvas::new();
+        let blank_canvas = BlankCanvas::new(Framebuffer::new(&self.tile_map, self.pixel_format), self.theme);
         let base_lined = blank_canvas.draw_baseline();
         let detected_line_drawn = base_lined.draw_detected_line(cents_offset);
         let notes_drawn = detected_line_drawn.draw_notes(detected_note, prev_note, next_note, in_tune);
 
-        notes_drawn.output()
+        self.frame = notes_drawn.output();
+        &self.frame
     }
 }
 
@@ -63,14 +92,16 @@ Typestates:
  - Draw detected line for note (the estimate of the actual frequency being played)
  - Draw note names
 */
-struct BlankCanvas {
-    color_vec: Vec<RGB>,
+struct BlankCanvas<'t> {
+    framebuffer: Framebuffer,
+    theme: &'t Theme,
 
     // setting for the line to draw
     base_line_color: RGB
 }
-struct BaseLined {
-    color_vec: Vec<RGB>,
+struct BaseLined<'t> {
+    framebuffer: Framebuffer,
+    theme: &'t Theme,
 
     // settings for the line to draw
     detected_line_color: RGB,
@@ -78,7 +109,7 @@ struct BaseLined {
 }
 
 struct DetectedLineDrawn {
-    color_vec: Vec<RGB>,
+    framebuffer: Framebuffer,
 
     // settings for the note to draw
     detected_note_color: RGB,
@@ -90,87 +121,78 @@ struct DetectedLineDrawn {
 }
 
 struct NotesDrawn {
-    color_vec: Vec<RGB>,
+    framebuffer: Framebuffer,
 }
 
-impl BlankCanvas {
-    pub fn new() -> BlankCanvas {
-        let mut empty_canvas = Vec::with_capacity(LEDS_MAX_X*LEDS_MAX_Y);
-        for _ in 0..(LEDS_MAX_X*LEDS_MAX_Y) {
-            empty_canvas.push(RGB{r:1,g:1,b:5});
-        }
+impl<'t> BlankCanvas<'t> {
+    pub fn new(mut framebuffer: Framebuffer, theme: &'t Theme) -> BlankCanvas<'t> {
+        framebuffer.fill(&theme.tuner.background);
 
         BlankCanvas {
-            color_vec: empty_canvas,
-            base_line_color: RGB{r:255,g:215,b:0}
+            framebuffer,
+            theme,
+            base_line_color: theme.tuner.baseline
         }
     }
 
-    fn draw_baseline(mut self) -> BaseLined {
-        let baseline_row = (LEDS_MAX_Y as f32 / 2.0).round() as usize; // draw line starting at light 1 in row 17 (index 16), fixed around the center of the vertically placed ledstrip
-        let line_graphic = super::graphics::line(LEDS_MAX_X, RGB{r: self.base_line_color.r, g:self.base_line_color.g, b: self.base_line_color.b});
-        paint_element_rgb(&mut self.color_vec, &line_graphic, 0i32, baseline_row as i32);
+    fn draw_baseline(mut self) -> BaseLined<'t> {
+        let baseline_row = (self.framebuffer.height() as f32 / 2.0).round() as usize; // draw line starting at light 1 in row 17 (index 16) on 32 rows, fixed around the center of the vertically placed ledstrip
+        let line_graphic = super::graphics::line(self.framebuffer.width(), RGB{r: self.base_line_color.r, g:self.base_line_color.g, b: self.base_line_color.b});
+        self.framebuffer.blit(&line_graphic, 0i32, baseline_row as i32);
 
         BaseLined {
-            detected_line_color: RGB{r:51,g:255,b:255},
+            detected_line_color: self.theme.tuner.detected_line,
+            theme: self.theme,
             baseline_row,
-            color_vec: self.color_vec
+            framebuffer: self.framebuffer
         }
     }
 }
 
-impl BaseLined {
+impl BaseLined<'_> {
     fn draw_detected_line(mut self, cents_offset: f64) -> DetectedLineDrawn {      
         // -1 because even number leds with baseline in middle -> max distance is 1 less at one side of the baseline 
-        let max_distance = LEDS_MAX_Y - self.baseline_row - 1;
+        let max_distance = self.framebuffer.height().saturating_sub(self.baseline_row + 1);
 
         // draw the line in the positive or negative direction at cents_offset divided by 50
         // because as soon as the offset is more than 50% a new note becomes the baseline
         let offset_distance = (max_distance as f64 * cents_offset / 50.0).round() as i16;
         let draw_row = (self.baseline_row as i16 + offset_distance) as usize;
 
-        let line_graphic = super::graphics::line(LEDS_MAX_X, RGB{r: self.detected_line_color.r, g:self.detected_line_color.g, b: self.detected_line_color.b});
-        paint_element_rgb(&mut self.color_vec, &line_graphic, 0i32, draw_row as i32);
+        let line_graphic = super::graphics::line(self.framebuffer.width(), RGB{r: self.detected_line_color.r, g:self.detected_line_color.g, b: self.detected_line_color.b});
+        self.framebuffer.blit(&line_graphic, 0i32, draw_row as i32);
 
+        // note positions were picked on 32 rows: previous note at the bottom, detected just under the baseline, next at the top
+        let height = self.framebuffer.height();
         DetectedLineDrawn {
-            color_vec: self.color_vec,
-            detected_note_color: RGB{r:200, g:0, b: 0},
-            in_tune_color: RGB{r:0, g:255, b:0},
-            adjacent_note_color: RGB{r:100, g:0, b: 100},
-            start_row_col_detected: (14, 1),
+            framebuffer: self.framebuffer,
+            detected_note_color: self.theme.tuner.detected_note,
+            in_tune_color: self.theme.tuner.in_tune_note,
+            adjacent_note_color: self.theme.tuner.adjacent_notes,
+            start_row_col_detected: (self.baseline_row.saturating_sub(2), 1),
             start_row_col_prev: (1, 1),
-            start_row_col_next: (25, 1)
+            start_row_col_next: (height.saturating_sub(7), 1)
         }
     }
 }
 
 impl DetectedLineDrawn {
     fn draw_notes(mut self, detected_note: &NoteName, prev_note: &NoteName, next_note: &NoteName, in_tune: bool) -> NotesDrawn {
-        let graphical_detected_note = GraphicalNote::new(detected_note);
-        let graphical_prev_note = GraphicalNote::new(prev_note);
-        let graphical_next_note = GraphicalNote::new(next_note);
-        
         let detected_note_color = if in_tune {self.in_tune_color} else {self.detected_note_color};
-        let adjacent_note_color = RGB{r:self.adjacent_note_color.r, g:self.adjacent_note_color.g, b:self.adjacent_note_color.b};
-        
-        let detected_graphic = convert_vecvecbool_to_xy_rgb_vec(graphical_detected_note.matrix, detected_note_color);
-        let prev_note_graphic = convert_vecvecbool_to_xy_rgb_vec(graphical_prev_note.matrix, RGB{r:adjacent_note_color.r, g:adjacent_note_color.g, b:adjacent_note_color.b});
-        let next_note_graphic = convert_vecvecbool_to_xy_rgb_vec(graphical_next_note.matrix, adjacent_note_color);
         
-        paint_element_rgb(&mut self.color_vec, &detected_graphic, self.start_row_col_detected.1 as i32, self.start_row_col_detected.0 as i32);
-        paint_element_rgb(&mut self.color_vec, &prev_note_graphic, self.start_row_col_prev.1 as i32, self.start_row_col_prev.0 as i32);
-        paint_element_rgb(&mut self.color_vec, &next_note_graphic, self.start_row_col_next.1 as i32, self.start_row_col_next.0 as i32);
+        draw_text(&mut self.framebuffer, note_label(detected_note), self.start_row_col_detected.1 as i32, self.start_row_col_detected.0 as i32, &detected_note_color);
+        draw_text(&mut self.framebuffer, note_label(prev_note), self.start_row_col_prev.1 as i32, self.start_row_col_prev.0 as i32, &self.adjacent_note_color);
+        draw_text(&mut self.framebuffer, note_label(next_note), self.start_row_col_next.1 as i32, self.start_row_col_next.0 as i32, &self.adjacent_note_color);
 
         NotesDrawn {
-            color_vec: self.color_vec
+            framebuffer: self.framebuffer
         }
     }
 }
 
 impl NotesDrawn {
-    fn output(self) -> Vec<u8> {
-        // led matrix needs a vec of separate GRB values
-        self.color_vec.into_iter().flat_map(|rgb| [rgb.g, rgb.r, rgb.b]).collect()
+    fn output(self) -> Framebuffer {
+        self.framebuffer
     }
 }
 
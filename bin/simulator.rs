@@ -0,0 +1,275 @@
+use std::{env, path::PathBuf, process, thread, time::{Duration, Instant}};
+
+use frequatuner::audiovisual::{AudioProcessor, VisualProcessor};
+use frequatuner::audiovisual::audio_source::{AudioSource, WavAudioSource};
+use frequatuner::audiovisual::led_sink::{FrameDumper, FrameDumpFormat, LedSink};
+use frequatuner::audiovisual::frame_throttle::FrameThrottle;
+use frequatuner::audiovisual::config::{PANELS, PIXEL_FORMAT, MODE_TRANSITION, MODE_ORDER, SCOPE_TRIGGER, METER_BALLISTICS, BUTTON_BINDINGS, OUTPUT_GAMMA, LED_POWER_BUDGET};
+use frequatuner::audiovisual::output_stage::OutputStage;
+use frequatuner::audiovisual::tile_map::TileMap;
+use frequatuner::audiovisual::pixel_format::PixelFormat;
+use frequatuner::audiovisual::transition::Transition;
+use frequatuner::audiovisual::theme::Theme;
+use frequatuner::audiovisual::audio_scope::ScopeTrigger;
+use frequatuner::audiovisual::audio_meter::Ballistics;
+use frequatuner::audiovisual::builtin_modes::{self, ScopeMode, MeterMode};
+use frequatuner::audiovisual::button::GestureRecognizer;
+use frequatuner::audiovisual::gain_knob::GainKnob;
+
+/*
+Host simulator: runs a WAV file through the same AudioProcessor -> VisualProcessor pipeline as the board and dumps the frames that would
+have reached the ledmatrix to a GIF or a directory of PNGs. Build with the host feature.
+
+    simulator <input.wav> <output.gif | output_dir> [--speed X] [--mode-at SECONDS:eq|tuner|spectrogram|scope|meter]... [--matrix LAYOUT] [--pixel-format ORDER] [--transition KIND]
+              [--theme-at SECONDS:NAME]... [--scope-trigger TRIGGER] [--meter-ballistics vu|ppm] [--press-at SECONDS:MILLIS]...
+              [--knob-at SECONDS:POSITION]...
+
+--speed 1 runs in real time, 4 runs four times faster, 0 runs as fast as possible (default). The frame throttle runs on the clock of the
+samples played, so the output is the same regardless of speed.
+--mode-at switches mode at a timestamp in the recording, can be given more than once. Modes are the names in MODE_ORDER. The simulation
+starts in the first of those like the board, every switch shows the switch splash while the new mode keeps analysing the recording.
+--matrix simulates another panel, for instance 16x16,progressive,top-left (see MatrixLayout), or several chained panels like
+8x32@0:0+8x32@8:0+8x32@16:0+8x32@24:0 (see TileMap). Default is the board's PANELS.
+--pixel-format sets the colour order of the leds (GRB, RGB, GRBW for RGBW strips, ...). The output images look the same, but the frames take
+the same path through the encoding as on the board.
+--transition sets how the display blends between modes and splashes, for instance crossfade:400, wipe-up:300, slide-left or cut (see
+Transition). Default is the board's MODE_TRANSITION.
+--theme-at switches the colour theme at a timestamp, like a theme switch on the board. 0:colorblind runs the whole recording in the
+colour-blind safe theme. Themes are classic, colorblind, ember and ice (see theme.rs).
+--scope-trigger sets the trigger of the oscilloscope mode: auto, normal or single, with an optional level and holdoff in milliseconds like
+normal:0.1:5 (see ScopeTrigger). Default is the board's SCOPE_TRIGGER.
+--meter-ballistics sets how the level of the meter mode moves: vu or ppm (see Ballistics). Default is the board's METER_BALLISTICS.
+--press-at presses the mode button at a timestamp for that many milliseconds, with bouncing contacts. The presses go through the same
+GestureRecognizer and BUTTON_BINDINGS as on the board: 10:100 --press-at 10.25:100 is a double press, 10:1000 a long press.
+--knob-at turns the gain knob to a position from 0.0 to 1.0 at a timestamp, it goes through the same GainKnob filter and GAIN_CURVE as on the
+board and shows the gain overlay. Until the first one the gain is GAIN_DEFAULT_DB.
+
+Frames also go through the board's OutputStage to report the current they would draw. The images are what the leds get, with the gamma
+correction taken out again (see FrameDumper::with_display_gamma): the corrected values look too dark on a monitor, which does its own gamma.
+*/
+
+const USAGE: &str = "usage: simulator <input.wav> <output.gif | output_dir> [--speed X] [--mode-at SECONDS:eq|tuner|spectrogram|scope|meter]... [--matrix LAYOUT] [--pixel-format ORDER] [--transition KIND] [--theme-at SECONDS:NAME]... [--scope-trigger TRIGGER] [--meter-ballistics vu|ppm] [--press-at SECONDS:MILLIS]... [--knob-at SECONDS:POSITION]...";
+
+struct SimulatorArgs {
+    input: PathBuf,
+    output: FrameDumpFormat,
+    speed: f64,
+    mode_switches: Vec<(Duration, String)>, // sorted by time, mode by name
+    theme_switches: Vec<(Duration, &'static Theme)>, // sorted by time
+    tile_map: TileMap,
+    pixel_format: PixelFormat,
+    transition: Transition,
+    scope_trigger: ScopeTrigger,
+    meter_ballistics: Ballistics,
+    button_edges: Vec<(Duration, bool)>, // sorted by time, true is pressed
+    knob_turns: Vec<(Duration, f32)>, // sorted by time
+}
+
+fn exit_with_usage(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    process::exit(1)
+}
+
+fn parse_mode_switch(arg: &str) -> (Duration, String) {
+    let (seconds, mode) = arg.split_once(':').unwrap_or_else(|| exit_with_usage("--mode-at needs SECONDS:MODE"));
+    let seconds: f64 = seconds.parse().unwrap_or_else(|_| exit_with_usage("--mode-at has an invalid timestamp"));
+    // the names from before there were mode names
+    let mode = match mode {
+        "equalizer" => "eq",
+        "waterfall" => "spectrogram",
+        "oscilloscope" => "scope",
+        "vu" => "meter",
+        mode => mode
+    };
+    (Duration::from_secs_f64(seconds.max(0.0)), mode.to_string())
+}
+
+fn parse_theme_switch(arg: &str) -> (Duration, &'static Theme) {
+    let (seconds, name) = arg.split_once(':').unwrap_or_else(|| exit_with_usage("--theme-at needs SECONDS:NAME"));
+    let seconds: f64 = seconds.parse().unwrap_or_else(|_| exit_with_usage("--theme-at has an invalid timestamp"));
+    let theme = Theme::by_name(name).unwrap_or_else(|| exit_with_usage("--theme-at theme should be classic, colorblind, ember or ice"));
+    (Duration::from_secs_f64(seconds.max(0.0)), theme)
+}
+
+// the edges of one press, the contacts bounce for a few ms on the way down and up
+fn parse_button_press(arg: &str) -> [(Duration, bool); 8] {
+    let (seconds, millis) = arg.split_once(':').unwrap_or_else(|| exit_with_usage("--press-at needs SECONDS:MILLIS"));
+    let seconds: f64 = seconds.parse().unwrap_or_else(|_| exit_with_usage("--press-at has an invalid timestamp"));
+    let millis: u64 = millis.parse().unwrap_or_else(|_| exit_with_usage("--press-at has an invalid duration"));
+    let down = Duration::from_secs_f64(seconds.max(0.0));
+    let up = down + Duration::from_millis(millis.max(5));
+    let bounce = Duration::from_micros(1500);
+    [
+        (down, true), (down + bounce, false), (down + bounce * 2, true), (down + bounce * 3, false), (down + bounce * 4, true),
+        (up, false), (up + bounce, true), (up + bounce * 2, false),
+    ]
+}
+
+fn parse_knob_turn(arg: &str) -> (Duration, f32) {
+    let (seconds, position) = arg.split_once(':').unwrap_or_else(|| exit_with_usage("--knob-at needs SECONDS:POSITION"));
+    let seconds: f64 = seconds.parse().unwrap_or_else(|_| exit_with_usage("--knob-at has an invalid timestamp"));
+    let position: f32 = position.parse().unwrap_or_else(|_| exit_with_usage("--knob-at position should be a number from 0.0 to 1.0"));
+    (Duration::from_secs_f64(seconds.max(0.0)), position.clamp(0.0, 1.0))
+}
+
+fn parse_args() -> SimulatorArgs {
+    let mut args = env::args().skip(1);
+    let input = PathBuf::from(args.next().unwrap_or_else(|| exit_with_usage("missing input")));
+    let output = PathBuf::from(args.next().unwrap_or_else(|| exit_with_usage("missing output")));
+
+    let mut speed = 0.0;
+    let mut mode_switches = vec![];
+    let mut theme_switches = vec![];
+    let mut tile_map = TileMap::new(PANELS);
+    let mut pixel_format = PIXEL_FORMAT;
+    let mut transition = MODE_TRANSITION;
+    let mut scope_trigger = SCOPE_TRIGGER;
+    let mut meter_ballistics = METER_BALLISTICS;
+    let mut button_edges = vec![];
+    let mut knob_turns = vec![];
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| exit_with_usage("missing option value"));
+        match arg.as_str() {
+            "--speed" => speed = value.parse().unwrap_or_else(|_| exit_with_usage("--speed should be a number")),
+            "--mode-at" => mode_switches.push(parse_mode_switch(&value)),
+            "--theme-at" => theme_switches.push(parse_theme_switch(&value)),
+            "--matrix" => tile_map = value.parse().unwrap_or_else(|err: String| exit_with_usage(&err)),
+            "--pixel-format" => pixel_format = value.parse().unwrap_or_else(|err: String| exit_with_usage(&err)),
+            "--transition" => transition = value.parse().unwrap_or_else(|err: String| exit_with_usage(&err)),
+            "--scope-trigger" => scope_trigger = value.parse().unwrap_or_else(|err: String| exit_with_usage(&err)),
+            "--meter-ballistics" => meter_ballistics = value.parse().unwrap_or_else(|err: String| exit_with_usage(&err)),
+            "--press-at" => button_edges.extend(parse_button_press(&value)),
+            "--knob-at" => knob_turns.push(parse_knob_turn(&value)),
+            _ => exit_with_usage("unknown option")
+        }
+    }
+    mode_switches.sort_by_key(|(at, _)| *at);
+    theme_switches.sort_by_key(|(at, _)| *at);
+    button_edges.sort_by_key(|(at, _)| *at);
+    knob_turns.sort_by_key(|(at, _)| *at);
+
+    let output = if output.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gif")) {
+        FrameDumpFormat::Gif(output)
+    }
+    else {
+        std::fs::create_dir_all(&output).expect("Failed to create output directory");
+        FrameDumpFormat::PngSequence(output)
+    };
+
+    SimulatorArgs {
+        input,
+        output,
+        speed,
+        mode_switches,
+        theme_switches,
+        tile_map,
+        pixel_format,
+        transition,
+        scope_trigger,
+        meter_ballistics,
+        button_edges,
+        knob_turns,
+    }
+}
+
+fn main() {
+    let args = parse_args();
+
+    let mut audio_source = WavAudioSource::open(&args.input).expect("Failed to open input wav");
+    let sample_rate = audio_source.sample_rate();
+
+    let mut modes = builtin_modes::registry(sample_rate, &args.tile_map, args.pixel_format)
+        .with_mode(ScopeMode::new(sample_rate, &args.tile_map, args.pixel_format).with_trigger(args.scope_trigger))
+        .with_mode(MeterMode::new(sample_rate, &args.tile_map, args.pixel_format).with_ballistics(args.meter_ballistics))
+        .with_order(MODE_ORDER);
+    if let Some((_, unknown)) = args.mode_switches.iter().find(|(_, name)| !modes.names().any(|known| known == name)) {
+        exit_with_usage(&format!("--mode-at mode {} should be one of {}", unknown, modes.names().collect::<Vec<_>>().join(", ")));
+    }
+    let mut mode_switches = args.mode_switches.into_iter().peekable();
+    let mut theme_switches = args.theme_switches.into_iter().peekable();
+    let mut button_edges = args.button_edges.into_iter().peekable();
+    let mut mode_button = GestureRecognizer::new();
+    let mut knob_turns = args.knob_turns.into_iter().peekable();
+    let knob_full_scale = 1000; // readings of the simulated knob
+    let mut knob_reading = None; // not there until the first --knob-at
+    let mut gain_knob = GainKnob::new(knob_full_scale);
+    let mut audio_processor = AudioProcessor::new();
+    let mut visual_processor = VisualProcessor::new(args.tile_map.clone(), args.pixel_format).with_transition(args.transition);
+
+    let mut frame_throttle = FrameThrottle::new(Duration::from_micros(50000)); // same as HwCommander
+    let mut output_stage = OutputStage::new(&args.pixel_format).with_power_budget(LED_POWER_BUDGET);
+    let mut max_estimate_milliamps: f32 = 0.0;
+    let mut frames_limited = 0;
+    let mut frame_dumper = FrameDumper::new(args.output, args.tile_map.clone(), args.pixel_format)
+        .with_frame_delay(frame_throttle.frame_duration())
+        .with_display_gamma(OUTPUT_GAMMA);
+
+    let wall_clock_start = Instant::now();
+    let mut samples_played: u64 = 0;
+
+    loop {
+        // a corrupt file stops the simulator, frames up to the corruption would look like a recording that ended early
+        let audio_values = audio_source.read_frames().unwrap_or_else(|err| {
+            eprintln!("Failed to decode {}: {}", args.input.display(), err);
+            process::exit(1)
+        });
+        if audio_values.is_empty() {
+            break
+        }
+        samples_played += audio_values.len() as u64;
+        let playback_time = Duration::from_secs_f64(samples_played as f64 / sample_rate as f64);
+
+        // wait for the wall clock to catch up to the recording when not running as fast as possible
+        if args.speed > 0.0 {
+            let wall_clock_target = playback_time.div_f64(args.speed);
+            if let Some(wait) = wall_clock_target.checked_sub(wall_clock_start.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
+
+        while let Some((_, theme)) = theme_switches.next_if(|(at, _)| *at <= playback_time) {
+            modes.set_theme(theme);
+        }
+        while let Some((_, next_mode)) = mode_switches.next_if(|(at, _)| *at <= playback_time) {
+            modes.switch_to(&next_mode);
+            visual_processor.play_animation(modes.splash(&args.tile_map));
+        }
+        while let Some((at, pressed)) = button_edges.next_if(|(at, _)| *at <= playback_time) {
+            mode_button.edge(at, pressed);
+        }
+        while let Some(event) = mode_button.poll(playback_time) {
+            if let Some(action) = BUTTON_BINDINGS.action(event) {
+                println!("{:.2}s: {:?} -> {:?}", playback_time.as_secs_f64(), event, action);
+                action.perform(&mut modes, &mut visual_processor, &args.tile_map);
+            }
+        }
+
+        while let Some((_, position)) = knob_turns.next_if(|(at, _)| *at <= playback_time) {
+            knob_reading = Some((position * knob_full_scale as f32).round() as u16);
+        }
+        if let Some(gain_db) = gain_knob.update(playback_time, || knob_reading) {
+            audio_processor.set_gain_db(gain_db);
+            visual_processor.show_gain(gain_db, gain_knob.position(), modes.theme(), playback_time);
+        }
+
+        audio_processor.process(audio_values, modes.current_mut());
+
+        let display_vec_option = visual_processor.process_and_output(modes.current_mut(), playback_time);
+
+        if let Some(display_vec) = display_vec_option {
+            if frame_throttle.ready(playback_time) {
+                let mut output_frame = display_vec.clone();
+                output_stage.process(&mut output_frame);
+                max_estimate_milliamps = max_estimate_milliamps.max(output_stage.last_estimate_milliamps());
+                if output_stage.last_frame_limited() {
+                    frames_limited += 1;
+                }
+
+                frame_dumper.write_frame(&output_frame);
+            }
+        }
+    }
+
+    println!("{} frames written for {:.1} seconds of audio", frame_dumper.frames_written(), samples_played as f64 / sample_rate as f64);
+    println!("leds drew up to {:.0} mA, {} frames were scaled down to the {:.0} mA budget", max_estimate_milliamps, frames_limited, LED_POWER_BUDGET.milliamps);
+}
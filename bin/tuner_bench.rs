@@ -0,0 +1,100 @@
+use std::{env, fs, path::{Path, PathBuf}, process, time::Duration};
+
+use frequatuner::audiovisual::config::{AUDIO_SAMPLE_RATE, GAIN_DEFAULT_DB};
+use frequatuner::audiovisual::audio_source::{AudioSource, WavAudioSource};
+use frequatuner::audiovisual::tuner_bench::{self, BenchSignal};
+
+/*
+Prints the GiTuner accuracy report, see audiovisual::tuner_bench. Build with the host feature.
+
+    tuner_bench [--signal sine|pluck] [--decay D] [--amplitude A] [--seconds S] [--recordings DIR]
+
+Synthetic notes from B0 to E6 are always run, --amplitude is their peak at the ADC (1.0 is full scale), 0.1 by default: a guitar at line
+level. Recordings are WAV files named after the note they contain (E2.wav, A#1.wav, Bb0.wav) and should start at the pluck. Recordings at
+another sample rate than AUDIO_SAMPLE_RATE are skipped. Both go through the gain at GAIN_DEFAULT_DB and the filters before the tuner.
+*/
+
+const USAGE: &str = "usage: tuner_bench [--signal sine|pluck] [--decay D] [--amplitude A] [--seconds S] [--recordings DIR]";
+
+fn exit_with_usage(message: &str) -> ! {
+    eprintln!("{}\n{}", message, USAGE);
+    process::exit(1)
+}
+
+fn parse_number(value: &str) -> f32 {
+    value.parse().unwrap_or_else(|_| exit_with_usage("option value should be a number"))
+}
+
+fn bench_recordings(directory: &Path) -> Vec<tuner_bench::NoteBenchResult> {
+    let mut results = vec![];
+    let mut paths: Vec<PathBuf> = fs::read_dir(directory).expect("Failed to read recordings directory")
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("wav")))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let label = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        let Some(midi) = tuner_bench::label_to_midi(&label) else {
+            eprintln!("skipping {}: file name is not a note", path.display());
+            continue
+        };
+
+        let mut audio_source = WavAudioSource::open(&path).expect("Failed to open recording");
+        if audio_source.sample_rate() != AUDIO_SAMPLE_RATE {
+            eprintln!("skipping {}: recorded at {} Hz instead of {} Hz", path.display(), audio_source.sample_rate(), AUDIO_SAMPLE_RATE);
+            continue
+        }
+
+        let mut samples = vec![];
+        loop {
+            let mut audio_values = audio_source.read_frames().unwrap_or_else(|err| {
+                eprintln!("Failed to decode {}: {}", path.display(), err);
+                process::exit(1)
+            });
+            if audio_values.is_empty() {
+                break
+            }
+            samples.append(&mut audio_values);
+        }
+        results.push(tuner_bench::bench_note(&label, tuner_bench::midi_to_frequency(midi), &samples, AUDIO_SAMPLE_RATE));
+    }
+    results
+}
+
+fn main() {
+    let mut signal_name = String::from("pluck");
+    let mut decay = 0.996;
+    let mut amplitude = 0.1; // line level at the ADC
+    let mut seconds = 2.0;
+    let mut recordings = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().unwrap_or_else(|| exit_with_usage("missing option value"));
+        match arg.as_str() {
+            "--signal" => signal_name = value,
+            "--decay" => decay = parse_number(&value),
+            "--amplitude" => amplitude = parse_number(&value),
+            "--seconds" => seconds = parse_number(&value),
+            "--recordings" => recordings = Some(PathBuf::from(value)),
+            _ => exit_with_usage("unknown option")
+        }
+    }
+
+    let signal = match signal_name.as_str() {
+        "sine" => BenchSignal::Sine,
+        "pluck" => BenchSignal::Pluck { decay },
+        _ => exit_with_usage("--signal should be sine or pluck")
+    };
+
+    println!("synthetic {} notes, amplitude {} at the ADC, gain {} dB, {} s each, {} Hz", signal_name, amplitude, GAIN_DEFAULT_DB, seconds, AUDIO_SAMPLE_RATE);
+    let results = tuner_bench::bench_synthetic_range(&signal, amplitude, Duration::from_secs_f32(seconds), AUDIO_SAMPLE_RATE);
+    println!("{}", tuner_bench::format_report(&results));
+
+    if let Some(directory) = recordings {
+        println!("recordings in {}", directory.display());
+        let results = bench_recordings(&directory);
+        println!("{}", tuner_bench::format_report(&results));
+    }
+}
@@ -0,0 +1,143 @@
+use std::{env, fs, fs::File, path::Path};
+
+/*
+Turns the images in assets/ into sprite tables, so the graphics can be edited in an image editor instead of as number arrays in
+graphics.rs. Every image becomes a `pub static NAME: StaticSprite` in $OUT_DIR/sprites.rs, NAME is the file name without extension in
+upper case (one_up.png -> ONE_UP). audiovisual::sprites includes the generated file.
+
+Supported:
+ - PNG, any colour type and bit depth. Transparency (alpha channel or tRNS) becomes the alpha of the sprite.
+ - PPM (P6, 8 bit), no transparency so every pixel is opaque
+ - PAM (P7) with TUPLTYPE RGB or RGB_ALPHA, for an alpha channel without PNG
+
+Needs png as a build dependency.
+*/
+
+fn main() {
+    let assets_dir = Path::new(&env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR is set by cargo")).join("assets");
+    println!("cargo:rerun-if-changed={}", assets_dir.display());
+
+    let mut entries: Vec<_> = fs::read_dir(&assets_dir)
+        .expect("Failed to read assets directory")
+        .map(|entry| entry.expect("Failed to read assets directory").path())
+        .collect();
+    entries.sort(); // same output on every machine
+
+    let mut generated = String::from("// Generated by build.rs from the images in assets/, edit those instead\n\n");
+    for path in entries {
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default().to_ascii_lowercase();
+        let (width, height, rgba) = match extension.as_str() {
+            "png" => decode_png(&path),
+            "ppm" | "pam" => decode_netpbm(&path),
+            _ => continue
+        };
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        let name: String = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .expect("asset file name should be valid UTF-8")
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+            .collect();
+
+        generated += &format!("// {}\npub static {}: StaticSprite = StaticSprite {{\n    width: {},\n    height: {},\n    pixels: &[\n", path.display(), name, width, height);
+        for row in rgba.chunks_exact(width * 4) {
+            generated += "        ";
+            for pixel in row.chunks_exact(4) {
+                generated += &format!("[{},{},{},{}],", pixel[0], pixel[1], pixel[2], pixel[3]);
+            }
+            generated += "\n";
+        }
+        generated += "    ],\n};\n\n";
+    }
+
+    let out_path = Path::new(&env::var("OUT_DIR").expect("OUT_DIR is set by cargo")).join("sprites.rs");
+    fs::write(out_path, generated).expect("Failed to write sprites.rs");
+}
+
+// width, height and RGBA bytes row by row from the top
+fn decode_png(path: &Path) -> (usize, usize, Vec<u8>) {
+    let mut decoder = png::Decoder::new(File::open(path).expect("Failed to open PNG asset"));
+    decoder.set_transformations(png::Transformations::normalize_to_color8()); // palettes and tRNS expanded, 16 bit to 8
+    let mut reader = decoder.read_info().unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+    let pixels = &buffer[..info.buffer_size()];
+
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect(),
+        png::ColorType::GrayscaleAlpha => pixels.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0], p[1]]).collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&p| [p, p, p, 255]).collect(),
+        png::ColorType::Indexed => panic!("{}: palette should have been expanded", path.display()),
+    };
+    (info.width as usize, info.height as usize, rgba)
+}
+
+fn decode_netpbm(path: &Path) -> (usize, usize, Vec<u8>) {
+    let data = fs::read(path).expect("Failed to read asset");
+    let fail = |message: &str| -> ! { panic!("{}: {}", path.display(), message) };
+
+    // header tokens up to the binary data, # starts a comment until the end of the line
+    let mut position = 0;
+    let mut next_token = |data: &[u8]| -> String {
+        loop {
+            while position < data.len() && data[position].is_ascii_whitespace() {
+                position += 1;
+            }
+            if position < data.len() && data[position] == b'#' {
+                while position < data.len() && data[position] != b'\n' {
+                    position += 1;
+                }
+                continue
+            }
+            break
+        }
+        let start = position;
+        while position < data.len() && !data[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        String::from_utf8_lossy(&data[start..position]).into_owned()
+    };
+    let parse_number = |token: String| -> usize { token.parse().unwrap_or_else(|_| fail("invalid number in header")) };
+
+    let (width, height, maxval, channels) = match next_token(&data).as_str() {
+        "P6" => {
+            let width = parse_number(next_token(&data));
+            let height = parse_number(next_token(&data));
+            let maxval = parse_number(next_token(&data));
+            (width, height, maxval, 3)
+        },
+        "P7" => {
+            let (mut width, mut height, mut maxval, mut channels) = (0, 0, 255, 0);
+            loop {
+                match next_token(&data).as_str() {
+                    "WIDTH" => width = parse_number(next_token(&data)),
+                    "HEIGHT" => height = parse_number(next_token(&data)),
+                    "DEPTH" => channels = parse_number(next_token(&data)),
+                    "MAXVAL" => maxval = parse_number(next_token(&data)),
+                    "TUPLTYPE" => { next_token(&data); },
+                    "ENDHDR" => break,
+                    "" => fail("missing ENDHDR"),
+                    _ => fail("unknown PAM header field")
+                }
+            }
+            if channels != 3 && channels != 4 {
+                fail("PAM assets should be RGB or RGB_ALPHA")
+            }
+            (width, height, maxval, channels)
+        },
+        _ => fail("only binary PPM (P6) and PAM (P7) are supported")
+    };
+    if maxval != 255 {
+        fail("only 8 bit images are supported")
+    }
+
+    let pixels = data.get(position + 1..position + 1 + width * height * channels).unwrap_or_else(|| fail("image data is too short"));
+    let rgba = pixels
+        .chunks_exact(channels)
+        .flat_map(|p| [p[0], p[1], p[2], if channels == 4 { p[3] } else { 255 }])
+        .collect();
+    (width, height, rgba)
+}
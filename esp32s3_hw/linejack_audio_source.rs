@@ -0,0 +1,45 @@
+use esp_idf_hal::i2s::{I2sDriver, I2sRx};
+use esp_idf_sys::EspError;
+
+use crate::audiovisual::audio_source::AudioSource;
+
+/*
+AudioSource on top of the line jack I2S driver. Every read blocks until the DMA buffer has data (or the 1000 tick timeout passes) and decodes the
+32 bit little endian frames into normalized f32 samples.
+*/
+pub struct LineJackAudioSource<'a> {
+    audiobuffer: [u8; 3072], // buffer for the sound driver
+    audio_driver: I2sDriver<'a, I2sRx>,
+    sample_rate: u32,
+}
+
+impl <'a>LineJackAudioSource<'a> {
+    pub fn new(audio_driver: I2sDriver<'a, I2sRx>, sample_rate: u32) -> Self {
+        LineJackAudioSource {
+            audiobuffer: [0u8; 3072],
+            audio_driver,
+            sample_rate,
+        }
+    }
+}
+
+impl <'a>AudioSource for LineJackAudioSource<'a> {
+    type Error = EspError;
+
+    fn read_frames(&mut self) -> Result<Vec<f32>, EspError> {
+        let bytes_read = self.audio_driver.read(&mut self.audiobuffer, 1000)?;
+        let mut audio_values = Vec::with_capacity(self.audiobuffer.len() / 4);
+
+        for chunks in self.audiobuffer.chunks(4).take(bytes_read / 4) {
+            // on Esp32S3 for my two devices the MEMS microphone outputted the middle two bytes and garbage in the 1st and 4th. The linejack hardware outputs all 4 useful bytes. Currently working with linejack
+            let unprocessed_audio_value = i32::from_le_bytes( [chunks[0], chunks[1], chunks[2], chunks[3]]);
+            let audio_value = unprocessed_audio_value as f64 / (i32::MAX) as f64; // normalized, between -1 and 1
+            audio_values.push(audio_value as f32);
+        }
+        Ok(audio_values)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+}
@@ -4,11 +4,15 @@ use esp_idf_hal::{gpio::*, i2s::*, adc::*, modem::Modem, prelude::Peripherals};
 use esp_idf_svc::wifi::EspWifi;
 use ws2812_esp32_rmt_driver::driver::Ws2812Esp32RmtDriver;
 
+use crate::audiovisual::led_sink::LedSink;
+
 mod i2s_rx_mems_mic;
 mod i2s_rx_adc_jack;
 mod esp32s3_wifi;
 mod adc_channel_driver;
+mod linejack_audio_source;
 pub use adc_channel_driver::AdcChannelWrap;
+pub use linejack_audio_source::LineJackAudioSource;
 pub mod config;
 // macro crate used for gpio retrieval
 use seq_macro::seq;
@@ -266,6 +270,13 @@ pub fn get_ws2812ledstrip_driver (esp32: &mut Esp32S3c1, channel_num: u8, gpio_d
     Ws2812Esp32RmtDriver::new(channel_num, gpio_din_number.into()).unwrap()
 }
 
+// the ledmatrix and on board led take the wire bytes as they are
+impl LedSink for Ws2812Esp32RmtDriver {
+    fn write_frame(&mut self, color_vec: &[u8]) {
+        self.write(color_vec).ok();
+    }
+}
+
 pub fn get_on_board_wifi_driver<'a>(esp32: &'a mut Esp32S3c1, ssid: &str, password: &str) -> EspWifi<'a> {
     let modem = &mut esp32.modem;
     esp32s3_wifi::boot_get_driver(modem, &ssid, &password)
@@ -293,6 +304,19 @@ pub fn get_linejack_i2s_driver<'a>(
         i2s_rx_adc_jack::boot_get_driver(esp32, sample_rate, i2s_num, bclk_num, din_num, ws_num)
 }
 
+// linejack i2s driver wrapped as an AudioSource for the audiovisual pipeline
+pub fn get_linejack_audio_source<'a>( 
+    esp32: &mut Esp32S3c1,
+    sample_rate: u32,
+    i2s_num: u8, 
+    bclk_num: u8,
+    din_num: u8,
+    ws_num: u8
+    ) -> LineJackAudioSource<'a> {
+        let audio_driver = get_linejack_i2s_driver(esp32, sample_rate, i2s_num, bclk_num, din_num, ws_num);
+        LineJackAudioSource::new(audio_driver, sample_rate)
+}
+
 // the called function automatically selects the right ADC channel for the selected GPIO pin (for ESP32S3c1)
 pub fn get_adc_channel_driver<'a>(
     esp32: &mut Esp32S3c1,
@@ -0,0 +1,10 @@
+/*
+Library part of the crate so the board binary (main.rs) and the host simulator (bin/simulator.rs) share the same pipeline.
+
+The audiovisual core is hardware independent and builds (and tests) on any host. The ESP32S3 board layer needs the esp-idf toolchain and is
+only built with the esp32s3 feature.
+*/
+
+#[cfg(feature = "esp32s3")]
+pub mod esp32s3_hw; // driver wrappers for confirmed working on-board and connected hardware in my setup
+pub mod audiovisual; // process audio feed and output to led matrix
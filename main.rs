@@ -1,171 +1,180 @@
-use std::sync::atomic::{AtomicBool, Ordering}; // for the interrupt handling
-use std::time::{Duration, SystemTime};
-
-use esp_idf_hal::{delay::FreeRtos, gpio::{PinDriver, AnyIOPin, Input}, i2s::{I2sDriver, I2sRx}, peripherals::*};
-use ws2812_esp32_rmt_driver::driver::Ws2812Esp32RmtDriver;
-
-mod esp32s3_hw; // driver wrappers for confirmed working on-board and connected hardware in my setup
-use esp32s3_hw::{config::*, *};
+/*
+Board binary: the hardware loop for the ESP32S3. Everything in the board module needs the esp32s3 feature, without it main only says so
+and the hardware independent core (lib.rs, audiovisual) can be built and tested on a host.
+*/
 
-mod audiovisual; // process audio feed and output to led matrix
-use audiovisual::graphics;
-use audiovisual::{AudioProcessor, VisualProcessor};
+#[cfg(feature = "esp32s3")]
+mod board {
+    use std::time::Duration;
 
-// Used by the interrupt on the boot button
-static BOOTTON_PRESSED: AtomicBool = AtomicBool::new(false);
+    use esp_idf_hal::{delay::FreeRtos, gpio::{PinDriver, AnyIOPin, Input}, peripherals::*, sys::{self, esp}};
+    use ws2812_esp32_rmt_driver::driver::Ws2812Esp32RmtDriver;
 
-fn boot_button_callback() {
-    BOOTTON_PRESSED.store(true, Ordering::Relaxed);
-}
+    use frequatuner::esp32s3_hw::{self, config::*, *}; // driver wrappers for confirmed working on-board and connected hardware in my setup
 
-// Manages setup of, and direct interactions with, hardware drivers
-struct HwCommander<'a>
-{
-    audiobuffer: [u8; 3072],
-    audio_driver: I2sDriver<'a, I2sRx>,
-    ledmatrix_driver: Ws2812Esp32RmtDriver,
-    mode_button_driver: PinDriver<'a, AnyIOPin, Input>,
-    gain_button_driver: Box<dyn AdcChannelWrap>,
-    frame_duration: Duration, // ledmatrix starts glitching if this is too short 
-    last_visual_update: SystemTime,
-}
+    use frequatuner::audiovisual::config::*; // process audio feed and output to led matrix
+    use frequatuner::audiovisual::{AudioProcessor, VisualProcessor, builtin_modes};
+    use frequatuner::audiovisual::{audio_source::AudioSource, led_sink::LedSink, frame_throttle::FrameThrottle, tile_map::TileMap, output_stage::OutputStage};
+    use frequatuner::audiovisual::marquee::Marquee;
+    use frequatuner::audiovisual::button::{EdgeQueue, GestureRecognizer};
+    use frequatuner::audiovisual::gain_knob::GainKnob;
 
-impl <'a>HwCommander<'a>
-{
-    fn new() -> HwCommander<'a> {
-        let periphs = Peripherals::take().unwrap();
-        let mut esp32 = Esp32S3c1::new(periphs);
+    const MODE_BUTTON_GPIO: u8 = if EXTERNAL_MODE_BUTTON_USE { EXTERNAL_MODE_BUTTON_GPIO_NUM } else { 0 };
 
-        let audiobuffer = [0u8; 3072]; // buffer for the sound driver
-        let audio_driver = esp32s3_hw::get_linejack_i2s_driver(&mut esp32, AUDIO_SAMPLE_RATE, AUDIO_IN_I2S, AUDIO_IN_BCLK, AUDIO_IN_DIN, AUDIO_IN_WS);    
+    // Edges of the mode button, from the interrupt to the main loop
+    static MODE_BUTTON_EDGES: EdgeQueue<32> = EdgeQueue::new();
 
-        let ledmatrix_driver = esp32s3_hw::get_ws2812ledstrip_driver(&mut esp32, LEDS_CHANNEL, LEDS_IN);
-        
-        let mut mode_button_driver = if EXTERNAL_MODE_BUTTON_USE {
-            esp32s3_hw::get_pin_driver_input_button(&mut esp32, EXTERNAL_MODE_BUTTON_GPIO_NUM)
-        }
-        else {
-            esp32s3_hw::get_on_board_boot_button(&mut esp32, None)
-        };
+    // Time since boot from the esp timer, that one can be read in the interrupt as well
+    fn esp_now() -> Duration {
+        Duration::from_micros(unsafe { esp_idf_hal::sys::esp_timer_get_time() } as u64)
+    }
 
-        mode_button_driver.set_interrupt_type(esp_idf_hal::gpio::InterruptType::NegEdge).ok();
-        unsafe {
-            mode_button_driver.subscribe(boot_button_callback).expect("Interrupt subscribe failed");
-        }
-        mode_button_driver.enable_interrupt().ok();
-
-        let gain_button_driver = esp32s3_hw::get_adc_channel_driver(&mut esp32, GAIN_KNOB_GPIO);
-
-        HwCommander {
-            audiobuffer,
-            audio_driver,
-            ledmatrix_driver,
-            mode_button_driver,
-            gain_button_driver,
-            frame_duration: Duration::from_micros(50000), // 20 fps is more than enough. Won't be exact due to execution times
-            last_visual_update: SystemTime::now(),
-        }
+    // Both edges, the level is read right away so the GestureRecognizer sees the bounces in order. Pulled up, so pressed is low.
+    // Added to the esp-idf ISR service directly: PinDriver::subscribe disables the interrupt after every edge until the main loop enables it
+    // again, which would let through one edge per loop and lose the bounces and quick presses in between. This one stays enabled.
+    unsafe extern "C" fn mode_button_isr(_arg: *mut core::ffi::c_void) {
+        let pressed = unsafe { sys::gpio_get_level(MODE_BUTTON_GPIO as i32) } == 0;
+        MODE_BUTTON_EDGES.push(esp_now(), pressed);
     }
 
-    fn re_enable_interrupt(&mut self) {
-        self.mode_button_driver.enable_interrupt().ok();
+    // Manages setup of, and direct interactions with, hardware drivers
+    struct HwCommander<'a>
+    {
+        audio_source: LineJackAudioSource<'a>,
+        ledmatrix_driver: Ws2812Esp32RmtDriver,
+        mode_button_driver: PinDriver<'a, AnyIOPin, Input>,
+        gain_button_driver: Box<dyn AdcChannelWrap>,
+        frame_throttle: FrameThrottle, // ledmatrix starts glitching if frames come in too fast
+        output_stage: OutputStage, // keeps the leds within what the power supply can give, gamma and brightness are in the VisualProcessor
     }
 
-    fn read_audio_buffer(&mut self) -> Vec<f32> {
-        let bytes_read = self.audio_driver.read(&mut self.audiobuffer, 1000).unwrap();
-        let mut audio_values = Vec::with_capacity(self.audiobuffer.len() / 4);
+    impl <'a>HwCommander<'a>
+    {
+        fn new() -> HwCommander<'a> {
+            let periphs = Peripherals::take().unwrap();
+            let mut esp32 = Esp32S3c1::new(periphs);
+
+            let audio_source = esp32s3_hw::get_linejack_audio_source(&mut esp32, AUDIO_SAMPLE_RATE, AUDIO_IN_I2S, AUDIO_IN_BCLK, AUDIO_IN_DIN, AUDIO_IN_WS);
 
-        for chunks in self.audiobuffer.chunks(4).take(bytes_read / 4) { 
-            // on Esp32S3 for my two devices the MEMS microphone outputted the middle two bytes and garbage in the 1st and 4th. The linejack hardware outputs all 4 useful bytes. Currently working with linejack
-            let unprocessed_audio_value = i32::from_le_bytes( [chunks[0], chunks[1], chunks[2], chunks[3]]);
-            let audio_value = unprocessed_audio_value as f64 / (i32::MAX) as f64; // normalized, between 0 and 1
-            audio_values.push(audio_value as f32);
+            let ledmatrix_driver = esp32s3_hw::get_ws2812ledstrip_driver(&mut esp32, LEDS_CHANNEL, LEDS_IN);
+
+            let mut mode_button_driver = if EXTERNAL_MODE_BUTTON_USE {
+                esp32s3_hw::get_pin_driver_input_button(&mut esp32, MODE_BUTTON_GPIO)
+            }
+            else {
+                esp32s3_hw::get_on_board_boot_button(&mut esp32, Some(MODE_BUTTON_GPIO))
+            };
+
+            mode_button_driver.set_interrupt_type(esp_idf_hal::gpio::InterruptType::AnyEdge).ok();
+            unsafe {
+                // another driver may have installed the service already, that's fine
+                let installed = sys::gpio_install_isr_service(0);
+                if installed != sys::ESP_ERR_INVALID_STATE as sys::esp_err_t {
+                    esp!(installed).expect("GPIO ISR service install failed");
+                }
+                esp!(sys::gpio_isr_handler_add(MODE_BUTTON_GPIO as i32, Some(mode_button_isr), core::ptr::null_mut())).expect("Interrupt handler add failed");
+                esp!(sys::gpio_intr_enable(MODE_BUTTON_GPIO as i32)).expect("Interrupt enable failed");
+            }
+
+            let gain_button_driver = esp32s3_hw::get_adc_channel_driver(&mut esp32, GAIN_KNOB_GPIO);
+
+            HwCommander {
+                audio_source,
+                ledmatrix_driver,
+                mode_button_driver,
+                gain_button_driver,
+                frame_throttle: FrameThrottle::new(Duration::from_micros(50000)), // 20 fps is more than enough. Won't be exact due to execution times
+                output_stage: OutputStage::new(&PIXEL_FORMAT).with_power_budget(LED_POWER_BUDGET),
+            }
         }
-        audio_values
-    }
 
-    fn display_ledmatrix(&mut self, color_vec: Vec<u8>) {
-        let now = SystemTime::now();
-        let elapsed = now.duration_since(self.last_visual_update).unwrap_or(Duration::ZERO);
+        // the mode button as it is now, for when EdgeQueue was full and dropped edges
+        fn mode_button_down(&self) -> bool {
+            self.mode_button_driver.is_low()
+        }
 
-        if elapsed >= self.frame_duration {
-            self.ledmatrix_driver.write(&color_vec).ok();
-            self.last_visual_update = now;
+        // None when the ADC read failed, the knob skips that reading
+        fn read_gain_knob(&mut self) -> Option<u16> {
+            self.gain_button_driver.read().ok()
         }
-    }
-}
 
-/*
-Keeps track of if we're in equalizer or tuner mode, switches mode
-*/
-struct FrequalizerMode {
-    mode: EqTunerModeEnum
-}
-impl FrequalizerMode {
-    fn new() -> FrequalizerMode {
-        FrequalizerMode {
-            mode: EqTunerModeEnum::Equalizer
+        // time since boot, the clock of the frame throttle and the button edges. Animations and transitions run on it too.
+        fn now(&self) -> Duration {
+            esp_now()
         }
-    }
-    fn check_switch_mode(&mut self) -> bool {
-        if BOOTTON_PRESSED.load(Ordering::Relaxed) {
-            BOOTTON_PRESSED.store(false, Ordering::Relaxed);
-            self.switch_mode(None);
-            true
+
+        fn display_ledmatrix(&mut self, color_vec: &[u8]) {
+            if self.frame_throttle.ready(self.now()) {
+                let mut frame = color_vec.to_vec();
+                self.output_stage.process(&mut frame);
+                self.ledmatrix_driver.write_frame(&frame);
+            }
         }
-        else {
-            false
+    }
+
+    // Frames pushed through the HwCommander get throttled to frame_duration
+    impl <'a>LedSink for HwCommander<'a> {
+        fn write_frame(&mut self, color_vec: &[u8]) {
+            self.display_ledmatrix(color_vec);
         }
     }
 
-    fn switch_mode(&mut self, desired_mode: Option<EqTunerModeEnum>) {
-        match desired_mode {
-            Some(EqTunerModeEnum::Equalizer) => self.mode = EqTunerModeEnum::Equalizer,
-            Some(EqTunerModeEnum::Tuner) => self.mode = EqTunerModeEnum::Tuner,
-            None => {
-                match self.mode {
-                    EqTunerModeEnum::Equalizer => self.mode = EqTunerModeEnum::Tuner,
-                    EqTunerModeEnum::Tuner => self.mode = EqTunerModeEnum::Equalizer
+    pub fn run() {
+        esp_idf_hal::sys::link_patches();
+
+        let tile_map = TileMap::new(PANELS);
+        let mut hw_commander = HwCommander::new();
+        let mut modes = builtin_modes::registry(hw_commander.audio_source.sample_rate(), &tile_map, PIXEL_FORMAT); // in MODE_ORDER
+        let mut gain_knob = GainKnob::new(GAIN_KNOB_FULL_SCALE_MV);
+        gain_knob.update(hw_commander.now(), || hw_commander.read_gain_knob()); // the first reading is the gain at boot, no overlay for that one
+        let mut audio_processor = AudioProcessor::new().with_gain_db(gain_knob.gain_db());
+        let mut visual_processor = VisualProcessor::new(tile_map.clone(), PIXEL_FORMAT);
+        let mut mode_button = GestureRecognizer::new();
+        visual_processor.play_animation(Marquee::new("FREQUATUNER")); // boot splash, scrolls by while the first mode gets going
+
+        /*
+        Main loop: read the audiobuffer and run the audio processor on it. 
+        The visual processor reads audioprocessor output, processes, and outputs a color array (size is ledmatrix_x*ledmatrix_y*3 for g,r,b on every led) 
+        */
+        loop { 
+            FreeRtos::delay_ms(5); // give OS a chance to do some threading and prevent watchdog triggers
+
+            // Button edges to gestures, the gestures do what BUTTON_BINDINGS says. The interrupt gets every edge, the level only matters
+            // when a long stall of the loop filled the queue and the last edges were dropped. A mode switch starts the splash, the visual
+            // processor draws it while the loop goes on, so the audio keeps being read and the new mode warms up.
+            let now = hw_commander.now();
+            while let Some((at, pressed)) = MODE_BUTTON_EDGES.pop(now) {
+                mode_button.edge(at, pressed);
+            }
+            mode_button.level(now, hw_commander.mode_button_down());
+            while let Some(event) = mode_button.poll(now) {
+                if let Some(action) = BUTTON_BINDINGS.action(event) {
+                    action.perform(&mut modes, &mut visual_processor, &tile_map);
                 }
             }
-        }
-    }
-}
 
-pub enum EqTunerModeEnum {
-    Equalizer,
-    Tuner
-}
+            // The knob is read every GAIN_KNOB_INTERVAL, turning it shows the gain over the frame for a moment
+            if let Some(gain_db) = gain_knob.update(now, || hw_commander.read_gain_knob()) {
+                audio_processor.set_gain_db(gain_db);
+                visual_processor.show_gain(gain_db, gain_knob.position(), modes.theme(), now);
+            }
 
-fn main() {
-    esp_idf_hal::sys::link_patches();
-
-    let mut hw_commander = HwCommander::new();
-    let mut fr_mode = FrequalizerMode::new();
-    let mut audio_processor = AudioProcessor::new(AUDIO_SAMPLE_RATE);
-    let mut visual_processor = VisualProcessor::new();
-
-    /*
-    Main loop: read the audiobuffer and run the audio processor on it. 
-    The visual processor reads audioprocessor output, processes, and outputs a color array (size is ledmatrix_x*ledmatrix_y*3 for g,r,b on every led) 
-    */
-    loop { 
-        FreeRtos::delay_ms(5); // give OS a chance to do some threading and prevent watchdog triggers
-        
-        // Switch from equalizer to tuner and back on button presses. If the button was pressed an animation plays and the interrupt needs to be re-enabled
-        if fr_mode.check_switch_mode() {
-            graphics::display_switch_animation(&fr_mode.mode, &mut hw_commander);
-            hw_commander.re_enable_interrupt();
-        }
+            let audio_values = hw_commander.audio_source.read_frames().expect("Failed to read the line jack"); // the driver broke, a reboot it is
+            audio_processor.process(audio_values, modes.current_mut());
 
-        let audio_values = hw_commander.read_audio_buffer();
-        audio_processor.process(audio_values, &fr_mode.mode);
+            let display_vec_option = visual_processor.process_and_output(modes.current_mut(), hw_commander.now());
 
-        let input_for_visual_processor = audio_processor.output(&fr_mode.mode);
-        let display_vec_option = visual_processor.process_and_output(input_for_visual_processor);
-        
-        if let Some(display_vec) = display_vec_option {
-            hw_commander.display_ledmatrix(display_vec);
+            if let Some(display_vec) = display_vec_option {
+                hw_commander.display_ledmatrix(&display_vec);
+            }
         }
     }
-}
\ No newline at end of file
+}
+
+fn main() {
+    #[cfg(feature = "esp32s3")]
+    board::run();
+
+    #[cfg(not(feature = "esp32s3"))]
+    eprintln!("The board binary needs the esp32s3 feature. On a host use the simulator binary (host feature) instead.");
+}